@@ -0,0 +1,121 @@
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+
+use crate::color::Color;
+
+/// Adaptive sampling settings; `Camera::samples_per_pixel` is the upper bound.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    /// samples every pixel takes before convergence is tested
+    pub min_samples: usize,
+    /// target relative standard error of the pixel luminance
+    pub threshold: f64,
+    /// where to write the per-pixel sample-count heatmap, if anywhere
+    pub heatmap_path: Option<String>,
+}
+
+impl AdaptiveSampling {
+    pub fn new(min_samples: usize, threshold: f64) -> Self {
+        Self { min_samples: min_samples.max(2), threshold, heatmap_path: None }
+    }
+
+    pub fn converged(&self, stats: &RunningStats) -> bool {
+        stats.count >= self.min_samples && stats.relative_error() < self.threshold
+    }
+}
+
+/// Welford running mean of the color and variance of its luminance, plus the mean
+/// alpha (coverage) of the samples
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RunningStats {
+    pub count: usize,
+    /// premultiplied by alpha
+    pub mean: Color,
+    pub mean_alpha: f64,
+    pub mean_luminance: f64,
+    /// sum of squared luminance deviations (Welford's M2)
+    pub m2: f64,
+}
+
+impl Default for RunningStats {
+    fn default() -> Self {
+        Self { count: 0, mean: Color::new(0.0, 0.0, 0.0), mean_alpha: 0.0, mean_luminance: 0.0, m2: 0.0 }
+    }
+}
+
+impl RunningStats {
+    pub fn add(&mut self, sample: Color, alpha: f64) {
+        self.count += 1;
+        let n = self.count as f64;
+        self.mean += (sample - self.mean) / n;
+        self.mean_alpha += (alpha - self.mean_alpha) / n;
+
+        let y = sample.luminance();
+        let delta = y - self.mean_luminance;
+        self.mean_luminance += delta / n;
+        self.m2 += delta * (y - self.mean_luminance);
+    }
+
+    /// unbiased sample variance of the luminance
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 { 0.0 } else { self.m2 / (self.count - 1) as f64 }
+    }
+
+    /// standard error of the mean relative to the mean (floored for near-black pixels)
+    pub fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        (self.variance() / self.count as f64).sqrt() / self.mean_luminance.max(1e-2)
+    }
+}
+
+/// false-color ramp (blue -> green -> red) of samples taken per pixel, as binary PPM
+pub fn write_sample_heatmap(path: &str, width: usize, height: usize, counts: &[usize], max_samples: usize) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    for &n in counts.iter().take(width * height) {
+        let c = Color::heatmap(n as f64 / max_samples.max(1) as f64);
+        out.write_all(&[(c.r() * 255.0) as u8, (c.g() * 255.0) as u8, (c.b() * 255.0) as u8])?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    /// samples a pixel takes under `adaptive`, up to `max`, drawing them from `sample`
+    fn samples_taken(adaptive: &AdaptiveSampling, max: usize, mut sample: impl FnMut() -> f64) -> usize {
+        let mut stats = RunningStats::default();
+        while stats.count < max && !adaptive.converged(&stats) {
+            let y = sample();
+            stats.add(Color::new(y, y, y), 1.0);
+        }
+        stats.count
+    }
+
+    #[test]
+    fn flat_pixels_stop_at_min_samples_and_noisy_ones_run_to_the_cap() {
+        let adaptive = AdaptiveSampling::new(8, 0.01);
+        let mut rng = Rng::new(5, 0);
+        assert_eq!(samples_taken(&adaptive, 256, || 0.5), 8);
+        assert_eq!(samples_taken(&adaptive, 256, || 0.5 + 0.001 * rng.random_double()), 8);
+        assert_eq!(samples_taken(&adaptive, 256, || if rng.random_double() < 0.1 { 5.0 } else { 0.0 }), 256);
+        // below two samples there is no variance to test
+        assert_eq!(AdaptiveSampling::new(0, 0.01).min_samples, 2);
+        assert_eq!(samples_taken(&AdaptiveSampling::new(0, 0.01), 256, || 0.5), 2);
+    }
+
+    #[test]
+    fn heatmap_scales_counts_to_the_sample_cap() {
+        let path = std::env::temp_dir().join(format!("raytrace_rs_heatmap_{}.ppm", std::process::id())).to_string_lossy().into_owned();
+        write_sample_heatmap(&path, 3, 1, &[0, 32, 64], 64).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let header = b"P6\n3 1\n255\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(&bytes[header.len()..], [0, 0, 255, 0, 255, 0, 255, 0, 0]);
+    }
+}
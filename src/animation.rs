@@ -0,0 +1,307 @@
+//! Keyframed animation of the camera, object transforms and material parameters.
+//!
+//! Tracks are keyed on frame numbers and may be sampled at any (fractional) frame;
+//! before the first key and after the last they hold the end values. An `Animation`
+//! never touches the loaded scene: `world_at` wraps the animated objects of the
+//! shared `HittableList` in `Instance`s for each frame.
+
+use std::ops::{Add, Mul, Sub};
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hittable_list::HittableList;
+use crate::instance::{Instance, Transform};
+use crate::material::{Dielectric, Lambertian, MaterialPtr, Metal};
+use crate::rtweekend::Shared;
+use crate::vec3::{Vec3, Point3};
+
+/// values a track can interpolate
+pub trait Animatable: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f64, Output = Self> {}
+
+impl<T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>> Animatable for T {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// cubic Bezier per segment through the keys' handles
+    Bezier,
+    /// smooth curve through every key, tangents from the neighboring keys
+    CatmullRom,
+}
+
+impl Interpolation {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Interpolation::Linear),
+            "bezier" => Some(Interpolation::Bezier),
+            "catmull-rom" | "catmullrom" => Some(Interpolation::CatmullRom),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Keyframe<T> {
+    pub frame: f64,
+    pub value: T,
+    /// Bezier handles relative to `value`, toward the previous and the next key; None
+    /// uses the Catmull-Rom tangent, which makes an unhandled Bezier track smooth
+    pub in_handle: Option<T>,
+    pub out_handle: Option<T>,
+}
+
+impl<T> Keyframe<T> {
+    pub fn new(frame: f64, value: T) -> Self {
+        Self { frame, value, in_handle: None, out_handle: None }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Track<T> {
+    pub interpolation: Interpolation,
+    /// sorted by frame
+    keys: Vec<Keyframe<T>>,
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new(interpolation: Interpolation, mut keys: Vec<Keyframe<T>>) -> Self {
+        keys.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+        Self { interpolation, keys }
+    }
+
+    /// track through `(frame, value)` pairs
+    pub fn from_values(interpolation: Interpolation, values: &[(f64, T)]) -> Self {
+        Self::new(interpolation, values.iter().map(|&(frame, value)| Keyframe::new(frame, value)).collect())
+    }
+
+    pub fn keys(&self) -> &[Keyframe<T>] { &self.keys }
+
+    /// None for a track without keys
+    pub fn sample(&self, frame: f64) -> Option<T> {
+        let keys = &self.keys;
+        let first = keys.first()?;
+        let last = keys.last()?;
+        if frame <= first.frame {
+            return Some(first.value);
+        }
+        if frame >= last.frame {
+            return Some(last.value);
+        }
+        let k = keys.partition_point(|key| key.frame <= frame) - 1;
+        let (a, b) = (&keys[k], &keys[k + 1]);
+        let span = b.frame - a.frame;
+        let s = (frame - a.frame) / span;
+        Some(match self.interpolation {
+            Interpolation::Linear => a.value + (b.value - a.value) * s,
+            Interpolation::CatmullRom => {
+                let (m0, m1) = (self.tangent(k) * span, self.tangent(k + 1) * span);
+                let (s2, s3) = (s * s, s * s * s);
+                a.value * (2.0 * s3 - 3.0 * s2 + 1.0) + m0 * (s3 - 2.0 * s2 + s) + b.value * (3.0 * s2 - 2.0 * s3) + m1 * (s3 - s2)
+            }
+            Interpolation::Bezier => {
+                let p1 = a.value + a.out_handle.unwrap_or(self.tangent(k) * (span / 3.0));
+                let p2 = b.value + b.in_handle.unwrap_or(self.tangent(k + 1) * (-span / 3.0));
+                let t = 1.0 - s;
+                a.value * (t * t * t) + p1 * (3.0 * t * t * s) + p2 * (3.0 * t * s * s) + b.value * (s * s * s)
+            }
+        })
+    }
+
+    /// Catmull-Rom slope (per frame) at key `k`; one-sided at the ends
+    fn tangent(&self, k: usize) -> T {
+        let keys = &self.keys;
+        let (a, b) = (&keys[k.saturating_sub(1)], &keys[(k + 1).min(keys.len() - 1)]);
+        if b.frame > a.frame { (b.value - a.value) * (1.0 / (b.frame - a.frame)) } else { a.value * 0.0 }
+    }
+}
+
+/// Animated camera parameters; unset tracks leave the camera's value alone. A sequence
+/// refuses tracks for what the camera sets itself: the pose and vfov of a calibrated
+/// camera, the vfov of a physical camera or lens, the focus distance under autofocus.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CameraTracks {
+    pub lookfrom: Option<Track<Point3>>,
+    pub lookat: Option<Track<Point3>>,
+    pub vfov: Option<Track<f64>>,
+    pub focus_dist: Option<Track<f64>>,
+}
+
+impl CameraTracks {
+    pub fn apply(&self, cam: &mut Camera, frame: f64) {
+        let sample = |track: &Option<Track<f64>>, value: &mut f64| {
+            if let Some(v) = track.as_ref().and_then(|t| t.sample(frame)) {
+                *value = v;
+            }
+        };
+        let (lookfrom, lookat) = self.pose(cam, frame);
+        cam.lookfrom = lookfrom;
+        cam.lookat = lookat;
+        sample(&self.vfov, &mut cam.vfov);
+        sample(&self.focus_dist, &mut cam.focus_dist);
+    }
+
+    /// (lookfrom, lookat) at `frame`, falling back to the camera's own
+    pub fn pose(&self, cam: &Camera, frame: f64) -> (Point3, Point3) {
+        let at = |track: &Option<Track<Point3>>, value: Point3| track.as_ref().and_then(|t| t.sample(frame)).unwrap_or(value);
+        (at(&self.lookfrom, cam.lookfrom), at(&self.lookat, cam.lookat))
+    }
+}
+
+/// Animated parameters of one of the built-in materials; replaces the object's own.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MaterialTracks {
+    Lambertian { albedo: Track<Color> },
+    Metal { albedo: Track<Color>, fuzz: Track<f64> },
+    Dielectric { refraction_index: Track<f64> },
+}
+
+impl MaterialTracks {
+    pub fn material_at(&self, frame: f64) -> MaterialPtr {
+        let gray = Color::new(0.5, 0.5, 0.5);
+        match self {
+            MaterialTracks::Lambertian { albedo } => Shared::new(Lambertian::new(albedo.sample(frame).unwrap_or(gray))),
+            MaterialTracks::Metal { albedo, fuzz } => {
+                Shared::new(Metal::new(albedo.sample(frame).unwrap_or(gray), fuzz.sample(frame).unwrap_or(0.0)))
+            }
+            MaterialTracks::Dielectric { refraction_index } => Shared::new(Dielectric::new(refraction_index.sample(frame).unwrap_or(1.5))),
+        }
+    }
+}
+
+/// Transform and material tracks for the object at `index` in the scene's list. The
+/// transform applies on top of the object's own placement; rotation and scale pivot
+/// on `pivot`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObjectTracks {
+    pub index: usize,
+    pub pivot: Point3,
+    pub translation: Option<Track<Vec3>>,
+    /// degrees about x, y and z
+    pub rotation: Option<Track<Vec3>>,
+    pub scale: Option<Track<f64>>,
+    pub material: Option<MaterialTracks>,
+}
+
+impl ObjectTracks {
+    pub fn transform_at(&self, frame: f64) -> Transform {
+        let base = Transform::default();
+        Transform {
+            translation: self.translation.as_ref().and_then(|t| t.sample(frame)).unwrap_or(base.translation),
+            rotation: self.rotation.as_ref().and_then(|t| t.sample(frame)).unwrap_or(base.rotation),
+            scale: self.scale.as_ref().and_then(|t| t.sample(frame)).unwrap_or(base.scale),
+            pivot: self.pivot,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Animation {
+    pub camera: CameraTracks,
+    pub objects: Vec<ObjectTracks>,
+}
+
+impl Animation {
+    /// The scene at `frame`: the same objects, animated ones wrapped in an `Instance`.
+    /// An animated material keeps the id and name of the material it replaces.
+    pub fn world_at(&self, world: &HittableList, frame: f64) -> HittableList {
+        let mut objects = world.objects.clone();
+        let mut materials = world.materials.clone();
+        for tracks in &self.objects {
+            if let Some(object) = objects.get_mut(tracks.index) {
+                let material = tracks.material.as_ref().map(|m| m.material_at(frame));
+                let id = object.material().and_then(|m| world.registered_material_id(&m));
+                if let (Some(material), Some(id)) = (&material, id) {
+                    materials.push((material.clone(), id));
+                }
+                *object = Shared::new(Instance::new(object.clone(), tracks.transform_at(frame), material));
+            }
+        }
+        HittableList {
+            objects,
+            lights: world.lights.clone(),
+            names: world.names.clone(),
+            material_names: world.material_names.clone(),
+            materials,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aov::material_id;
+    use crate::hittable::Hittable;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn animated_materials_keep_their_name() {
+        let gold: MaterialPtr = Shared::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.0));
+        let mut world = HittableList::new();
+        world.push(Sphere::new(Point3::new(0.0, 0.0, -2.0), 0.5, gold.clone()));
+        world.name_material(&gold, "gold");
+        let fuzz = Track::from_values(Interpolation::Linear, &[(0.0, 0.0), (10.0, 0.5)]);
+        let tracks = ObjectTracks {
+            material: Some(MaterialTracks::Metal { albedo: Track::from_values(Interpolation::Linear, &[]), fuzz }),
+            ..ObjectTracks::default()
+        };
+        let animation = Animation { objects: vec![tracks], ..Animation::default() };
+
+        let ray = Ray::new(Point3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        for frame in [0.0, 5.0, 10.0] {
+            let rec = animation.world_at(&world, frame).hit(&ray, 0.001, f64::INFINITY).unwrap();
+            assert_eq!(world.material_name(material_id(&rec)), "gold", "frame {}", frame);
+        }
+    }
+
+    #[test]
+    fn object_tracks_move_only_their_object_about_the_pivot() {
+        let mat: MaterialPtr = Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.push(Sphere::new(Point3::new(0.0, 10.0, 0.0), 0.5, mat.clone()));
+        world.push(Sphere::new(Point3::new(2.0, 0.0, 0.0), 0.5, mat));
+        let tracks = ObjectTracks {
+            index: 1,
+            rotation: Some(Track::from_values(Interpolation::Linear, &[(0.0, Vec3::zero()), (10.0, Vec3::new(0.0, 90.0, 0.0))])),
+            scale: Some(Track::from_values(Interpolation::Linear, &[(0.0, 1.0), (10.0, 2.0)])),
+            ..ObjectTracks::default()
+        };
+        let animation = Animation { objects: vec![tracks], ..Animation::default() };
+
+        let hit_t = |frame: f64, direction: Vec3| {
+            animation.world_at(&world, frame).hit(&Ray::new(Point3::zero(), direction), 0.001, f64::INFINITY).map(|rec| rec.t)
+        };
+        let (x, minus_z, up) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!((hit_t(-3.0, x).unwrap() - 1.5).abs() < 1e-9);
+        assert!(hit_t(-3.0, minus_z).is_none());
+        // a quarter turn about the pivot at the origin, twice the size
+        assert!(hit_t(10.0, x).is_none());
+        assert!((hit_t(10.0, minus_z).unwrap() - 3.0).abs() < 1e-9);
+        assert!((hit_t(10.0, up).unwrap() - 9.5).abs() < 1e-9, "the other object stays put");
+    }
+
+    #[test]
+    fn interpolations_pass_through_their_keys() {
+        let keys = [(0.0, 0.0), (10.0, 10.0), (20.0, 0.0), (40.0, 10.0)];
+        for interpolation in [Interpolation::Linear, Interpolation::Bezier, Interpolation::CatmullRom] {
+            let track = Track::from_values(interpolation, &keys);
+            for (frame, value) in keys {
+                assert!((track.sample(frame).unwrap() - value).abs() < 1e-12, "{:?} at {}", interpolation, frame);
+            }
+            assert_eq!(track.sample(-5.0), Some(0.0));
+            assert_eq!(track.sample(50.0), Some(10.0));
+        }
+        assert_eq!(Track::from_values(Interpolation::Linear, &keys).sample(5.0), Some(5.0));
+
+        // handle-less Bezier follows the Catmull-Rom curve; an explicit handle bends it
+        let smooth = Track::from_values(Interpolation::CatmullRom, &keys);
+        let auto = Track::from_values(Interpolation::Bezier, &keys);
+        assert!((smooth.sample(13.0).unwrap() - auto.sample(13.0).unwrap()).abs() < 1e-12);
+        let mut handled = keys.map(|(frame, value)| Keyframe::new(frame, value));
+        handled[1].out_handle = Some(5.0);
+        let handled = Track::new(Interpolation::Bezier, handled.to_vec());
+        assert!(handled.sample(12.0).unwrap() > 10.0, "overshoots toward the handle");
+        assert!(Track::<f64>::new(Interpolation::Linear, Vec::new()).sample(0.0).is_none());
+    }
+}
@@ -0,0 +1,218 @@
+use std::io;
+
+use crate::color::Color;
+use crate::vec3::{Vec3, Point3};
+use crate::ray::Ray;
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::fingerprint::Fingerprint;
+use crate::film::{AlphaMode, Film};
+use crate::exr::ExrImage;
+use crate::lpe::LightPass;
+
+/// what the camera ray of one sample saw first
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FirstHit {
+    /// distance from the ray origin
+    pub depth: f64,
+    pub position: Point3,
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub object_id: u32,
+    pub material_id: u32,
+    pub uv: (f64, f64),
+    /// raster-space motion in pixels; filled in by the camera
+    pub motion: (f64, f64),
+}
+
+impl FirstHit {
+    pub fn from_record(r: &Ray, rec: &HitRecord) -> Self {
+        Self {
+            depth: rec.t * r.direction.length(),
+            position: rec.p,
+            normal: rec.normal,
+            albedo: rec.mat.albedo(),
+            object_id: rec.object_id,
+            material_id: material_id(rec),
+            uv: (rec.u, rec.v),
+            motion: (0.0, 0.0),
+        }
+    }
+}
+
+/// Material id of a hit: the id its world named the material under, or for unnamed
+/// materials one derived from the parameters (bit 22 set, so the two never meet).
+/// Either fits in 23 bits so it survives a round trip through an f32 channel.
+pub fn material_id(rec: &HitRecord) -> u32 {
+    match rec.material_id {
+        0 => id_of_material(&*rec.mat),
+        id => id,
+    }
+}
+
+/// the id `material_id` gives hits on an unnamed `material`
+pub fn id_of_material(material: &dyn Material) -> u32 {
+    let mut fp = Fingerprint::new();
+    material.fingerprint(&mut fp);
+    0x40_0000 | (fp.finish() & 0x3f_ffff) as u32
+}
+
+/// Per-pixel AOV accumulator. Continuous quantities are summed over the samples
+/// whose camera ray hit something; ids come from the first such sample.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AovPixel {
+    pub samples: u32,
+    pub hits: u32,
+    pub depth: f64,
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub uv: (f64, f64),
+    pub motion: (f64, f64),
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+impl Default for AovPixel {
+    fn default() -> Self {
+        Self {
+            samples: 0,
+            hits: 0,
+            depth: 0.0,
+            position: Vec3::zero(),
+            normal: Vec3::zero(),
+            albedo: Color::new(0.0, 0.0, 0.0),
+            uv: (0.0, 0.0),
+            motion: (0.0, 0.0),
+            object_id: 0,
+            material_id: 0,
+        }
+    }
+}
+
+impl AovPixel {
+    pub fn add(&mut self, hit: Option<&FirstHit>) {
+        self.samples += 1;
+        let Some(h) = hit else { return };
+        if self.hits == 0 {
+            self.object_id = h.object_id;
+            self.material_id = h.material_id;
+        }
+        self.hits += 1;
+        self.depth += h.depth;
+        self.position += h.position;
+        self.normal += h.normal;
+        self.albedo += h.albedo;
+        self.uv = (self.uv.0 + h.uv.0, self.uv.1 + h.uv.1);
+        self.motion = (self.motion.0 + h.motion.0, self.motion.1 + h.motion.1);
+    }
+
+    /// fraction of samples whose camera ray hit geometry
+    pub fn alpha(&self) -> f64 {
+        if self.samples == 0 { 0.0 } else { self.hits as f64 / self.samples as f64 }
+    }
+
+    fn hit_scale(&self) -> f64 {
+        if self.hits == 0 { 0.0 } else { 1.0 / self.hits as f64 }
+    }
+
+    /// mean over the samples that hit; zero for a pixel that only saw background
+    pub fn mean_depth(&self) -> f64 { self.depth * self.hit_scale() }
+    pub fn mean_normal(&self) -> Vec3 { self.normal * self.hit_scale() }
+    pub fn mean_albedo(&self) -> Color { self.albedo * self.hit_scale() }
+}
+
+/// how `Camera` writes the AOVs next to image.ppm
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AovOutput {
+    /// one EXR per AOV: image.depth.exr, image.N.exr, ...
+    SeparateFiles,
+    /// beauty plus every AOV as named layers of image.exr
+    MultiLayerExr,
+}
+
+impl AovOutput {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "separate" => Some(AovOutput::SeparateFiles),
+            "exr" => Some(AovOutput::MultiLayerExr),
+            _ => None,
+        }
+    }
+}
+
+/// layer name and its (channel suffix, per-pixel values)
+type Layer = (String, Vec<(&'static str, Vec<f32>)>);
+
+fn aov_layers(film: &Film) -> Vec<Layer> {
+    let column = |f: &dyn Fn(&AovPixel) -> f64| film.aovs.iter().map(|p| f(p) as f32).collect::<Vec<f32>>();
+    let layers: [(&str, _); 9] = [
+        ("depth", vec![("Z", column(&|p| p.depth * p.hit_scale()))]),
+        ("P", vec![
+            ("X", column(&|p| p.position.x * p.hit_scale())),
+            ("Y", column(&|p| p.position.y * p.hit_scale())),
+            ("Z", column(&|p| p.position.z * p.hit_scale())),
+        ]),
+        ("N", vec![
+            ("X", column(&|p| p.normal.x * p.hit_scale())),
+            ("Y", column(&|p| p.normal.y * p.hit_scale())),
+            ("Z", column(&|p| p.normal.z * p.hit_scale())),
+        ]),
+        ("albedo", vec![
+            ("R", column(&|p| p.albedo.r() * p.hit_scale())),
+            ("G", column(&|p| p.albedo.g() * p.hit_scale())),
+            ("B", column(&|p| p.albedo.b() * p.hit_scale())),
+        ]),
+        ("objectId", vec![("id", column(&|p| p.object_id as f64))]),
+        ("materialId", vec![("id", column(&|p| p.material_id as f64))]),
+        ("uv", vec![("U", column(&|p| p.uv.0 * p.hit_scale())), ("V", column(&|p| p.uv.1 * p.hit_scale()))]),
+        ("motion", vec![("X", column(&|p| p.motion.0 * p.hit_scale())), ("Y", column(&|p| p.motion.1 * p.hit_scale()))]),
+        ("alpha", vec![("A", column(&|p| p.alpha()))]),
+    ];
+    layers.into_iter().map(|(name, channels)| (name.to_string(), channels)).collect()
+}
+
+fn pass_layers(film: &Film, passes: &[LightPass]) -> Vec<Layer> {
+    passes.iter().enumerate().map(|(k, pass)| {
+        let colors = film.pass_colors(k);
+        let column = |f: fn(&Color) -> f64| colors.iter().map(|c| f(c) as f32).collect::<Vec<f32>>();
+        (pass.name.clone(), vec![("R", column(Color::r)), ("G", column(Color::g)), ("B", column(Color::b))])
+    }).collect()
+}
+
+/// Write the film's AOVs and its light `passes` as `<stem>.<layer>.exr` files or as
+/// layers of `<stem>.exr`; the layered file also carries the beauty with its alpha
+/// stored as `alpha` says.
+pub fn write_aovs(film: &Film, stem: &str, output: AovOutput, alpha: AlphaMode, passes: &[LightPass]) -> io::Result<Vec<String>> {
+    let mut layers = if film.aovs.is_empty() { Vec::new() } else { aov_layers(film) };
+    layers.extend(pass_layers(film, passes));
+    if layers.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut written = Vec::new();
+    match output {
+        AovOutput::SeparateFiles => {
+            for (layer, channels) in layers {
+                let mut image = ExrImage::new(film.width, film.height);
+                for (suffix, values) in channels {
+                    image.add_channel(suffix, values);
+                }
+                let path = format!("{}.{}.exr", stem, layer);
+                image.write(&path)?;
+                written.push(path);
+            }
+        }
+        AovOutput::MultiLayerExr => {
+            let mut image = film.beauty_exr(alpha);
+            for (layer, channels) in layers {
+                for (suffix, values) in channels {
+                    image.add_channel(format!("{}.{}", layer, suffix), values);
+                }
+            }
+            let path = format!("{}.exr", stem);
+            image.write(&path)?;
+            written.push(path);
+        }
+    }
+    Ok(written)
+}
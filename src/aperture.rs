@@ -0,0 +1,210 @@
+//! Lens aperture shapes, which set the shape of out-of-focus highlights (bokeh).
+//!
+//! Shapes are sampled on [-1, 1]^2 in units of the aperture radius; the camera scales
+//! the point by its defocus disk. Every shape consumes exactly one 2D sample.
+
+use std::fs;
+use std::io;
+
+use crate::fingerprint::Fingerprint;
+use crate::rtweekend::{PI, Shared};
+use crate::vec3::Vec3;
+
+#[derive(Clone, Debug, Default)]
+pub enum ApertureShape {
+    #[default]
+    Circle,
+    /// regular polygon inscribed in the unit circle, rotated by `rotation` degrees
+    Polygon { blades: usize, rotation: f64 },
+    /// ring between `inner` and 1, as behind a catadioptric (mirror) lens
+    Annular { inner: f64 },
+    /// grayscale transmission image stretched over [-1, 1]^2
+    Mask(Shared<ApertureMask>),
+}
+
+/// Aperture shape plus optional cat's-eye vignetting: toward the frame edges the lens
+/// barrel clips the aperture to the overlap with a disk shifted by `cat_eye` radii
+/// per unit of distance from the image center (1 at the corners).
+#[derive(Clone, Debug, Default)]
+pub struct Aperture {
+    pub shape: ApertureShape,
+    pub cat_eye: f64,
+}
+
+impl Aperture {
+    /// Point on the aperture for the 2D sample (u, v), for a pixel at `film` (center
+    /// (0, 0), corners at length 1). None when the barrel blocks that point.
+    pub fn sample(&self, u: f64, v: f64, film: (f64, f64)) -> Option<(f64, f64)> {
+        let (x, y) = match &self.shape {
+            ApertureShape::Circle => {
+                let p = Vec3::in_unit_disk_from_sample(u, v);
+                (p.x, p.y)
+            }
+            ApertureShape::Polygon { blades, rotation } => {
+                let blades = (*blades).max(3);
+                let k = ((u * blades as f64) as usize).min(blades - 1);
+                let a = u * blades as f64 - k as f64;
+                let corner = |k: usize| {
+                    let phi = 2.0 * PI * k as f64 / blades as f64 + rotation.to_radians();
+                    (phi.cos(), phi.sin())
+                };
+                let ((x0, y0), (x1, y1)) = (corner(k), corner(k + 1));
+                // uniform in the triangle (center, corner k, corner k + 1)
+                let s = a.sqrt();
+                (s * ((1.0 - v) * x0 + v * x1), s * ((1.0 - v) * y0 + v * y1))
+            }
+            ApertureShape::Annular { inner } => {
+                let inner = inner.clamp(0.0, 1.0);
+                let r = (inner * inner + u * (1.0 - inner * inner)).sqrt();
+                let phi = 2.0 * PI * v;
+                (r * phi.cos(), r * phi.sin())
+            }
+            ApertureShape::Mask(mask) => mask.sample(u, v),
+        };
+        let (sx, sy) = (-self.cat_eye * film.0, -self.cat_eye * film.1);
+        if self.cat_eye > 0.0 && (x - sx).powi(2) + (y - sy).powi(2) > 1.0 {
+            return None;
+        }
+        Some((x, y))
+    }
+
+    pub fn fingerprint(&self, fp: &mut Fingerprint) {
+        match &self.shape {
+            ApertureShape::Mask(mask) => {
+                fp.write_str("Mask");
+                fp.write_u64(mask.width as u64);
+                for &c in &mask.cdf {
+                    fp.write_f64(c);
+                }
+            }
+            shape => fp.write_str(&format!("{:?}", shape)),
+        }
+        fp.write_f64(self.cat_eye);
+    }
+}
+
+/// Aperture transmission image, sampled in proportion to its brightness.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApertureMask {
+    pub width: usize,
+    pub height: usize,
+    /// running sum of pixel values in scanline order, normalized to end at 1
+    cdf: Vec<f64>,
+}
+
+impl ApertureMask {
+    /// `values` are width * height transmissions in scanline order, top row first
+    pub fn new(width: usize, height: usize, values: &[f64]) -> Result<Self, String> {
+        if width == 0 || height == 0 || values.len() != width * height {
+            return Err(format!("aperture mask needs {}x{} values", width, height));
+        }
+        let mut total = 0.0;
+        let mut cdf: Vec<f64> = values.iter().map(|&v| { total += v.max(0.0); total }).collect();
+        if total <= 0.0 {
+            return Err("aperture mask is black".into());
+        }
+        cdf.iter_mut().for_each(|c| *c /= total);
+        Ok(Self { width, height, cdf })
+    }
+
+    /// load a binary or plain PGM/PPM; color images use their luminance
+    pub fn load(path: &str) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, msg));
+        let bytes = fs::read(path)?;
+        let (magic, width, height, max, data) = parse_pnm(&bytes).ok_or_else(|| invalid("not a PGM/PPM image".into()))?;
+        let channels = if magic == "P3" || magic == "P6" { 3 } else { 1 };
+        let samples: Vec<f64> = match magic.as_str() {
+            "P2" | "P3" => std::str::from_utf8(data).map_err(|e| invalid(e.to_string()))?
+                .split_whitespace()
+                .map(|t| t.parse::<f64>().map_err(|e| invalid(e.to_string())))
+                .collect::<io::Result<_>>()?,
+            _ if max < 256 => data.iter().map(|&b| b as f64).collect(),
+            _ => data.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as f64).collect(),
+        };
+        if samples.len() < width * height * channels {
+            return Err(invalid("truncated image".into()));
+        }
+        let values: Vec<f64> = samples.chunks_exact(channels).take(width * height)
+            .map(|c| if channels == 3 { 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2] } else { c[0] } / max as f64)
+            .collect();
+        Self::new(width, height, &values).map_err(invalid)
+    }
+
+    /// point in [-1, 1]^2; u picks the pixel and, rescaled, the position across it
+    pub fn sample(&self, u: f64, v: f64) -> (f64, f64) {
+        let k = self.cdf.partition_point(|&c| c <= u).min(self.cdf.len() - 1);
+        let lo = if k == 0 { 0.0 } else { self.cdf[k - 1] };
+        let fx = ((u - lo) / (self.cdf[k] - lo).max(1e-300)).clamp(0.0, 1.0);
+        let (i, j) = (k % self.width, k / self.width);
+        let x = (i as f64 + fx) / self.width as f64;
+        let y = (j as f64 + v) / self.height as f64;
+        (2.0 * x - 1.0, 1.0 - 2.0 * y)
+    }
+}
+
+/// (magic, width, height, max value, pixel data) of a PNM file
+fn parse_pnm(bytes: &[u8]) -> Option<(String, usize, usize, usize, &[u8])> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while fields.len() < 4 {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'#') {
+            if bytes[pos] == b'#' {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+            } else {
+                pos += 1;
+            }
+        }
+        let start = pos;
+        while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return None;
+        }
+        fields.push(std::str::from_utf8(&bytes[start..pos]).ok()?.to_string());
+    }
+    let magic = fields[0].clone();
+    if !["P2", "P3", "P5", "P6"].contains(&magic.as_str()) {
+        return None;
+    }
+    let (width, height, max) = (fields[1].parse().ok()?, fields[2].parse().ok()?, fields[3].parse().ok()?);
+    if max == 0 {
+        return None;
+    }
+    // a single whitespace byte separates the header from binary data
+    Some((magic, width, height, max, bytes.get(pos + 1..)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_stay_inside_their_shape() {
+        let hexagon = Aperture { shape: ApertureShape::Polygon { blades: 6, rotation: 0.0 }, cat_eye: 0.0 };
+        let ring = Aperture { shape: ApertureShape::Annular { inner: 0.5 }, cat_eye: 0.0 };
+        // right half of a 2x1 mask is open
+        let mask = ApertureMask::new(2, 1, &[0.0, 1.0]).unwrap();
+        let half = Aperture { shape: ApertureShape::Mask(Shared::new(mask)), cat_eye: 0.0 };
+        let apothem = (PI / 6.0).cos();
+        for a in 0..16 {
+            for b in 0..16 {
+                let (u, v) = ((a as f64 + 0.5) / 16.0, (b as f64 + 0.5) / 16.0);
+                let (x, y) = hexagon.sample(u, v, (0.0, 0.0)).unwrap();
+                for k in 0..6 {
+                    let phi = 2.0 * PI * (k as f64 + 0.5) / 6.0;
+                    assert!(x * phi.cos() + y * phi.sin() <= apothem + 1e-9);
+                }
+                let (x, y) = ring.sample(u, v, (0.0, 0.0)).unwrap();
+                assert!((0.25 - 1e-9..=1.0 + 1e-9).contains(&(x * x + y * y)));
+                let (x, _) = half.sample(u, v, (0.0, 0.0)).unwrap();
+                assert!(x >= 0.0);
+            }
+        }
+        let cat_eye = Aperture { shape: ApertureShape::Circle, cat_eye: 1.0 };
+        assert!(cat_eye.sample(0.6, 0.5, (0.0, 0.0)).is_some());
+        assert!(cat_eye.sample(0.99, 0.0, (1.0, 0.0)).is_none());
+    }
+}
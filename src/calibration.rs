@@ -0,0 +1,549 @@
+//! Pinhole cameras described the way calibration tools report them: an intrinsic
+//! matrix K, a world-to-camera pose and a lens distortion model.
+//!
+//! Conventions follow OpenCV: the camera frame has x right, y down and z forward,
+//! a world point X lands at R X + t in it, and pixel centers sit at integer
+//! coordinates (raster pixel (i, j) is centered on (i, j)).
+
+use std::fs;
+use std::io;
+
+use crate::ray::Ray;
+use crate::rtweekend::PI;
+use crate::vec3::{Vec3, Point3};
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum Distortion {
+    #[default]
+    None,
+    /// OpenCV's `plumb_bob` / Kalibr's `radtan`: radial k1, k2, k3 and tangential p1, p2
+    BrownConrady { k1: f64, k2: f64, p1: f64, p2: f64, k3: f64 },
+    /// OpenCV's `rational_polynomial`: Brown-Conrady with the radial factor divided by
+    /// 1 + k4 r^2 + k5 r^4 + k6 r^6
+    RationalPolynomial { k1: f64, k2: f64, p1: f64, p2: f64, k3: f64, k4: f64, k5: f64, k6: f64 },
+    /// OpenCV's fisheye model (`equidistant`): theta_d = theta (1 + k1 theta^2 + ... + k4 theta^8)
+    KannalaBrandt { k1: f64, k2: f64, k3: f64, k4: f64 },
+}
+
+impl Distortion {
+    /// Model from its usual names and coefficients in OpenCV order. OpenCV-style
+    /// models take 8 coefficients as the rational model; the thin prism and tilt
+    /// terms that may follow aren't supported and must be zero.
+    pub fn from_coefficients(model: &str, d: &[f64]) -> Result<Self, String> {
+        let at = |k: usize| d.get(k).copied().unwrap_or(0.0);
+        let rational = || Distortion::RationalPolynomial {
+            k1: at(0), k2: at(1), p1: at(2), p2: at(3), k3: at(4), k4: at(5), k5: at(6), k6: at(7),
+        };
+        let opencv = matches!(model, "plumb_bob" | "radtan" | "brown" | "brown-conrady" | "brown_conrady" | "opencv" | "rational_polynomial");
+        if opencv && d.iter().skip(8).any(|&c| c != 0.0) {
+            return Err(format!("{} thin prism and tilt coefficients are not supported", model));
+        }
+        match model {
+            "none" => Ok(Distortion::None),
+            "rational_polynomial" => Ok(rational()),
+            _ if opencv && d.iter().skip(5).any(|&c| c != 0.0) => Ok(rational()),
+            _ if opencv => Ok(Distortion::BrownConrady { k1: at(0), k2: at(1), p1: at(2), p2: at(3), k3: at(4) }),
+            "equidistant" | "fisheye" | "kannala-brandt" | "kannala_brandt" | "kb4" => {
+                Ok(Distortion::KannalaBrandt { k1: at(0), k2: at(1), k3: at(2), k4: at(3) })
+            }
+            _ => Err(format!("unknown distortion model '{}'", model)),
+        }
+    }
+}
+
+/// A calibrated pinhole camera: K = [fx 0 cx; 0 fy cy; 0 0 1] for a `width` x `height`
+/// image, pose (`rotation`, `translation`) from world to camera, and `distortion`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CalibratedCamera {
+    pub width: usize,
+    pub height: usize,
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+    /// rows of R
+    pub rotation: [[f64; 3]; 3],
+    pub translation: Vec3,
+    pub distortion: Distortion,
+}
+
+impl CalibratedCamera {
+    /// undistorted camera at the world origin looking down +z, y down
+    pub fn new(width: usize, height: usize, fx: f64, fy: f64, cx: f64, cy: f64) -> Self {
+        Self {
+            width, height, fx, fy, cx, cy,
+            rotation: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            translation: Vec3::zero(),
+            distortion: Distortion::None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err("calibrated image size must be > 0".into());
+        }
+        if self.fx <= 0.0 || self.fy <= 0.0 {
+            return Err("focal lengths fx and fy must be > 0".into());
+        }
+        let r = &self.rotation;
+        for a in 0..3 {
+            for b in 0..3 {
+                let dot: f64 = (0..3).map(|k| r[a][k] * r[b][k]).sum();
+                if (dot - if a == b { 1.0 } else { 0.0 }).abs() > 1e-6 {
+                    return Err("extrinsic rotation is not orthonormal".into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// camera axis `k` (x right, y down, z forward) in world space: row k of R
+    pub fn axis(&self, k: usize) -> Vec3 {
+        Vec3::new(self.rotation[k][0], self.rotation[k][1], self.rotation[k][2])
+    }
+
+    /// optical center in world space, -R^T t
+    pub fn center(&self) -> Point3 {
+        let t = self.translation;
+        -(t.x * self.axis(0) + t.y * self.axis(1) + t.z * self.axis(2))
+    }
+
+    /// world-space ray through pixel position (px, py); None outside the valid
+    /// range of the distortion model
+    pub fn ray(&self, px: f64, py: f64) -> Option<Ray> {
+        let d = self.unproject(px, py)?;
+        Some(Ray::new(self.center(), d.x * self.axis(0) + d.y * self.axis(1) + d.z * self.axis(2)))
+    }
+
+    /// pixel position at which the world point `p` appears; None behind the camera
+    pub fn project_point(&self, p: Point3) -> Option<(f64, f64)> {
+        let d = p - self.center();
+        self.project(Vec3::new(d.dot(&self.axis(0)), d.dot(&self.axis(1)), d.dot(&self.axis(2))))
+    }
+
+    /// pixel position of the camera-frame direction or point `p`
+    pub fn project(&self, p: Vec3) -> Option<(f64, f64)> {
+        let (xd, yd) = match self.distortion {
+            Distortion::KannalaBrandt { k1, k2, k3, k4 } => {
+                let r = p.x.hypot(p.y);
+                let theta = r.atan2(p.z);
+                let theta_d = kannala_brandt(theta, k1, k2, k3, k4);
+                if r > 0.0 { (theta_d * p.x / r, theta_d * p.y / r) } else { (0.0, 0.0) }
+            }
+            distortion => {
+                if p.z <= 0.0 {
+                    return None;
+                }
+                brown_conrady(p.x / p.z, p.y / p.z, &distortion)
+            }
+        };
+        Some((self.fx * xd + self.cx, self.fy * yd + self.cy))
+    }
+
+    /// camera-frame direction (not normalized) through pixel position (px, py)
+    pub fn unproject(&self, px: f64, py: f64) -> Option<Vec3> {
+        let (xd, yd) = ((px - self.cx) / self.fx, (py - self.cy) / self.fy);
+        match self.distortion {
+            Distortion::None => Some(Vec3::new(xd, yd, 1.0)),
+            Distortion::BrownConrady { .. } | Distortion::RationalPolynomial { .. } => {
+                // Newton's method on the 2D distortion map, from the distorted point
+                let (mut x, mut y) = (xd, yd);
+                for _ in 0..50 {
+                    let (gx, gy) = brown_conrady(x, y, &self.distortion);
+                    let (ex, ey) = (gx - xd, gy - yd);
+                    if ex.abs() < 1e-14 && ey.abs() < 1e-14 {
+                        break;
+                    }
+                    let h = 1e-7;
+                    let (ax, ay) = brown_conrady(x + h, y, &self.distortion);
+                    let (bx, by) = brown_conrady(x, y + h, &self.distortion);
+                    let (j00, j10, j01, j11) = ((ax - gx) / h, (ay - gy) / h, (bx - gx) / h, (by - gy) / h);
+                    let det = j00 * j11 - j01 * j10;
+                    if det.abs() < 1e-12 {
+                        return None;
+                    }
+                    x -= (j11 * ex - j01 * ey) / det;
+                    y -= (j00 * ey - j10 * ex) / det;
+                }
+                let (gx, gy) = brown_conrady(x, y, &self.distortion);
+                ((gx - xd).hypot(gy - yd) < 1e-9).then_some(Vec3::new(x, y, 1.0))
+            }
+            Distortion::KannalaBrandt { k1, k2, k3, k4 } => {
+                let theta_d = xd.hypot(yd);
+                if theta_d == 0.0 {
+                    return Some(Vec3::new(0.0, 0.0, 1.0));
+                }
+                let mut theta = theta_d.min(PI);
+                for _ in 0..50 {
+                    let t2 = theta * theta;
+                    let slope = 1.0 + t2 * (3.0 * k1 + t2 * (5.0 * k2 + t2 * (7.0 * k3 + t2 * 9.0 * k4)));
+                    let step = (kannala_brandt(theta, k1, k2, k3, k4) - theta_d) / slope;
+                    theta = (theta - step).clamp(0.0, PI);
+                    if step.abs() < 1e-14 {
+                        break;
+                    }
+                }
+                if (kannala_brandt(theta, k1, k2, k3, k4) - theta_d).abs() > 1e-9 {
+                    return None;
+                }
+                let s = theta.sin() / theta_d;
+                Some(Vec3::new(s * xd, s * yd, theta.cos()))
+            }
+        }
+    }
+
+    /// Read a calibration from JSON or YAML. Recognized layouts include OpenCV
+    /// `FileStorage` YAML, ROS `camera_info` YAML, Kalibr camchains and flat JSON
+    /// objects; see `from_document` for the keys looked at.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let doc = if text.trim_start().starts_with('{') { parse_json(text)? } else { parse_yaml(text)? };
+        Self::from_document(&doc)
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+    }
+
+    /// Image size from `image_width`/`image_height` (or `width`/`height`,
+    /// `resolution: [w, h]`); intrinsics from a 3x3 `camera_matrix`/`K`, Kalibr's
+    /// `intrinsics: [fx, fy, cx, cy]` or separate `fx`, `fy`, `cx`, `cy`; distortion from
+    /// `distortion_model` and `distortion_coefficients`/`D`; the world-to-camera pose
+    /// from `R` (or a Rodrigues `rvec`) and `t`/`tvec`, or a 4x4 `T_cam_world`.
+    fn from_document(doc: &Value) -> Result<Self, String> {
+        let number = |keys: &[&str]| doc.find(keys).and_then(|v| v.numbers().first().copied());
+        let (width, height) = match (number(&["image_width", "width"]), number(&["image_height", "height"])) {
+            (Some(w), Some(h)) => (w, h),
+            _ => match doc.find(&["resolution", "image_size", "size"]).map(Value::numbers).as_deref() {
+                Some([w, h, ..]) => (*w, *h),
+                _ => return Err("calibration has no image size".into()),
+            },
+        };
+
+        let k = doc.find(&["camera_matrix", "K", "intrinsic_matrix", "k"]).map(Value::numbers);
+        let (fx, fy, cx, cy) = match (k.as_deref(), doc.find(&["intrinsics"]).map(Value::numbers).as_deref()) {
+            (Some(k), _) if k.len() == 9 => (k[0], k[4], k[2], k[5]),
+            (_, Some([fx, fy, cx, cy])) => (*fx, *fy, *cx, *cy),
+            _ => match (number(&["fx"]), number(&["fy"]), number(&["cx"]), number(&["cy"])) {
+                (Some(fx), Some(fy), Some(cx), Some(cy)) => (fx, fy, cx, cy),
+                _ => return Err("calibration has no intrinsic matrix".into()),
+            },
+        };
+        let mut camera = Self::new(width as usize, height as usize, fx, fy, cx, cy);
+
+        let coefficients = doc.find(&["distortion_coefficients", "distortion_coeffs", "dist_coeffs", "distortion", "D", "d"]).map(Value::numbers);
+        let model = doc.find(&["distortion_model", "model"]).and_then(Value::as_str).map(str::to_lowercase);
+        camera.distortion = match (model.as_deref(), coefficients) {
+            (Some(model), coefficients) => Distortion::from_coefficients(model, coefficients.as_deref().unwrap_or(&[]))?,
+            (None, Some(coefficients)) => Distortion::from_coefficients("plumb_bob", &coefficients)?,
+            (None, None) => Distortion::None,
+        };
+
+        let matrix = doc.find(&["T_cam_world", "world_to_camera", "extrinsics", "T"]).map(Value::numbers);
+        if let Some(m) = matrix.filter(|m| m.len() == 16 || m.len() == 12) {
+            camera.rotation = [[m[0], m[1], m[2]], [m[4], m[5], m[6]], [m[8], m[9], m[10]]];
+            camera.translation = Vec3::new(m[3], m[7], m[11]);
+        }
+        let rotation = doc.find(&["R", "rotation_matrix", "rotation"]).map(Value::numbers);
+        match (rotation.as_deref(), doc.find(&["rvec", "rotation_vector"]).map(Value::numbers).as_deref()) {
+            (Some(r), _) if r.len() == 9 => camera.rotation = [[r[0], r[1], r[2]], [r[3], r[4], r[5]], [r[6], r[7], r[8]]],
+            (_, Some([x, y, z])) => camera.rotation = rodrigues(Vec3::new(*x, *y, *z)),
+            _ => {}
+        }
+        if let Some([x, y, z]) = doc.find(&["t", "tvec", "translation"]).map(Value::numbers).as_deref() {
+            camera.translation = Vec3::new(*x, *y, *z);
+        }
+        camera.validate()?;
+        Ok(camera)
+    }
+}
+
+/// distorted normalized coordinates of the undistorted ones (x, y), for the
+/// Brown-Conrady and rational models
+fn brown_conrady(x: f64, y: f64, distortion: &Distortion) -> (f64, f64) {
+    let (k1, k2, p1, p2, k3, k4, k5, k6) = match *distortion {
+        Distortion::BrownConrady { k1, k2, p1, p2, k3 } => (k1, k2, p1, p2, k3, 0.0, 0.0, 0.0),
+        Distortion::RationalPolynomial { k1, k2, p1, p2, k3, k4, k5, k6 } => (k1, k2, p1, p2, k3, k4, k5, k6),
+        _ => return (x, y),
+    };
+    let r2 = x * x + y * y;
+    let radial = (1.0 + r2 * (k1 + r2 * (k2 + r2 * k3))) / (1.0 + r2 * (k4 + r2 * (k5 + r2 * k6)));
+    (x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x), y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y)
+}
+
+fn kannala_brandt(theta: f64, k1: f64, k2: f64, k3: f64, k4: f64) -> f64 {
+    let t2 = theta * theta;
+    theta * (1.0 + t2 * (k1 + t2 * (k2 + t2 * (k3 + t2 * k4))))
+}
+
+/// rotation matrix of the axis-angle vector `r`
+fn rodrigues(r: Vec3) -> [[f64; 3]; 3] {
+    let angle = r.length();
+    if angle < 1e-12 {
+        return [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    }
+    let k = r / angle;
+    let (s, c) = angle.sin_cos();
+    let t = 1.0 - c;
+    [
+        [c + t * k.x * k.x, t * k.x * k.y - s * k.z, t * k.x * k.z + s * k.y],
+        [t * k.x * k.y + s * k.z, c + t * k.y * k.y, t * k.y * k.z - s * k.x],
+        [t * k.x * k.z - s * k.y, t * k.y * k.z + s * k.x, c + t * k.z * k.z],
+    ]
+}
+
+/// just enough of a JSON / YAML document model for calibration files
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(f64),
+    Str(String),
+    List(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// first value stored under any of `keys`, searching depth first
+    fn find(&self, keys: &[&str]) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => {
+                for key in keys {
+                    if let Some((_, v)) = entries.iter().find(|(k, _)| k == key) {
+                        return Some(v);
+                    }
+                }
+                entries.iter().find_map(|(_, v)| v.find(keys))
+            }
+            Value::List(items) => items.iter().find_map(|v| v.find(keys)),
+            _ => None,
+        }
+    }
+
+    /// all numbers, flattened; a matrix map contributes its `data`
+    fn numbers(&self) -> Vec<f64> {
+        match self {
+            Value::Number(n) => vec![*n],
+            Value::Str(s) => s.parse().map_or(Vec::new(), |n| vec![n]),
+            Value::List(items) => items.iter().flat_map(Value::numbers).collect(),
+            Value::Map(entries) => entries.iter().find(|(k, _)| k == "data").map_or(Vec::new(), |(_, v)| v.numbers()),
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        if let Value::Str(s) = self { Some(s) } else { None }
+    }
+}
+
+fn scalar(text: &str) -> Value {
+    let text = text.trim().trim_matches(['"', '\'']);
+    text.parse().map_or_else(|_| Value::Str(text.to_string()), Value::Number)
+}
+
+fn parse_json(text: &str) -> Result<Value, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut pos = 0;
+    let value = json_value(&chars, &mut pos)?;
+    skip_space(&chars, &mut pos);
+    if pos < chars.len() {
+        return Err(format!("trailing characters at offset {}", pos));
+    }
+    Ok(value)
+}
+
+fn skip_space(chars: &[char], pos: &mut usize) {
+    while *pos < chars.len() && chars[*pos].is_whitespace() {
+        *pos += 1;
+    }
+}
+
+fn json_value(chars: &[char], pos: &mut usize) -> Result<Value, String> {
+    skip_space(chars, pos);
+    let expect = |pos: &mut usize, c: char| {
+        skip_space(chars, pos);
+        if chars.get(*pos) == Some(&c) { *pos += 1; Ok(()) } else { Err(format!("expected '{}' at offset {}", c, pos)) }
+    };
+    match chars.get(*pos) {
+        Some('{') => {
+            *pos += 1;
+            let mut entries = Vec::new();
+            skip_space(chars, pos);
+            if chars.get(*pos) == Some(&'}') {
+                *pos += 1;
+                return Ok(Value::Map(entries));
+            }
+            loop {
+                let Value::Str(key) = json_value(chars, pos)? else { return Err(format!("expected a key at offset {}", pos)) };
+                expect(pos, ':')?;
+                entries.push((key, json_value(chars, pos)?));
+                skip_space(chars, pos);
+                match chars.get(*pos) {
+                    Some(',') => *pos += 1,
+                    _ => { expect(pos, '}')?; return Ok(Value::Map(entries)); }
+                }
+            }
+        }
+        Some('[') => {
+            *pos += 1;
+            let mut items = Vec::new();
+            skip_space(chars, pos);
+            if chars.get(*pos) == Some(&']') {
+                *pos += 1;
+                return Ok(Value::List(items));
+            }
+            loop {
+                items.push(json_value(chars, pos)?);
+                skip_space(chars, pos);
+                match chars.get(*pos) {
+                    Some(',') => *pos += 1,
+                    _ => { expect(pos, ']')?; return Ok(Value::List(items)); }
+                }
+            }
+        }
+        Some('"') => {
+            *pos += 1;
+            let mut s = String::new();
+            while let Some(&c) = chars.get(*pos) {
+                *pos += 1;
+                match c {
+                    '"' => return Ok(Value::Str(s)),
+                    '\\' => {
+                        s.push(*chars.get(*pos).ok_or("unterminated string")?);
+                        *pos += 1;
+                    }
+                    c => s.push(c),
+                }
+            }
+            Err("unterminated string".into())
+        }
+        Some(_) => {
+            let start = *pos;
+            while *pos < chars.len() && !matches!(chars[*pos], ',' | '}' | ']') && !chars[*pos].is_whitespace() {
+                *pos += 1;
+            }
+            Ok(scalar(&chars[start..*pos].iter().collect::<String>()))
+        }
+        None => Err("unexpected end of document".into()),
+    }
+}
+
+/// YAML subset: block maps by indentation, `- ` block lists, flow lists (which may
+/// wrap over lines), scalars; tags such as `!!opencv-matrix` and directives are ignored
+fn parse_yaml(text: &str) -> Result<Value, String> {
+    // (indent, content) of each meaningful line, with wrapped flow lists joined
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut open = 0i32;
+    for line in text.lines() {
+        let content = line.split(" #").next().unwrap_or("");
+        let trimmed = content.trim();
+        if open > 0 {
+            lines.last_mut().unwrap().1.push_str(trimmed);
+        } else {
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('%') || trimmed == "---" {
+                continue;
+            }
+            lines.push((content.len() - content.trim_start().len(), trimmed.to_string()));
+        }
+        open += trimmed.matches('[').count() as i32 - trimmed.matches(']').count() as i32;
+    }
+    let mut pos = 0;
+    yaml_block(&lines, &mut pos, 0)
+}
+
+fn yaml_block(lines: &[(usize, String)], pos: &mut usize, indent: usize) -> Result<Value, String> {
+    let is_list = lines.get(*pos).is_some_and(|(_, l)| l.starts_with("- ") || l == "-");
+    let mut entries = Vec::new();
+    let mut items = Vec::new();
+    while let Some((line_indent, line)) = lines.get(*pos) {
+        if *line_indent < indent || (is_list && *line_indent == indent && !line.starts_with('-')) {
+            break;
+        }
+        *pos += 1;
+        if is_list {
+            let item = line.trim_start_matches('-').trim();
+            items.push(if item.is_empty() { yaml_nested(lines, pos, *line_indent)? } else { yaml_inline(item)? });
+            continue;
+        }
+        let Some((key, rest)) = line.split_once(':') else { return Err(format!("expected 'key: value', got '{}'", line)) };
+        let rest = rest.trim();
+        let rest = if rest.starts_with("!!") { rest.split_once(' ').map_or("", |(_, r)| r).trim() } else { rest };
+        let value = if rest.is_empty() { yaml_nested(lines, pos, *line_indent)? } else { yaml_inline(rest)? };
+        entries.push((key.trim().trim_matches('"').to_string(), value));
+    }
+    Ok(if is_list { Value::List(items) } else { Value::Map(entries) })
+}
+
+/// the block below a line indented by `indent`; lists may sit at the same indent
+fn yaml_nested(lines: &[(usize, String)], pos: &mut usize, indent: usize) -> Result<Value, String> {
+    match lines.get(*pos) {
+        Some((next, l)) if *next > indent || (*next == indent && l.starts_with('-')) => yaml_block(lines, pos, *next),
+        _ => Ok(Value::Map(Vec::new())),
+    }
+}
+
+fn yaml_inline(text: &str) -> Result<Value, String> {
+    if text.starts_with('[') || text.starts_with('{') {
+        // flow collections are JSON apart from quoting
+        let quoted: String = text.split_inclusive(['{', ',', '[']).map(|part| {
+            match part.split_once(':') {
+                Some((key, rest)) if !key.trim().starts_with('"') => format!("\"{}\":{}", key.trim(), rest),
+                _ => part.to_string(),
+            }
+        }).collect();
+        parse_json(&quoted)
+    } else {
+        Ok(scalar(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unproject_inverts_project() {
+        let mut camera = CalibratedCamera::new(640, 480, 500.0, 505.0, 321.0, 238.5);
+        for distortion in [
+            Distortion::BrownConrady { k1: -0.28, k2: 0.07, p1: 0.001, p2: -0.0005, k3: 0.0 },
+            Distortion::RationalPolynomial { k1: 0.4, k2: -0.1, p1: 0.001, p2: -0.0005, k3: 0.01, k4: 0.7, k5: -0.05, k6: 0.02 },
+            Distortion::KannalaBrandt { k1: 0.05, k2: -0.01, k3: 0.002, k4: 0.0 },
+        ] {
+            camera.distortion = distortion;
+            for (px, py) in [(320.0, 240.0), (10.0, 20.0), (600.5, 470.0), (100.0, 400.0)] {
+                let d = camera.unproject(px, py).unwrap();
+                let (qx, qy) = camera.project(d).unwrap();
+                assert!((qx - px).abs() < 1e-6 && (qy - py).abs() < 1e-6, "{:?}: {} {} -> {} {}", distortion, px, py, qx, qy);
+            }
+        }
+        // rotated a quarter turn about y and moved: the world origin sits 2 units ahead
+        camera.distortion = Distortion::None;
+        camera.rotation = rodrigues(Vec3::new(0.0, PI / 2.0, 0.0));
+        camera.translation = Vec3::new(0.0, 0.0, 2.0);
+        let (px, py) = camera.project_point(Point3::zero()).unwrap();
+        assert!((px - camera.cx).abs() < 1e-9 && (py - camera.cy).abs() < 1e-9);
+        let ray = camera.ray(camera.cx, camera.cy).unwrap();
+        assert!((ray.at(2.0) - Point3::zero()).length() < 1e-9);
+    }
+
+    #[test]
+    fn reads_opencv_ros_and_json_layouts() {
+        let opencv = "%YAML:1.0\n---\nimage_width: 640\nimage_height: 480\ncamera_matrix: !!opencv-matrix\n   rows: 3\n   cols: 3\n   dt: d\n   data: [ 500., 0., 320., 0.,\n       505., 240., 0., 0., 1. ]\ndistortion_coefficients: !!opencv-matrix\n   rows: 1\n   cols: 5\n   dt: d\n   data: [ -0.2, 0.05, 0., 0., 0. ]\n";
+        let ros = "image_width: 640\nimage_height: 480\ncamera_name: left\ncamera_matrix:\n  rows: 3\n  cols: 3\n  data: [500, 0, 320, 0, 505, 240, 0, 0, 1]\ndistortion_model: equidistant\ndistortion_coefficients:\n  rows: 1\n  cols: 4\n  data: [0.01, 0.0, 0.0, 0.0]\n";
+        let json = r#"{"width": 640, "height": 480, "K": [[500, 0, 320], [0, 505, 240], [0, 0, 1]],
+            "distortion_model": "radtan", "D": [-0.2, 0.05, 0, 0], "rvec": [0, 0, 0], "tvec": [1, 2, 3]}"#;
+
+        let a = CalibratedCamera::parse(opencv).unwrap();
+        assert_eq!((a.width, a.height, a.fx, a.fy, a.cx, a.cy), (640, 480, 500.0, 505.0, 320.0, 240.0));
+        assert_eq!(a.distortion, Distortion::BrownConrady { k1: -0.2, k2: 0.05, p1: 0.0, p2: 0.0, k3: 0.0 });
+        let b = CalibratedCamera::parse(ros).unwrap();
+        assert_eq!(b.distortion, Distortion::KannalaBrandt { k1: 0.01, k2: 0.0, k3: 0.0, k4: 0.0 });
+        let c = CalibratedCamera::parse(json).unwrap();
+        assert_eq!(c.fy, 505.0);
+        assert_eq!(c.center(), Point3::new(-1.0, -2.0, -3.0));
+        assert!(CalibratedCamera::parse("image_width: 640\n").is_err());
+
+        // eight OpenCV coefficients are the rational model, whatever it's called
+        let d = [0.4, -0.1, 0.0, 0.0, 0.01, 0.7, -0.05, 0.02];
+        let rational = Distortion::RationalPolynomial { k1: 0.4, k2: -0.1, p1: 0.0, p2: 0.0, k3: 0.01, k4: 0.7, k5: -0.05, k6: 0.02 };
+        assert_eq!(Distortion::from_coefficients("rational_polynomial", &d), Ok(rational));
+        assert_eq!(Distortion::from_coefficients("plumb_bob", &d), Ok(rational));
+        assert!(Distortion::from_coefficients("plumb_bob", &[0.4, -0.1, 0.0, 0.0, 0.01, 0.7, -0.05, 0.02, 0.001, 0.0, 0.0, 0.0]).is_err());
+    }
+}
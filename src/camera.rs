@@ -1,33 +1,142 @@
 use std::fs::File;
 use std::io::{self, Write, BufWriter};
-use std::cmp::max;
 
 use crate::color::Color;
 use crate::vec3::Vec3;
 use crate::vec3::Point3;
-use crate::rtweekend::{Shared, INFINITY_F64, PI, degrees_to_radians, random_double};
+use crate::rtweekend::degrees_to_radians;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::adaptive::{AdaptiveSampling, RunningStats, write_sample_heatmap};
 use crate::ray::Ray;
-use crate::hittable::{Hittable, HitRecord};
+use crate::hittable::Hittable;
 use crate::hittable_list::HittableList;
 
-use rayon::iter::IntoParallelIterator;
-use rayon::prelude::*;
+use crate::scheduler::{TileScheduler, RenderEvent, Tile};
+use crate::filter::{PixelFilter, Splat, SplatBuffer};
+use crate::projection::Projection;
+use crate::physical::PhysicalCamera;
+use crate::aperture::Aperture;
+use crate::lens::LensSystem;
+use crate::calibration::CalibratedCamera;
+use crate::rtweekend::INFINITY_F64;
+use crate::film::{self, AlphaMode, Film};
+use crate::checkpoint::Checkpoint;
+use crate::fingerprint::Fingerprint;
+use crate::integrator::{Integrator, IntegratorPtr, PathIntegrator, SampleRecord};
+use crate::aov::{AovOutput, AovPixel, write_aovs};
+use crate::denoise::{DenoiseSettings, denoise_film};
+use crate::animation::Animation;
+use crate::lpe::{self, LightPass};
+use crate::cryptomatte::{MattePixel, write_cryptomatte};
+use crate::stats::{self, RayCounters, RenderStats, TileStats};
+use crate::diagnostics::{BadSample, Diagnostics, Finding, Problem};
+use crate::path_debug::PathTrace;
+
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+/// how `Camera::render_progressive` splits the work and when it checkpoints
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgressiveSettings {
+    /// samples added to each pixel per pass
+    pub pass_samples: usize,
+    pub checkpoint_path: Option<String>,
+    /// passes between checkpoints; 0 only writes one at the end
+    pub checkpoint_interval: usize,
+    pub multithreaded: bool,
+    /// stop starting new passes once the next one would overrun this wall-clock budget;
+    /// the first pass then takes one sample per pixel and always runs
+    pub time_budget: Option<Duration>,
+}
+
+impl Default for ProgressiveSettings {
+    fn default() -> Self {
+        Self { pass_samples: 16, checkpoint_path: None, checkpoint_interval: 1, multithreaded: true, time_budget: None }
+    }
+}
 
-use std::time::Instant;
+impl ProgressiveSettings {
+    fn checkpoint_due(&self, pass: usize) -> bool {
+        pass > 0 && self.checkpoint_interval > 0 && pass.is_multiple_of(self.checkpoint_interval)
+    }
+}
 
 pub struct Camera {
 
     pub aspect_ratio: f64,
     pub image_width: usize,
     pub samples_per_pixel: usize,
-    pub max_depth: usize,
+    /// hard cap on bounces; None leaves termination to Russian roulette
+    pub max_depth: Option<usize>,
+    /// bounces before Russian roulette may end a path
+    pub rr_min_depth: usize,
+    /// what to compute per camera ray; None is the path tracer built from
+    /// `max_depth` and `rr_min_depth`
+    pub integrator: Option<IntegratorPtr>,
+    /// how film positions map to rays; every projection shares the `lookfrom`/`lookat`/`vup` frame
+    pub projection: Projection,
     pub vfov: f64,
     pub lookfrom: Point3,
     pub lookat: Point3,
     pub vup: Vec3,
     pub defocus_angle: f64,
+    /// shape of the defocus disk, and cat's-eye vignetting
+    pub aperture: Aperture,
+    /// trace perspective rays through this lens prescription instead of the thin-lens
+    /// disk; it brings its own stop and field of view, focused at `focus_dist` on the
+    /// `physical` sensor (or a full-frame one)
+    pub lens: Option<LensSystem>,
+    /// match a real calibrated camera: when set, rendering takes the image size,
+    /// `lookfrom`/`lookat`/`vup` and `vfov` from it, and rays follow its intrinsics and
+    /// distortion instead of the projection
+    pub calibration: Option<CalibratedCamera>,
     pub focus_dist: f64,
-    pixel_sample_scale: f64,
+    /// sensor, lens, shutter and ISO; when set, `initialize` derives `vfov`,
+    /// `defocus_angle` and `exposure` from it
+    pub physical: Option<PhysicalCamera>,
+    /// set `focus_dist` to whatever the center pixel's ray hits before rendering
+    pub autofocus: bool,
+    /// factor applied to every radiance sample
+    pub exposure: f64,
+    /// global seed; together with pixel and sample index it fixes every random number
+    pub seed: u64,
+    /// sample generator for pixel jitter, lens and per-bounce material sampling
+    pub sampler: SamplerKind,
+    /// stop converged pixels early; `samples_per_pixel` becomes the maximum
+    pub adaptive: Option<AdaptiveSampling>,
+    /// tile size, ordering, thread count, progress events and cancellation
+    pub scheduler: TileScheduler,
+    /// record first-hit AOVs and write them next to image.ppm (film-based renders only;
+    /// the streaming `render` ignores it)
+    pub aov_output: Option<AovOutput>,
+    /// (lookfrom, lookat) one frame earlier, for the motion-vector AOV
+    pub previous_pose: Option<(Point3, Point3)>,
+    /// also write image.denoised.ppm, filtered with the first-hit AOVs as guides
+    pub denoise: Option<DenoiseSettings>,
+    /// splat samples into neighboring pixels with this filter (film-based renders only);
+    /// None averages each pixel's own samples, a box of one pixel
+    pub filter: Option<PixelFilter>,
+    /// transparent film for compositing: background and holdouts get zero alpha,
+    /// shadow catchers keep only what falls onto them, and image.png and image.exr
+    /// are written as RGBA stored this way (film-based renders only)
+    pub alpha: Option<AlphaMode>,
+    /// split the beauty into these passes, written next to image.ppm as EXR layers or
+    /// files (film-based renders only)
+    pub light_passes: Vec<LightPass>,
+    /// keep this many (id, coverage) ranks of object and material ID mattes per pixel
+    /// and write them as image.cryptomatte.exr (film-based renders only)
+    pub cryptomatte: Option<usize>,
+    /// print a table of ray counts, intersection tests, path lengths and tile times
+    /// per worker thread when the render finishes
+    pub print_stats: bool,
+    /// also write them next to the image as image.stats.json
+    pub stats_json: bool,
+    /// check every sample for NaN, infinite and negative values, and report how many
+    /// there were and where the first one came from
+    pub check_samples: bool,
+    /// with `check_samples`, paint the pixels that received bad samples this color
+    /// (film-based renders keep the real color underneath)
+    pub signal_color: Option<Color>,
     image_height: usize,
     center: Point3,
     pixel00_loc: Point3,
@@ -52,14 +161,37 @@ impl Default for Camera {
             aspect_ratio,
             image_width,
             samples_per_pixel,
-            max_depth,
+            max_depth: Some(max_depth),
+            rr_min_depth: 3,
+            integrator: None,
+            projection: Projection::default(),
             vfov : 90.0, //will be computed in initialize()
             lookfrom: Point3::new(0.0,0.0,0.0),
             lookat: Point3::new(0.0,0.0,0.0),
             vup: Vec3::new(0.0,0.0,0.0),
             defocus_angle: 0.0,
+            aperture: Aperture::default(),
+            lens: None,
+            calibration: None,
             focus_dist: 0.0,
-            pixel_sample_scale: 1.0,
+            physical: None,
+            autofocus: false,
+            exposure: 1.0,
+            seed: 0,
+            sampler: SamplerKind::default(),
+            adaptive: None,
+            scheduler: TileScheduler::default(),
+            aov_output: None,
+            previous_pose: None,
+            denoise: None,
+            filter: None,
+            alpha: None,
+            light_passes: Vec::new(),
+            cryptomatte: None,
+            print_stats: false,
+            stats_json: false,
+            check_samples: false,
+            signal_color: None,
             image_height: 0, // will be computed in initialize()
             center: Point3::new(0.0, 0.0, 0.0),
             pixel00_loc: Point3::new(0.0, 0.0, 0.0),
@@ -77,16 +209,17 @@ impl Default for Camera {
 impl Camera{
 
     pub fn new_with(image_width: usize, aspect_ratio: f64, samples_per_pixel: usize, max_depth: usize, vfov: f64) -> Self {
-        let mut c = Self::default();
-        c.image_width = image_width;
-        c.aspect_ratio = aspect_ratio;
-        c.samples_per_pixel = samples_per_pixel;
-        c.max_depth = max_depth;
-        c.vfov = vfov;
-        // c.lookfrom = lookfrom;
-        // c.lookat = lookat;
-        // c.vup = vup;
-        c
+        Self {
+            image_width,
+            aspect_ratio,
+            samples_per_pixel,
+            max_depth: Some(max_depth),
+            vfov,
+            // lookfrom,
+            // lookat,
+            // vup,
+            ..Self::default()
+        }
     }
 
     fn initialize(&mut self) -> Result<(), String>{
@@ -97,11 +230,28 @@ impl Camera{
         if self.image_width == 0 {
             return Err("image_width must be > 0".into());
         }
+        self.projection.validate()?;
+        if let Some(physical) = &self.physical {
+            physical.validate()?;
+            self.vfov = physical.vfov(self.aspect_ratio);
+            self.defocus_angle = physical.defocus_angle(self.focus_dist);
+            self.exposure = physical.exposure();
+        }
+        let sensor = self.sensor_size();
+        if let Some(lens) = &mut self.lens {
+            if self.projection != Projection::Perspective {
+                return Err("a lens prescription needs the perspective projection".into());
+            }
+            let mm_per_unit = 1000.0 * self.physical.as_ref().map_or(1.0, |p| p.meters_per_unit);
+            lens.prepare(sensor.0, sensor.1, self.focus_dist * mm_per_unit)?;
+            self.vfov = lens.vfov().ok_or("lens vignettes the whole frame edge")?;
+        }
 
         // self.image_height = ((image_width as f64 / aspect_ratio).max(1.0)) as usize;
         self.image_height = ((self.image_width as f64) / self.aspect_ratio).max(1.0) as usize;
-
-        self.pixel_sample_scale = 1.0 / self.samples_per_pixel as f64;
+        if let Some(calibration) = &self.calibration {
+            self.image_height = calibration.height;
+        }
 
         self.center = self.lookfrom;
 
@@ -138,65 +288,451 @@ impl Camera{
 
     }
 
-        pub fn render_multithreaded(&mut self, world: &HittableList) -> io::Result<()> {
-            self.initialize().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    /// (width, height) in mm of the part of the sensor the image covers, cropped to its aspect
+    fn sensor_size(&self) -> (f64, f64) {
+        let sensor = self.physical.clone().unwrap_or_default();
+        let aspect = self.image_width as f64 / (((self.image_width as f64) / self.aspect_ratio).max(1.0) as usize) as f64;
+        let height = sensor.sensor_height_mm.min(sensor.sensor_width_mm / aspect);
+        (height * aspect, height)
+    }
+
+    /// take the image size, pose and vfov from the calibration, if any
+    fn calibrate(&mut self) -> Result<(), String> {
+        let Some(calibration) = &self.calibration else { return Ok(()) };
+        calibration.validate()?;
+        if self.projection != Projection::Perspective || self.lens.is_some() {
+            return Err("a calibrated camera brings its own projection".into());
+        }
+        self.image_width = calibration.width;
+        self.aspect_ratio = calibration.width as f64 / calibration.height as f64;
+        self.lookfrom = calibration.center();
+        self.lookat = self.lookfrom + calibration.axis(2);
+        self.vup = -calibration.axis(1);
+        self.vfov = 2.0 * (calibration.height as f64 / (2.0 * calibration.fy)).atan().to_degrees();
+        Ok(())
+    }
+
+    /// `calibrate`, autofocus if asked to (from the calibrated pose), then `initialize`
+    fn prepare(&mut self, world: &HittableList) -> io::Result<()> {
+        self.calibrate().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if self.autofocus {
+            match self.focus_distance(world) {
+                Some(distance) => {
+                    self.focus_dist = distance;
+                    eprintln!("autofocus: {:.3}", distance);
+                }
+                None => eprintln!("autofocus: center ray hits nothing, keeping focus_dist {}", self.focus_dist),
+            }
+        }
+        self.initialize().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// distance along the view axis to the first surface the center pixel sees
+    pub fn focus_distance(&self, world: &HittableList) -> Option<f64> {
+        let axis = (self.lookat - self.lookfrom).unit_vector();
+        let rec = world.hit(&Ray::new(self.lookfrom, axis), 0.001, INFINITY_F64)?;
+        Some(rec.t)
+    }
+
+    /// Replay sample `sample` of pixel (i, j) with this camera's seed and sampler, the
+    /// same sample a render takes, logging every bounce of its path.
+    pub fn trace_sample(&mut self, world: &HittableList, i: usize, j: usize, sample: usize) -> io::Result<PathTrace> {
+        self.prepare(world)?;
+        if i >= self.image_width || j >= self.image_height {
+            let message = format!("pixel ({}, {}) is outside the {}x{} image", i, j, self.image_width, self.image_height);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        let path = PathIntegrator::new(self.max_depth, self.rr_min_depth);
+        let integrator: &dyn Integrator = self.integrator.as_deref().unwrap_or(&path);
+        let mut sampler = self.sampler.build(self.seed, self.samples_per_pixel);
+        let mut record = SampleRecord { transparent: self.alpha.is_some(), want_bounces: true, alpha: 1.0, ..SampleRecord::default() };
+        sampler.start_pixel_sample(i, j, sample);
+        let camera_sample = self.get_ray(self.center, self.pixel00_loc, self.pixel_delta_u, self.pixel_delta_v, i, j, sampler.as_mut());
+        let radiance = match camera_sample.ray {
+            Some(r) => integrator.li(&r, world, sampler.as_mut(), &mut record) * (self.exposure * camera_sample.weight),
+            None => {
+                record.alpha = if camera_sample.covered { 1.0 } else { 0.0 };
+                Color::new(0.0, 0.0, 0.0)
+            }
+        };
+        Ok(PathTrace { pixel: (i, j), sample, seed: self.seed, radiance, alpha: record.alpha, bounces: record.bounces })
+    }
+
+    pub fn render_multithreaded(&mut self, world: &HittableList) -> io::Result<()> {
+        let start = Instant::now();
+        let (film, mut stats) = self.render_film(world)?;
+        stats.elapsed = start.elapsed();
+
+        film.write_ppm("image.ppm")?;
+        eprintln!("Wrote image.ppm ({}x{}) {:?}", self.image_width, self.image_height, start.elapsed());
+        self.write_aovs(world, &film, "image")?;
+        self.report_stats(world, &stats, "image")?;
+        self.finish_adaptive(&film.sample_counts())?;
+        Ok(())
+    }
+
+    /// all of `samples_per_pixel` in one multithreaded pass, into a new film, and the
+    /// pass's statistics
+    pub fn render_film(&mut self, world: &HittableList) -> io::Result<(Film, RenderStats)> {
+        self.prepare(world)?;
+        let mut film = self.new_film();
+        let start = Instant::now();
+        let mut stats = self.render_pass(world, &mut film, self.samples_per_pixel, true)?;
+        stats.elapsed = start.elapsed();
+        Ok((film, stats))
+    }
+
+    /// Render `frames` of `animation` as frame_0001.png, ... (plus AOVs and denoised
+    /// images named after the frame when enabled). `world` is loaded once and shared by
+    /// every frame; the camera pose one frame earlier drives the motion-vector AOV.
+    pub fn render_sequence(&mut self, world: &HittableList, animation: &Animation, frames: RangeInclusive<usize>) -> io::Result<()> {
+        let tracks = &animation.camera;
+        let refuse = |message: &str| Err(io::Error::new(io::ErrorKind::InvalidInput, message.to_string()));
+        if tracks.vfov.is_some() && (self.physical.is_some() || self.lens.is_some()) {
+            return refuse("a physical camera or lens sets the field of view; it can't follow a vfov track");
+        }
+        if self.calibration.is_some() && (tracks.lookfrom.is_some() || tracks.lookat.is_some() || tracks.vfov.is_some()) {
+            return refuse("a calibrated camera sets the pose and field of view; it can't follow pose or vfov tracks");
+        }
+        if self.autofocus && tracks.focus_dist.is_some() {
+            return refuse("autofocus sets the focus distance; it can't follow a focus_dist track");
+        }
+        let start = Instant::now();
+        let count = frames.clone().count();
+        for frame in frames {
+            let frame_start = Instant::now();
+            let (frame_world, film, stats) = self.render_frame(world, animation, frame as f64)?;
+            let stem = format!("frame_{:04}", frame);
+            // with a transparent film `write_aovs` writes the frame as RGBA instead
+            if self.alpha.is_none() {
+                film.write_png(&format!("{}.png", stem))?;
+                eprintln!("Wrote {}.png ({}x{}) {:?}", stem, film.width, film.height, frame_start.elapsed());
+            }
+            self.write_aovs(&frame_world, &film, &stem)?;
+            self.report_stats(&frame_world, &stats, &stem)?;
+        }
+        eprintln!("{} frames in {:?}", count, start.elapsed());
+        Ok(())
+    }
+
+    /// pose the camera for `frame` of `animation` and render that frame's scene
+    fn render_frame(&mut self, world: &HittableList, animation: &Animation, frame: f64) -> io::Result<(HittableList, Film, RenderStats)> {
+        self.previous_pose = Some(animation.camera.pose(self, frame - 1.0));
+        animation.camera.apply(self, frame);
+        let frame_world = animation.world_at(world, frame);
+        let (film, stats) = self.render_film(&frame_world)?;
+        Ok((frame_world, film, stats))
+    }
+
+    /// Render in passes of `settings.pass_samples` spp accumulated into a float film,
+    /// checkpointing as configured, until every pixel reaches `samples_per_pixel`.
+    pub fn render_progressive(&mut self, world: &HittableList, settings: &ProgressiveSettings) -> io::Result<Film> {
+        self.prepare(world)?;
+        let film = self.new_film();
+        self.continue_progressive(world, film, settings)
+    }
 
-            let file = File::create("image.ppm")?;
-            let mut out = BufWriter::new(file);
-            writeln!(out, "P3")?;
-            writeln!(out, "{} {}", self.image_width, self.image_height)?;
-            writeln!(out, "255")?;
+    /// Best image within `budget`: progressive passes of a few spp until the time is up
+    /// (`samples_per_pixel` still caps each pixel), then write image.ppm. The first pass
+    /// takes a single sample per pixel and always runs, so a budget shorter than that
+    /// one sample is overrun; it also times the samples, and a further pass only starts
+    /// if it should fit in what is left.
+    pub fn render_time_budget(&mut self, world: &HittableList, budget: Duration, multithreaded: bool) -> io::Result<Film> {
+        let settings = ProgressiveSettings {
+            pass_samples: 4,
+            checkpoint_interval: 0,
+            multithreaded,
+            time_budget: Some(budget),
+            ..ProgressiveSettings::default()
+        };
+        self.render_progressive(world, &settings)
+    }
 
-            // copy small hot values into locals so parallel closure captures cheap copies
-            let image_w = self.image_width;
-            let image_h = self.image_height;
-            let samples = self.samples_per_pixel;
-            let sample_scale = self.pixel_sample_scale;
-            let center = self.center;
-            let pixel00 = self.pixel00_loc;
-            let du = self.pixel_delta_u;
-            let dv = self.pixel_delta_v;
-            let max_depth = self.max_depth;
+    /// Continue the checkpoint at `settings.checkpoint_path` up to the current `samples_per_pixel`.
+    /// Fails with `InvalidData` if the scene or the camera no longer match the checkpoint.
+    pub fn resume_progressive(&mut self, world: &HittableList, settings: &ProgressiveSettings) -> io::Result<Film> {
+        self.prepare(world)?;
+        let path = settings.checkpoint_path.as_deref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "resume needs a checkpoint path"))?;
+        let checkpoint = Checkpoint::load(path)?;
 
+        if checkpoint.scene_hash != Self::scene_hash(world) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: scene changed since the checkpoint was written", path)));
+        }
+        if checkpoint.camera_hash != self.fingerprint() || checkpoint.seed != self.seed {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: camera changed since the checkpoint was written", path)));
+        }
+        if self.wants_aovs() && checkpoint.film.aovs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: checkpoint was written without AOVs", path)));
+        }
+        if self.cryptomatte.is_some() && checkpoint.film.mattes.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: checkpoint was written without ID mattes", path)));
+        }
+        eprintln!("Resuming {} at {:.1} spp", path, checkpoint.film.total_samples() as f64 / checkpoint.film.pixels.len().max(1) as f64);
+        let mut film = checkpoint.film;
+        if self.check_samples {
+            // samples taken before checking was turned on count as good
+            film.bad_samples.resize(film.pixels.len(), 0);
+            film.signal = self.signal_color;
+        }
+        self.continue_progressive(world, film, settings)
+    }
+
+    fn continue_progressive(&self, world: &HittableList, mut film: Film, settings: &ProgressiveSettings) -> io::Result<Film> {
+        let start = Instant::now();
+        let (pass, mut stats) = self.run_passes(world, &mut film, settings)?;
+        if !settings.checkpoint_due(pass) {
+            self.save_checkpoint(world, &film, settings)?;
+            film.write_ppm("image.ppm")?;
+        }
+        let elapsed = start.elapsed();
+        stats.elapsed = elapsed;
+        eprintln!("Wrote image.ppm ({}x{}) {:?}", film.width, film.height, elapsed);
+        let counts = film.sample_counts();
+        eprintln!(
+            "{} passes, spp min {} avg {:.1} max {}, {} rays, {:.2} Mrays/s",
+            pass,
+            counts.iter().min().copied().unwrap_or(0),
+            film.total_samples() as f64 / counts.len().max(1) as f64,
+            counts.iter().max().copied().unwrap_or(0),
+            stats.total().rays(),
+            stats.rays_per_second() / 1e6,
+        );
+        self.write_aovs(world, &film, "image")?;
+        self.report_stats(world, &stats, "image")?;
+        self.finish_adaptive(&counts)?;
+        Ok(film)
+    }
+
+    /// Progressive passes into `film` until it is done or the time budget runs out,
+    /// checkpointing as due; returns how many passes ran and what they traced.
+    fn run_passes(&self, world: &HittableList, film: &mut Film, settings: &ProgressiveSettings) -> io::Result<(usize, RenderStats)> {
+        let start = Instant::now();
+        let pass_samples = settings.pass_samples.max(1);
+        // under a budget the first pass is a one-sample probe of how long samples take
+        let mut samples = if settings.time_budget.is_some() { 1 } else { pass_samples };
+        let mut pass = 0;
+        let mut stats = RenderStats::default();
+        let mut sample_time = Duration::ZERO;
+        while film.pixels.iter().any(|p| self.pixel_active(p)) {
+            if settings.time_budget.is_some_and(|budget| pass > 0 && start.elapsed() + sample_time * samples as u32 > budget) {
+                break;
+            }
+            let pass_start = Instant::now();
+            stats.add(self.render_pass(world, film, samples, settings.multithreaded)?);
+            sample_time = pass_start.elapsed() / samples as u32;
+            samples = pass_samples;
+            pass += 1;
+            eprintln!("pass {}: {:.1} spp {:?}", pass, film.total_samples() as f64 / film.pixels.len() as f64, start.elapsed());
+            if settings.checkpoint_due(pass) {
+                self.save_checkpoint(world, film, settings)?;
+                film.write_ppm("image.ppm")?;
+            }
+        }
+        Ok((pass, stats))
+    }
 
+    fn wants_aovs(&self) -> bool {
+        self.aov_output.is_some() || self.denoise.is_some()
+    }
+
+    fn new_film(&self) -> Film {
+        let mut film = if self.wants_aovs() {
+            Film::with_aovs(self.image_width, self.image_height)
+        } else {
+            Film::new(self.image_width, self.image_height)
+        };
+        if self.filter.is_some() {
+            film.splats = vec![Splat::default(); film.pixels.len()];
+        }
+        film.passes = vec![vec![Splat::default(); film.pixels.len()]; self.light_passes.len()];
+        if self.cryptomatte.is_some() {
+            film.mattes = vec![MattePixel::default(); film.pixels.len()];
+        }
+        if self.check_samples {
+            film.bad_samples = vec![0; film.pixels.len()];
+            film.signal = self.signal_color;
+        }
+        film
+    }
+
+    /// RGBA images, AOVs, ID mattes and the denoised image, whichever are enabled,
+    /// named after `stem`; `world` names the objects and materials in the mattes
+    fn write_aovs(&self, world: &HittableList, film: &Film, stem: &str) -> io::Result<()> {
+        if let Some(mode) = self.alpha {
+            let path = format!("{}.png", stem);
+            film.write_png_rgba(&path, mode)?;
+            eprintln!("Wrote {}", path);
+            // the layered AOV file already carries the RGBA beauty
+            if self.aov_output != Some(AovOutput::MultiLayerExr) {
+                let path = format!("{}.exr", stem);
+                film.write_exr(&path, mode)?;
+                eprintln!("Wrote {}", path);
+            }
+        }
+        let output = self.aov_output.or((!self.light_passes.is_empty()).then_some(AovOutput::SeparateFiles));
+        if let Some(output) = output {
+            for path in write_aovs(film, stem, output, self.alpha.unwrap_or_default(), &self.light_passes)? {
+                eprintln!("Wrote {}", path);
+            }
+        }
+        if let Some(ranks) = self.cryptomatte {
+            let path = write_cryptomatte(film, world, stem, ranks, self.alpha.unwrap_or_default())?;
+            eprintln!("Wrote {}", path);
+        }
+        if let Some(settings) = &self.denoise {
             let start = Instant::now();
-            // compute each scanline in parallel (coarse-grain)
-            let rows: Vec<String> = (0..image_h)
-                .into_par_iter()
-                .map(|j| {
-                    let mut row_buf = String::with_capacity(image_w * 12);
-                    for i in 0..image_w {
-                        // per-pixel: do samples sequentially (avoids tiny rayon tasks)
-                        let sum = (0..samples).fold(Color::new(0.0, 0.0, 0.0), |acc, _| {
-                            let r = self.get_ray(center, pixel00, du, dv, i, j);
-                            acc + Self::ray_color(r, max_depth, world)
-                        });
-
-                        let pixel = sum * sample_scale;
-                        // convert to integer RGB components
-                        let ppm_string = pixel.to_ppm_string();
-                        row_buf.push_str(&ppm_string);
+            let denoised = denoise_film(film, settings);
+            let path = format!("{}.denoised.ppm", stem);
+            film::write_ppm(&path, film.width, film.height, denoised.into_iter())?;
+            eprintln!("Wrote {} {:?}", path, start.elapsed());
+        }
+        Ok(())
+    }
 
-                    }
-                    row_buf
-                })
-                .collect();
+    /// print and write `stats` as `print_stats` and `stats_json` ask, the JSON named
+    /// after `stem`, and the bad samples found when checking
+    fn report_stats(&self, world: &HittableList, stats: &RenderStats, stem: &str) -> io::Result<()> {
+        if self.print_stats || self.stats_json {
+            eprint!("{}", stats.summary());
+        }
+        if self.check_samples {
+            eprintln!("{}", stats.diagnostics().summary(world));
+        }
+        if self.stats_json {
+            let path = format!("{}.stats.json", stem);
+            stats.write_json(&path)?;
+            eprintln!("Wrote {}", path);
+        }
+        Ok(())
+    }
 
-            // write rows sequentially to avoid IO contention
-            for row in rows {
-                out.write_all(row.as_bytes())?;
+    fn save_checkpoint(&self, world: &HittableList, film: &Film, settings: &ProgressiveSettings) -> io::Result<()> {
+        let Some(path) = &settings.checkpoint_path else { return Ok(()) };
+        let checkpoint = Checkpoint { seed: self.seed, scene_hash: Self::scene_hash(world), camera_hash: self.fingerprint(), film: film.clone() };
+        checkpoint.save(path)?;
+        eprintln!("Checkpoint {}", path);
+        Ok(())
+    }
+
+    /// Add up to `pass_samples` more samples to every pixel that still needs them;
+    /// returns what each tile traced and how long it took.
+    fn render_pass(&self, world: &HittableList, film: &mut Film, pass_samples: usize, multithreaded: bool) -> io::Result<RenderStats> {
+        let end = |stats: &RunningStats| (stats.count + pass_samples).min(self.samples_per_pixel);
+        let film_ref = &*film;
+        // tiles only read the film and hand back their updates, which are applied in
+        // tile order, so the result does not depend on how the tiles were scheduled
+        let render_tile = |tile: &Tile| {
+            let start = Instant::now();
+            let mut counters = RayCounters::default();
+            let mut diagnostics = Diagnostics::default();
+            stats::take_intersection_tests();
+            let mut splats = self.filter.map(|f| SplatBuffer::for_tile(tile, &f, film_ref.width, film_ref.height));
+            let mut passes: Vec<SplatBuffer> = self.light_passes.iter().map(|_| match &self.filter {
+                Some(f) => SplatBuffer::for_tile(tile, f, film_ref.width, film_ref.height),
+                None => SplatBuffer::for_pixels(tile),
+            }).collect();
+            let pixels = tile.pixels().map(|(i, j)| {
+                let k = film_ref.index(i, j);
+                let mut stats = film_ref.pixels[k];
+                let mut aov = film_ref.aovs.get(k).copied();
+                let mut matte = film_ref.mattes.get(k).cloned();
+                let target = end(&stats);
+                let (pixel_counters, pixel_diagnostics) = self.sample_pixel(world, i, j, &mut stats, aov.as_mut(), matte.as_mut(), splats.as_mut(), &mut passes, target);
+                counters.add(&pixel_counters);
+                diagnostics.merge(&pixel_diagnostics);
+                (stats, aov, matte, pixel_diagnostics.bad_samples())
+            }).collect::<Vec<_>>();
+            counters.intersection_tests += stats::take_intersection_tests();
+            let thread = rayon::current_thread_index().unwrap_or(0);
+            let tile_stats = TileStats { tile: *tile, thread, time: start.elapsed(), counters, diagnostics };
+            (pixels, splats, passes, tile_stats)
+        };
+        let tiles = if multithreaded {
+            self.scheduler.run(film.width, film.height, render_tile)?
+        } else {
+            let mut results = Vec::new();
+            for tile in self.scheduler.tiles(film.width, film.height) {
+                if self.scheduler.cancel.is_cancelled() {
+                    return Err(io::Error::new(io::ErrorKind::Interrupted, "render cancelled"));
+                }
+                results.push((tile, render_tile(&tile)));
             }
+            results
+        };
 
-            out.flush()?;
-            eprintln!("Wrote image.ppm ({}x{}) {:?}", image_w, image_h, start.elapsed());
-            Ok(())
+        let mut stats = RenderStats::default();
+        for (tile, (results, splats, passes, tile_stats)) in tiles {
+            for ((i, j), (stats, aov, matte, bad)) in tile.pixels().zip(results) {
+                let k = film.index(i, j);
+                film.pixels[k] = stats;
+                if let Some(count) = film.bad_samples.get_mut(k) {
+                    *count += bad as u32;
+                }
+                if let Some(aov) = aov {
+                    film.aovs[k] = aov;
+                }
+                if let Some(matte) = matte {
+                    film.mattes[k] = matte;
+                }
+            }
+            if let Some(splats) = splats {
+                film.add_splats(&splats);
+            }
+            for (pass, buffer) in passes.iter().enumerate() {
+                film.add_pass_splats(pass, buffer);
+            }
+            stats.tiles.push(tile_stats);
         }
+        Ok(stats)
+    }
+
+    /// hash of everything about the camera that a resumed render must keep;
+    /// `samples_per_pixel` and the adaptive settings may change between runs
+    pub fn fingerprint(&self) -> u64 {
+        let mut fp = Fingerprint::new();
+        fp.write_u64(self.image_width as u64);
+        fp.write_f64(self.aspect_ratio);
+        let path = PathIntegrator::new(self.max_depth, self.rr_min_depth);
+        self.integrator.as_deref().unwrap_or(&path).fingerprint(&mut fp);
+        fp.write_str(&format!("{:?}", self.projection));
+        fp.write_f64(self.vfov);
+        fp.write_vec3(&self.lookfrom);
+        fp.write_vec3(&self.lookat);
+        fp.write_vec3(&self.vup);
+        fp.write_f64(self.defocus_angle);
+        self.aperture.fingerprint(&mut fp);
+        fp.write_str(&format!("{:?}", self.lens.as_ref().map(|lens| &lens.elements)));
+        fp.write_str(&format!("{:?}", self.calibration));
+        fp.write_f64(self.focus_dist);
+        fp.write_f64(self.exposure);
+        fp.write_u64(self.seed);
+        fp.write_str(&format!("{:?}", self.sampler));
+        if self.sampler == SamplerKind::Stratified {
+            // its strata are sized for the sample count, so raising it redraws every sample
+            fp.write_u64(self.samples_per_pixel as u64);
+        }
+        fp.write_str(&format!("{:?}", self.filter));
+        fp.write_u64(self.alpha.is_some() as u64);
+        for pass in &self.light_passes {
+            fp.write_str(&pass.name);
+            fp.write_str(&pass.expression);
+        }
+        fp.finish()
+    }
+
+    pub(crate) fn scene_hash(world: &HittableList) -> u64 {
+        let mut fp = Fingerprint::new();
+        world.fingerprint(&mut fp);
+        fp.finish()
+    }
 
 
     pub fn render(&mut self, world: &HittableList) -> io::Result<()> {
         let mut scanline_times: Vec<std::time::Duration> = Vec::with_capacity(self.image_height);
+        let mut render_stats = RenderStats::default();
 
-        self.initialize().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.prepare(world)?;
         // Open output file
         let file = File::create("image.ppm")?;
         let mut out = BufWriter::new(file);
@@ -210,24 +746,35 @@ impl Camera{
         // lock stderr once so we can overwrite the same line in-place
         let stderr = io::stderr();
         let mut err = stderr.lock();
+        let mut sample_counts = Vec::with_capacity(self.image_width * self.image_height);
 
         for j in 0..self.image_height {
-            // write carriage return so the next output overwrites the same line
-            // sleep(Duration::from_millis(1));
-            write!(err, "\rScanlines remaining: {:>3}", self.image_height - j)?;
-            err.flush()?;
+            if self.scheduler.cancel.is_cancelled() {
+                writeln!(err)?;
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "render cancelled"));
+            }
+            if self.scheduler.on_event.is_some() {
+                self.scheduler.emit(RenderEvent::Progress { percent: 100.0 * j as f64 / self.image_height as f64 });
+            } else {
+                // write carriage return so the next output overwrites the same line
+                // sleep(Duration::from_millis(1));
+                write!(err, "\rScanlines remaining: {:>3}", self.image_height - j)?;
+                err.flush()?;
+            }
             let start = Instant::now();
+            let mut counters = RayCounters::default();
+            let mut diagnostics = Diagnostics::default();
+            stats::take_intersection_tests();
             for i in 0..self.image_width {
 
-                let mut pixel_color = Color::new(0.0,0.0,0.0);
-
-                for sample in 0..self.samples_per_pixel {
-                    let r = self.get_ray(self.center, self.pixel00_loc, self.pixel_delta_u, self.pixel_delta_v, i, j);
-                    pixel_color += Self::ray_color(r, self.max_depth, &world);
+                let (mut pixel_color, count, pixel_counters, pixel_diagnostics) = self.pixel_color(world, i, j);
+                sample_counts.push(count);
+                counters.add(&pixel_counters);
+                diagnostics.merge(&pixel_diagnostics);
+                if let Some(signal) = self.signal_color.filter(|_| pixel_diagnostics.bad_samples() > 0) {
+                    pixel_color = signal;
                 }
 
-                pixel_color = pixel_color * self.pixel_sample_scale;
-
                 // let pixel_center = self.pixel00_loc + (i as f64 * self.pixel_delta_u) + (j as f64 * self.pixel_delta_v);
                 // let ray_direction = pixel_center - self.center;
 
@@ -242,7 +789,12 @@ impl Camera{
                 out.write_all(ppm_string.as_bytes())?;
             }
             scanline_times.push(start.elapsed());
+            // each scanline counts as a tile of the single thread
+            counters.intersection_tests += stats::take_intersection_tests();
+            let tile = Tile { index: j, x0: 0, y0: j, x1: self.image_width, y1: j + 1 };
+            render_stats.tiles.push(TileStats { tile, thread: 0, time: start.elapsed(), counters, diagnostics });
         }
+        self.scheduler.emit(RenderEvent::Progress { percent: 100.0 });
 
 
         out.flush()?;
@@ -258,69 +810,477 @@ impl Camera{
            std::time::Duration::ZERO
        };
        eprintln!("mrender: wrote image.ppm ({}x{}). total={:?} avg_per_scanline={:?}", self.image_width, self.image_height, total, avg);
+        render_stats.elapsed = total;
+        self.report_stats(world, &render_stats, "image")?;
+        self.finish_adaptive(&sample_counts)?;
         Ok(())
     }
 
-    fn get_ray(&self, center: Point3, pixel00: Point3, pixel_delta_u: Vec3, pixel_delta_v: Vec3,i: usize, j: usize) -> Ray {
-        let offset = Self::sample_square();
+    /// mean of the samples of pixel (i, j), how many were taken, what they traced and
+    /// which were bad
+    fn pixel_color(&self, world: &HittableList, i: usize, j: usize) -> (Color, usize, RayCounters, Diagnostics) {
+        let mut stats = RunningStats::default();
+        let (counters, diagnostics) = self.sample_pixel(world, i, j, &mut stats, None, None, None, &mut [], self.samples_per_pixel);
+        (stats.mean, stats.count, counters, diagnostics)
+    }
+
+    fn pixel_active(&self, stats: &RunningStats) -> bool {
+        stats.count < self.samples_per_pixel && !self.adaptive.as_ref().is_some_and(|a| a.converged(stats))
+    }
+
+    /// Take samples `stats.count..end` of pixel (i, j) and return the rays and paths
+    /// traced, and the bad samples when `check_samples` is set.
+    /// Each sample is seeded from (seed, pixel, sample index), so splitting a pixel
+    /// over passes changes nothing; adaptive sampling may stop early.
+    /// `aov`, when given, accumulates what each sample's camera ray hit first, and
+    /// `matte` the object and material ids it hit; `splats` receives every sample weighted by `self.filter`, and `passes` its
+    /// share of each of `self.light_passes`, weighted the same way.
+    #[allow(clippy::too_many_arguments)]
+    fn sample_pixel(&self, world: &HittableList, i: usize, j: usize, stats: &mut RunningStats, mut aov: Option<&mut AovPixel>, mut matte: Option<&mut MattePixel>, mut splats: Option<&mut SplatBuffer>, passes: &mut [SplatBuffer], end: usize) -> (RayCounters, Diagnostics) {
+        let path = PathIntegrator::new(self.max_depth, self.rr_min_depth);
+        let integrator: &dyn Integrator = self.integrator.as_deref().unwrap_or(&path);
+        let mut sampler = self.sampler.build(self.seed, self.samples_per_pixel);
+        let mut record = SampleRecord {
+            want_first_hit: aov.is_some() || matte.is_some(),
+            transparent: self.alpha.is_some(),
+            want_light_paths: !passes.is_empty(),
+            check: self.check_samples,
+            ..SampleRecord::default()
+        };
+        let mut diagnostics = Diagnostics::default();
+        let mut pass_values = vec![Color::new(0.0, 0.0, 0.0); passes.len()];
+        while stats.count < end && self.pixel_active(stats) {
+            sampler.start_pixel_sample(i, j, stats.count);
+            let sample = self.get_ray(self.center, self.pixel00_loc, self.pixel_delta_u, self.pixel_delta_v, i, j, sampler.as_mut());
+            let offset = sample.offset;
+            record.first_hit = None;
+            record.alpha = 1.0;
+            record.light_paths.clear();
+            record.finding = None;
+            // vignetted samples are black; film positions outside a fisheye's image
+            // circle are also transparent
+            let color = match sample.ray {
+                Some(r) => integrator.li(&r, world, sampler.as_mut(), &mut record) * (self.exposure * sample.weight),
+                None => {
+                    record.alpha = if sample.covered { 1.0 } else { 0.0 };
+                    Color::new(0.0, 0.0, 0.0)
+                }
+            };
+            if self.check_samples {
+                let finding = record.finding.or_else(|| {
+                    Problem::find(color.0, false).map(|problem| Finding { bounce: None, what: "radiance", problem, material: None })
+                });
+                if let Some(finding) = finding {
+                    diagnostics.add(BadSample { pixel: (i, j), sample: stats.count, finding });
+                }
+            }
+            stats.add(color, record.alpha);
+            if let (Some(splats), Some(filter)) = (splats.as_deref_mut(), &self.filter) {
+                splats.add(filter, i as f64 + 0.5 + offset.x, j as f64 + 0.5 + offset.y, color, record.alpha);
+            }
+            if !passes.is_empty() {
+                pass_values.fill(Color::new(0.0, 0.0, 0.0));
+                for (path, contribution) in &record.light_paths {
+                    lpe::accumulate(&self.light_passes, &mut pass_values, path, *contribution * (self.exposure * sample.weight));
+                }
+                for (buffer, &value) in passes.iter_mut().zip(&pass_values) {
+                    match &self.filter {
+                        Some(filter) => buffer.add(filter, i as f64 + 0.5 + offset.x, j as f64 + 0.5 + offset.y, value, 0.0),
+                        None => buffer.add_to_pixel(i, j, value, 0.0),
+                    }
+                }
+            }
+            if let Some(aov) = aov.as_deref_mut() {
+                if let (Some(hit), Some((from, at))) = (record.first_hit.as_mut(), self.previous_pose) {
+                    let now = self.raster_position(hit.position, self.lookfrom, self.lookat);
+                    let before = self.raster_position(hit.position, from, at);
+                    if let (Some(now), Some(before)) = (now, before) {
+                        hit.motion = (now.0 - before.0, now.1 - before.1);
+                    }
+                }
+                aov.add(record.first_hit.as_ref());
+            }
+            if let Some(matte) = matte.as_deref_mut() {
+                matte.add(record.first_hit.as_ref().map(|hit| (hit.object_id, hit.material_id)));
+            }
+        }
+        (record.counters, diagnostics)
+    }
+
+    /// raster coordinates (pixels) at which `p` appears for a camera at `from` looking at
+    /// `at`, with this camera's lens and resolution; None if `p` is not in view
+    fn raster_position(&self, p: Point3, from: Point3, at: Point3) -> Option<(f64, f64)> {
+        let w = (from - at).unit_vector();
+        let u = self.vup.cross(&w).unit_vector();
+        let v = w.cross(&u);
+        let d = p - from;
+        let z = -d.dot(&w);
+        let corner = self.pixel00_loc - self.center;
+        if let Some(calibration) = &self.calibration {
+            // calibration pixel coordinates put pixel centers on integers
+            let (x, y) = calibration.project(Vec3::new(d.dot(&u), -d.dot(&v), z))?;
+            return Some((x + 0.5, y + 0.5));
+        }
+        match self.projection {
+            Projection::Perspective => {}
+            Projection::Orthographic => {
+                let x = (d.dot(&u) - corner.dot(&self.u)) / self.pixel_delta_u.length() + 0.5;
+                let y = (corner.dot(&self.v) - d.dot(&v)) / self.pixel_delta_v.length() + 0.5;
+                return Some((x, y));
+            }
+            projection => {
+                let aspect = self.image_width as f64 / self.image_height as f64;
+                let (px, py) = projection.film_position(Vec3::new(d.dot(&u), d.dot(&v), d.dot(&w)), aspect)?;
+                return Some((px * self.image_width as f64, py * self.image_height as f64));
+            }
+        }
+        if z <= 0.0 {
+            return None;
+        }
+        // pixel (0, 0) sits at the same offset from the view axis in either pose
+        let scale = self.focus_dist / z;
+        let x = (d.dot(&u) * scale - corner.dot(&self.u)) / self.pixel_delta_u.length() + 0.5;
+        let y = (corner.dot(&self.v) - d.dot(&v) * scale) / self.pixel_delta_v.length() + 0.5;
+        Some((x, y))
+    }
+
+    /// report the achieved sample rate and write the heatmap when adaptive sampling is on
+    fn finish_adaptive(&self, sample_counts: &[usize]) -> io::Result<()> {
+        let Some(adaptive) = &self.adaptive else { return Ok(()) };
+        let total: usize = sample_counts.iter().sum();
+        eprintln!("adaptive: {:.1} spp on average (max {})", total as f64 / sample_counts.len().max(1) as f64, self.samples_per_pixel);
+        if let Some(path) = &adaptive.heatmap_path {
+            write_sample_heatmap(path, self.image_width, self.image_height, sample_counts, self.samples_per_pixel)?;
+            eprintln!("Wrote {}", path);
+        }
+        Ok(())
+    }
+
+    /// camera ray through a jittered point of pixel (i, j); see `CameraSample`
+    #[allow(clippy::too_many_arguments)]
+    fn get_ray(&self, center: Point3, pixel00: Point3, pixel_delta_u: Vec3, pixel_delta_v: Vec3, i: usize, j: usize, sampler: &mut dyn Sampler) -> CameraSample {
+        let offset = Self::sample_square(sampler);
+        let sample = |ray: Option<Ray>| CameraSample { ray, weight: 1.0, offset, covered: true };
+        let vignetted = CameraSample { ray: None, weight: 1.0, offset, covered: true };
 
         let pixel_sample = pixel00 + ((i as f64 + offset.x) * pixel_delta_u) + ((j as f64 + offset.y) * pixel_delta_v);
+        match self.projection {
+            Projection::Perspective => {}
+            Projection::Orthographic => {
+                // the pixel's point on the focus plane, moved back onto the lens plane
+                let origin = pixel_sample + self.focus_dist * self.w;
+                return sample(Some(Ray::new(origin, -self.w)));
+            }
+            projection => {
+                let px = (i as f64 + 0.5 + offset.x) / self.image_width as f64;
+                let py = (j as f64 + 0.5 + offset.y) / self.image_height as f64;
+                let aspect = self.image_width as f64 / self.image_height as f64;
+                let ray = projection.direction(px, py, aspect)
+                    .map(|d| Ray::new(center, d.x * self.u + d.y * self.v + d.z * self.w));
+                return CameraSample { covered: ray.is_some(), ..sample(ray) };
+            }
+        }
+
+        if let Some(calibration) = &self.calibration {
+            let ray = calibration.ray(i as f64 + offset.x, j as f64 + offset.y);
+            return CameraSample { covered: ray.is_some(), ..sample(ray) };
+        }
+
+        if let Some(lens) = &self.lens {
+            let sx = (i as f64 + 0.5 + offset.x) / self.image_width as f64 - 0.5;
+            let sy = 0.5 - (j as f64 + 0.5 + offset.y) / self.image_height as f64;
+            let (u, v) = sampler.get_2d();
+            let Some((o, d, weight)) = lens.sample_ray(sx, sy, u, v) else { return vignetted };
+            let units_per_mm = 0.001 / self.physical.as_ref().map_or(1.0, |p| p.meters_per_unit);
+            let origin = center + units_per_mm * (o.x * self.u + o.y * self.v + o.z * self.w);
+            let ray = Ray::new(origin, d.x * self.u + d.y * self.v + d.z * self.w);
+            return CameraSample { ray: Some(ray), weight, offset, covered: true };
+        }
 
         // let ray_origin = center;
         let ray_origin= match self.defocus_angle {
             x if x <= 0.0 => center,
-            _ => self.defocus_disk_sample(),
+            _ => {
+                // pixel position relative to the image center, 1 at the corners
+                let (x, y) = (i as f64 + 0.5 + offset.x - self.image_width as f64 / 2.0, self.image_height as f64 / 2.0 - (j as f64 + 0.5 + offset.y));
+                let half_diagonal = (self.image_width as f64).hypot(self.image_height as f64) / 2.0;
+                match self.defocus_disk_sample(sampler, (x / half_diagonal, y / half_diagonal)) {
+                    Some(origin) => origin,
+                    None => return vignetted,
+                }
+            }
         };
 
         let ray_direction = pixel_sample - ray_origin;
 
-        Ray::new(ray_origin, ray_direction)
+        sample(Some(Ray::new(ray_origin, ray_direction)))
 
     }
 
-    fn sample_square() -> Vec3 {
-        Vec3::new(random_double() - 0.5, random_double() - 0.5, 0.0)
+    fn sample_square(sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        Vec3::new(u - 0.5, v - 0.5, 0.0)
     }
 
-    fn defocus_disk_sample(&self) -> Vec3 {
-        let p = Vec3::random_in_unit_disk();
-        return self.center + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v);
+    /// point on the lens for a pixel at `film`; None where the barrel vignettes it
+    fn defocus_disk_sample(&self, sampler: &mut dyn Sampler, film: (f64, f64)) -> Option<Vec3> {
+        let (u, v) = sampler.get_2d();
+        let (x, y) = self.aperture.sample(u, v, film)?;
+        Some(self.center + (x * self.defocus_disk_u) + (y * self.defocus_disk_v))
     }
 
-    fn ray_color(r: Ray, depth: usize, world: &HittableList) -> Color {
-
-        if depth == 0 { return Color::new(0.0,0.0,0.0)}
+}
 
-        // if let Some(rec) = world.hit(&r, 0.001, INFINITY_F64) {
-        //     // shade by normal (rec.normal is a Vec3)
-        //     // let shaded = 0.5 * (rec.normal + Vec3::new(1.0, 1.0, 1.0));
-        //     // return Color::from(shaded);
+/// What `Camera::get_ray` makes of one film sample.
+struct CameraSample {
+    /// None if the projection leaves that point of the film empty or the aperture or
+    /// lens vignettes it
+    ray: Option<Ray>,
+    /// false only where the projection leaves the film empty, which is transparent; a
+    /// vignetted sample is covered but black
+    covered: bool,
+    /// factor on the radiance the ray brings back; below 1 where a lens vignettes
+    weight: f64,
+    /// jitter from the pixel center
+    offset: Vec3,
+}
 
-        //     let direction = rec.normal + Vec3::random_on_hemisphere(&rec.normal);
-        //     return Camera::ray_color(Ray::new(rec.p, direction), depth - 1, &world) * 0.1
-        // }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{CameraTracks, Interpolation, ObjectTracks, Track};
+    use crate::ray::RayKind;
+    use crate::material::Lambertian;
+    use crate::rtweekend::Shared;
+    use crate::sphere::Sphere;
+    use rayon::prelude::*;
+
+    #[test]
+    fn same_seed_same_image_on_any_thread_count() {
+        let mut world = HittableList::new();
+        let mat = Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.push(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, mat.clone()));
+        world.push(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, mat));
+
+        let mut cam = Camera::new_with(16, 2.0, 4, 5, 90.0);
+        cam.lookat = Point3::new(0.0, 0.0, -1.0);
+        cam.vup = Vec3::new(0.0, 1.0, 0.0);
+        cam.focus_dist = 1.0;
+        cam.defocus_angle = 2.0;
+        cam.seed = 1234;
+        cam.sampler = SamplerKind::Sobol;
+        cam.initialize().unwrap();
+
+        let serial: Vec<Color> = (0..cam.image_height)
+            .flat_map(|j| (0..cam.image_width).map(move |i| (i, j)))
+            .map(|(i, j)| cam.pixel_color(&world, i, j).0)
+            .collect();
+        let parallel: Vec<Color> = (0..cam.image_height * cam.image_width)
+            .into_par_iter()
+            .map(|k| cam.pixel_color(&world, k % cam.image_width, k / cam.image_width).0)
+            .collect();
+        assert_eq!(serial, parallel);
+
+        let mut stats = RunningStats::default();
+        cam.sample_pixel(&world, 3, 2, &mut stats, None, None, None, &mut [], 1);
+        cam.sample_pixel(&world, 3, 2, &mut stats, None, None, None, &mut [], 4);
+        assert_eq!(stats.mean, cam.pixel_color(&world, 3, 2).0);
+    }
 
-        if let Some(rec) = world.hit(&r, 0.001, INFINITY_F64) {
-            // Try to scatter via the material on the hit record.
-            // `scatter` should return Some((attenuation_color, scattered_ray)) or None.
-            if let Some((attenuation, scattered)) = rec.mat.scatter(&r, &rec) {
-                let scattered_color = Self::ray_color(scattered, depth - 1, world);
-                // component-wise multiply attenuation * scattered_color
-                // return Color::new(
-                //     attenuation.0.x * scattered_color.0.x,
-                //     attenuation.0.y * scattered_color.0.y,
-                //     attenuation.0.z * scattered_color.0.z,
-                // );
-                return attenuation * scattered_color;
-            }
-            // material absorbed the ray
-            return Color::new(0.0, 0.0, 0.0);
+    #[test]
+    fn resumed_render_matches_an_uninterrupted_one() {
+        let mut world = HittableList::new();
+        let mat = Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.push(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, mat.clone()));
+        world.push(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, mat));
+        let mut cam = Camera::new_with(12, 2.0, 3, 10, 90.0);
+        cam.lookat = Point3::new(0.0, 0.0, -1.0);
+        cam.vup = Vec3::new(0.0, 1.0, 0.0);
+        cam.focus_dist = 1.0;
+        cam.seed = 7;
+        cam.aov_output = Some(AovOutput::MultiLayerExr);
+        cam.prepare(&world).unwrap();
+
+        // three samples, through a checkpoint, then raised to eight
+        let mut film = cam.new_film();
+        cam.render_pass(&world, &mut film, 2, false).unwrap();
+        cam.render_pass(&world, &mut film, 2, false).unwrap();
+        let path = std::env::temp_dir().join(format!("raytrace_rs_resume_{}.ckpt", std::process::id())).to_string_lossy().into_owned();
+        Checkpoint { seed: cam.seed, scene_hash: Camera::scene_hash(&world), camera_hash: cam.fingerprint(), film }.save(&path).unwrap();
+        cam.samples_per_pixel = 8;
+        let mut resumed = Checkpoint::load(&path).unwrap().film;
+        std::fs::remove_file(&path).unwrap();
+        while resumed.pixels.iter().any(|p| cam.pixel_active(p)) {
+            cam.render_pass(&world, &mut resumed, 3, false).unwrap();
         }
 
-        let unit_direction: Vec3 = r.direction.unit_vector();
-        let a: f64 = 0.5 * (unit_direction.y + 1.0);
-        let c: Vec3 = (1.0 - a) * Vec3::new(1.0, 1.0, 1.0)
-            + a * Vec3::new(0.5, 0.7, 1.0);
-        Color::from(c)
+        let mut uninterrupted = cam.new_film();
+        cam.render_pass(&world, &mut uninterrupted, 8, false).unwrap();
+        assert_eq!(resumed, uninterrupted);
+    }
+
+    #[test]
+    fn time_budget_stops_after_the_probe_pass_when_spent() {
+        let mut world = HittableList::new();
+        world.push(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        let mut cam = Camera::new_with(8, 2.0, 64, 10, 90.0);
+        cam.lookat = Point3::new(0.0, 0.0, -1.0);
+        cam.vup = Vec3::new(0.0, 1.0, 0.0);
+        cam.focus_dist = 1.0;
+        cam.prepare(&world).unwrap();
+        let budget = |budget| ProgressiveSettings { pass_samples: 4, checkpoint_interval: 0, multithreaded: false, time_budget: Some(budget), ..ProgressiveSettings::default() };
+
+        let mut film = cam.new_film();
+        let (passes, _) = cam.run_passes(&world, &mut film, &budget(Duration::from_nanos(1))).unwrap();
+        assert_eq!(passes, 1);
+        assert!(film.sample_counts().iter().all(|&n| n == 1));
+
+        let mut film = cam.new_film();
+        let (passes, _) = cam.run_passes(&world, &mut film, &budget(Duration::from_secs(600))).unwrap();
+        assert_eq!(passes, 1 + 63_usize.div_ceil(4));
+        assert!(film.sample_counts().iter().all(|&n| n == 64));
+    }
+
+    #[test]
+    fn vignetting_darkens_without_making_the_film_transparent() {
+        // a wall of sphere fills the view, so every unvignetted sample is opaque
+        let mut world = HittableList::new();
+        world.push(Sphere::new(Point3::new(0.0, 0.0, -101.0), 100.0, Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        let mut cam = Camera::new_with(12, 1.5, 16, 3, 90.0);
+        cam.lookat = Point3::new(0.0, 0.0, -1.0);
+        cam.vup = Vec3::new(0.0, 1.0, 0.0);
+        cam.focus_dist = 1.0;
+        cam.defocus_angle = 5.0;
+        cam.aperture.cat_eye = 1.5;
+        cam.alpha = Some(AlphaMode::Premultiplied);
+
+        let (film, _) = cam.render_film(&world).unwrap();
+        assert!(film.alphas().iter().all(|&a| a == 1.0));
+        assert!(film.color(0, 0).luminance() < 0.5 * film.color(6, 4).luminance());
+    }
+
+    #[test]
+    fn traced_sample_matches_the_rendered_one() {
+        let mut world = HittableList::new();
+        let mat = Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.push(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, mat.clone()));
+        world.push(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, mat));
+        let mut cam = Camera::new_with(16, 2.0, 8, 10, 90.0);
+        cam.lookat = Point3::new(0.0, 0.0, -1.0);
+        cam.vup = Vec3::new(0.0, 1.0, 0.0);
+        cam.focus_dist = 1.0;
+        cam.seed = 99;
+
+        let trace = cam.trace_sample(&world, 8, 5, 3).unwrap();
+        // a pixel resumed at sample 3 takes exactly that sample next
+        let mut stats = RunningStats { count: 3, ..RunningStats::default() };
+        cam.sample_pixel(&world, 8, 5, &mut stats, None, None, None, &mut [], 4);
+        assert!((stats.mean * 4.0 - trace.radiance).0.length() < 1e-9);
+
+        let first = trace.bounces[0];
+        assert_eq!((first.depth, first.kind), (0, RayKind::Camera));
+        assert_eq!(first.hit.map(|hit| hit.object_id), Some(1));
+        assert!(trace.bounces.last().unwrap().end.is_some());
+        assert!(trace.json(&world).contains("\"segments\""));
+    }
+
+    #[test]
+    fn zero_vup_shows_up_as_nan_ray_directions() {
+        let mut world = HittableList::new();
+        world.push(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        let mut cam = Camera::new_with(8, 2.0, 2, 5, 90.0);
+        cam.lookat = Point3::new(0.0, 0.0, -1.0);
+        cam.focus_dist = 1.0;
+        cam.check_samples = true;
+        cam.signal_color = Some(Color::new(1.0, 0.0, 1.0));
+
+        let (film, stats) = cam.render_film(&world).unwrap();
+        let diagnostics = stats.diagnostics();
+        assert_eq!(diagnostics.nan, film.total_samples() as u64);
+        let first = diagnostics.first.unwrap();
+        assert_eq!((first.pixel, first.sample), ((0, 0), 0));
+        assert_eq!((first.finding.bounce, first.finding.what), (Some(0), "ray direction"));
+        assert_eq!(film.color(3, 1), Color::new(1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn pixel_centers_project_back_to_their_raster_position() {
+        let mut cam = Camera::new_with(32, 2.0, 1, 5, 60.0);
+        cam.lookfrom = Point3::new(1.0, 2.0, 3.0);
+        cam.lookat = Point3::new(0.0, 0.0, -1.0);
+        cam.vup = Vec3::new(0.0, 1.0, 0.0);
+        cam.focus_dist = 2.5;
+        cam.initialize().unwrap();
+
+        let p = cam.pixel00_loc + 7.0 * cam.pixel_delta_u + 5.0 * cam.pixel_delta_v;
+        let (x, y) = cam.raster_position(p, cam.lookfrom, cam.lookat).unwrap();
+        assert!((x - 7.5).abs() < 1e-9 && (y - 5.5).abs() < 1e-9, "{} {}", x, y);
+        assert!(cam.raster_position(cam.lookfrom + (cam.lookfrom - cam.lookat), cam.lookfrom, cam.lookat).is_none());
+    }
+
+    #[test]
+    fn autofocus_looks_from_the_calibrated_pose() {
+        let mut world = HittableList::new();
+        let mat = Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.push(Sphere::new(Point3::new(0.0, 0.0, 5.0), 1.0, mat));
+
+        // the user's pose looks away from the sphere; the calibrated one looks down +z
+        let mut cam = Camera::new_with(8, 1.0, 1, 5, 90.0);
+        cam.lookfrom = Point3::new(10.0, 0.0, 0.0);
+        cam.lookat = Point3::new(20.0, 0.0, 0.0);
+        cam.autofocus = true;
+        cam.calibration = Some(CalibratedCamera::new(16, 12, 20.0, 20.0, 7.5, 5.5));
+        cam.prepare(&world).unwrap();
+        assert!((cam.focus_dist - 4.0).abs() < 1e-9, "{}", cam.focus_dist);
+        assert_eq!((cam.image_width, cam.image_height), (16, 12));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sequence_frames_follow_the_camera_tracks() {
+        let mut world = HittableList::new();
+        let mat = Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.push(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, mat.clone()));
+        world.push(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, mat));
+        let lookfrom = Track::from_values(Interpolation::Linear, &[(0.0, Point3::zero()), (10.0, Point3::new(1.0, 0.0, 0.0))]);
+        let translation = Track::from_values(Interpolation::Linear, &[(0.0, Vec3::zero()), (10.0, Vec3::new(0.0, 1.0, 0.0))]);
+        let animation = Animation {
+            camera: CameraTracks { lookfrom: Some(lookfrom), ..CameraTracks::default() },
+            objects: vec![ObjectTracks { translation: Some(translation), ..ObjectTracks::default() }],
+        };
+
+        let new_camera = || {
+            let mut cam = Camera::new_with(8, 2.0, 2, 5, 90.0);
+            cam.lookat = Point3::new(0.0, 0.0, -1.0);
+            cam.seed = 7;
+            cam
+        };
+        let (mut cam, mut posed) = (new_camera(), new_camera());
+        let (_, film, _) = cam.render_frame(&world, &animation, 5.0).unwrap();
+        assert_eq!(cam.lookfrom, Point3::new(0.5, 0.0, 0.0));
+        assert_eq!(cam.previous_pose, Some((Point3::new(0.4, 0.0, 0.0), cam.lookat)));
+
+        posed.lookfrom = Point3::new(0.5, 0.0, 0.0);
+        let (expected, _) = posed.render_film(&animation.world_at(&world, 5.0)).unwrap();
+        assert_eq!(film.colors(), expected.colors());
+
+        // the physical camera would overwrite an animated vfov every frame
+        let zoom = Animation {
+            camera: CameraTracks { vfov: Some(Track::from_values(Interpolation::Linear, &[(0.0, 40.0), (10.0, 20.0)])), ..CameraTracks::default() },
+            ..Animation::default()
+        };
+        cam.physical = Some(PhysicalCamera::default());
+        assert_eq!(cam.render_sequence(&world, &zoom, 1..=2).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        // and so would a calibration the pose, and autofocus the focus distance
+        let mut calibrated = new_camera();
+        calibrated.calibration = Some(CalibratedCamera::new(16, 12, 20.0, 20.0, 7.5, 5.5));
+        assert_eq!(calibrated.render_sequence(&world, &animation, 1..=2).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let pull = Animation {
+            camera: CameraTracks { focus_dist: Some(Track::from_values(Interpolation::Linear, &[(0.0, 1.0), (10.0, 3.0)])), ..CameraTracks::default() },
+            ..Animation::default()
+        };
+        let mut autofocus = new_camera();
+        autofocus.autofocus = true;
+        assert_eq!(autofocus.render_sequence(&world, &pull, 1..=2).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
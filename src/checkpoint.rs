@@ -0,0 +1,329 @@
+//! Binary checkpoints of a progressive render.
+//!
+//! Random numbers are derived from (seed, pixel, sample index), so the seed plus
+//! the per-pixel sample counts stored in the film *are* the RNG state: a resumed
+//! render draws exactly the samples an uninterrupted one would have. Resuming may
+//! raise `samples_per_pixel`, except with the stratified sampler, whose strata
+//! depend on it; its sample count is part of the camera fingerprint.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write, BufReader, BufWriter};
+
+use crate::adaptive::RunningStats;
+use crate::aov::AovPixel;
+use crate::cryptomatte::MattePixel;
+use crate::color::Color;
+use crate::vec3::Vec3;
+use crate::film::Film;
+use crate::filter::Splat;
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub seed: u64,
+    pub scene_hash: u64,
+    pub camera_hash: u64,
+    pub film: Film,
+}
+
+impl Checkpoint {
+    /// write to `<path>.tmp` first and rename, so a killed render never leaves a torn file
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let tmp = format!("{}.tmp", path);
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        let has_aovs = !self.film.aovs.is_empty() as u64;
+        let has_splats = !self.film.splats.is_empty() as u64;
+        let pass_count = self.film.passes.len() as u64;
+        let has_mattes = !self.film.mattes.is_empty() as u64;
+        let has_bad_samples = !self.film.bad_samples.is_empty() as u64;
+        let header = [
+            self.seed, self.scene_hash, self.camera_hash, self.film.width as u64, self.film.height as u64,
+            has_aovs, has_splats, pass_count, has_mattes, has_bad_samples,
+        ];
+        for v in header {
+            out.write_all(&v.to_le_bytes())?;
+        }
+        for p in &self.film.pixels {
+            out.write_all(&(p.count as u64).to_le_bytes())?;
+            for v in [p.mean.r(), p.mean.g(), p.mean.b(), p.mean_alpha, p.mean_luminance, p.m2] {
+                out.write_all(&v.to_le_bytes())?;
+            }
+        }
+        for a in &self.film.aovs {
+            for v in [a.samples, a.hits, a.object_id, a.material_id] {
+                out.write_all(&v.to_le_bytes())?;
+            }
+            let values = [
+                a.depth,
+                a.position.x, a.position.y, a.position.z,
+                a.normal.x, a.normal.y, a.normal.z,
+                a.albedo.r(), a.albedo.g(), a.albedo.b(),
+                a.uv.0, a.uv.1,
+                a.motion.0, a.motion.1,
+            ];
+            for v in values {
+                out.write_all(&v.to_le_bytes())?;
+            }
+        }
+        for s in self.film.splats.iter().chain(self.film.passes.iter().flatten()) {
+            for v in [s.sum.r(), s.sum.g(), s.sum.b(), s.alpha, s.weight, s.abs_weight] {
+                out.write_all(&v.to_le_bytes())?;
+            }
+        }
+        // matte entries vary in number: samples, then each list as a length and (id, count) pairs
+        for m in &self.film.mattes {
+            out.write_all(&m.samples.to_le_bytes())?;
+            for entries in [&m.objects, &m.materials] {
+                out.write_all(&(entries.len() as u32).to_le_bytes())?;
+                for &(id, count) in entries {
+                    out.write_all(&id.to_le_bytes())?;
+                    out.write_all(&count.to_le_bytes())?;
+                }
+            }
+        }
+        for n in &self.film.bad_samples {
+            out.write_all(&n.to_le_bytes())?;
+        }
+        out.flush()?;
+        drop(out);
+        fs::rename(tmp, path)
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut input = BufReader::new(file);
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a checkpoint", path)));
+        }
+        let mut word = [0u8; 4];
+        input.read_exact(&mut word)?;
+        let version = u32::from_le_bytes(word);
+        if version != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported checkpoint version {}", version)));
+        }
+
+        let seed = read_u64(&mut input)?;
+        let scene_hash = read_u64(&mut input)?;
+        let camera_hash = read_u64(&mut input)?;
+        let width = read_u64(&mut input)? as usize;
+        let height = read_u64(&mut input)? as usize;
+        let has_aovs = read_u64(&mut input)? != 0;
+        let has_splats = read_u64(&mut input)? != 0;
+        let pass_count = read_u64(&mut input)? as usize;
+        let has_mattes = read_u64(&mut input)? != 0;
+        let has_bad_samples = read_u64(&mut input)? != 0;
+        // a torn or corrupt header mustn't make us allocate more than the file can hold
+        let splat_buffers = has_splats as u64 + pass_count as u64;
+        let needed = body_len(width as u64, height as u64, has_aovs, splat_buffers, has_mattes, has_bad_samples);
+        if needed.is_none_or(|needed| needed > file_len.saturating_sub(HEADER_LEN)) {
+            let message = format!("{} is truncated or corrupt ({}x{} film in {} bytes)", path, width, height, file_len);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+
+        let mut film = if has_aovs { Film::with_aovs(width, height) } else { Film::new(width, height) };
+        for p in film.pixels.iter_mut() {
+            let count = read_u64(&mut input)? as usize;
+            let (r, g, b) = (read_f64(&mut input)?, read_f64(&mut input)?, read_f64(&mut input)?);
+            let mean_alpha = read_f64(&mut input)?;
+            let mean_luminance = read_f64(&mut input)?;
+            let m2 = read_f64(&mut input)?;
+            *p = RunningStats { count, mean: Color::new(r, g, b), mean_alpha, mean_luminance, m2 };
+        }
+        for a in film.aovs.iter_mut() {
+            let (samples, hits) = (read_u32(&mut input)?, read_u32(&mut input)?);
+            let (object_id, material_id) = (read_u32(&mut input)?, read_u32(&mut input)?);
+            let mut v = [0.0; 14];
+            for x in v.iter_mut() {
+                *x = read_f64(&mut input)?;
+            }
+            *a = AovPixel {
+                samples,
+                hits,
+                depth: v[0],
+                position: Vec3::new(v[1], v[2], v[3]),
+                normal: Vec3::new(v[4], v[5], v[6]),
+                albedo: Color::new(v[7], v[8], v[9]),
+                uv: (v[10], v[11]),
+                motion: (v[12], v[13]),
+                object_id,
+                material_id,
+            };
+        }
+        if has_splats {
+            film.splats = vec![Splat::default(); width * height];
+        }
+        film.passes = vec![vec![Splat::default(); width * height]; pass_count];
+        for s in film.splats.iter_mut().chain(film.passes.iter_mut().flatten()) {
+            let (r, g, b) = (read_f64(&mut input)?, read_f64(&mut input)?, read_f64(&mut input)?);
+            let (alpha, weight) = (read_f64(&mut input)?, read_f64(&mut input)?);
+            *s = Splat { sum: Color::new(r, g, b), alpha, weight, abs_weight: read_f64(&mut input)? };
+        }
+        if has_mattes {
+            film.mattes = vec![MattePixel::default(); width * height];
+        }
+        for m in film.mattes.iter_mut() {
+            m.samples = read_u32(&mut input)?;
+            for entries in [&mut m.objects, &mut m.materials] {
+                let len = read_u32(&mut input)?;
+                for _ in 0..len {
+                    entries.push((read_u32(&mut input)?, read_u32(&mut input)?));
+                }
+            }
+        }
+        if has_bad_samples {
+            film.bad_samples = vec![0; width * height];
+        }
+        for n in film.bad_samples.iter_mut() {
+            *n = read_u32(&mut input)?;
+        }
+        Ok(Self { seed, scene_hash, camera_hash, film })
+    }
+}
+
+/// bytes before the film: magic, version and ten u64 header fields
+const HEADER_LEN: u64 = 4 + 4 + 10 * 8;
+
+/// Smallest body a film with this header can have (mattes with no entries), or None
+/// if it doesn't fit in a u64.
+fn body_len(width: u64, height: u64, has_aovs: bool, splat_buffers: u64, has_mattes: bool, has_bad_samples: bool) -> Option<u64> {
+    let per_pixel = 7 * 8
+        + if has_aovs { 4 * 4 + 14 * 8 } else { 0 }
+        + if has_mattes { 3 * 4 } else { 0 }
+        + if has_bad_samples { 4 } else { 0 };
+    let per_pixel = splat_buffers.checked_mul(6 * 8)?.checked_add(per_pixel)?;
+    width.checked_mul(height)?.checked_mul(per_pixel)
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    read_u64(input).map(f64::from_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("raytrace_rs_{}_{}.ckpt", name, std::process::id())).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn save_and_load_round_trip_the_whole_film() {
+        let mut film = Film::with_aovs(3, 2);
+        for (k, p) in film.pixels.iter_mut().enumerate() {
+            let x = k as f64;
+            *p = RunningStats { count: k + 1, mean: Color::new(x, 0.5, -x), mean_alpha: 0.25, mean_luminance: x / 3.0, m2: 1e-3 * x };
+        }
+        film.aovs[4] = AovPixel {
+            samples: 9,
+            hits: 7,
+            depth: 2.5,
+            position: Vec3::new(1.0, 2.0, 3.0),
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            albedo: Color::new(0.1, 0.2, 0.3),
+            uv: (0.5, 0.75),
+            motion: (-1.0, 0.5),
+            object_id: 3,
+            material_id: 0x12_3456,
+        };
+        let splat = |x: f64| Splat { sum: Color::new(x, 2.0 * x, 3.0 * x), alpha: 0.5, weight: 1.0 + x, abs_weight: 2.0 + x };
+        film.splats = (0..6).map(|k| splat(k as f64)).collect();
+        film.passes = vec![(0..6).map(|k| splat(-(k as f64))).collect(), vec![Splat::default(); 6]];
+        film.mattes = vec![MattePixel::default(); 6];
+        film.mattes[1] = MattePixel { samples: 4, objects: vec![(2, 3), (5, 1)], materials: vec![(7, 4)] };
+        film.bad_samples = vec![0, 0, 2, 0, 0, 1];
+
+        let path = temp_path("round_trip");
+        let checkpoint = Checkpoint { seed: 42, scene_hash: 0xdead_beef, camera_hash: 0xfeed, film };
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, checkpoint);
+    }
+
+    #[test]
+    fn resume_is_refused_after_a_scene_or_camera_change() {
+        use crate::camera::{Camera, ProgressiveSettings};
+        use crate::hittable_list::HittableList;
+        use crate::material::Lambertian;
+        use crate::rtweekend::Shared;
+        use crate::sampler::SamplerKind;
+        use crate::sphere::Sphere;
+
+        let scene = |albedo| {
+            let mut world = HittableList::new();
+            world.push(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, Shared::new(Lambertian::new(Color::new(albedo, 0.5, 0.5)))));
+            world
+        };
+        let camera = || {
+            let mut cam = Camera::new_with(8, 2.0, 4, 5, 90.0);
+            cam.lookat = Vec3::new(0.0, 0.0, -1.0);
+            cam.vup = Vec3::new(0.0, 1.0, 0.0);
+            cam.focus_dist = 1.0;
+            cam
+        };
+        let world = scene(0.5);
+        let path = temp_path("refused");
+        let settings = ProgressiveSettings { checkpoint_path: Some(path.clone()), ..ProgressiveSettings::default() };
+        let checkpoint = Checkpoint { seed: 0, scene_hash: Camera::scene_hash(&world), camera_hash: camera().fingerprint(), film: Film::new(8, 4) };
+        checkpoint.save(&path).unwrap();
+
+        let refusal = |cam: &mut Camera, world: &HittableList| cam.resume_progressive(world, &settings).unwrap_err().to_string();
+        assert!(refusal(&mut camera(), &scene(0.6)).contains("scene changed"));
+        let mut moved = camera();
+        moved.lookfrom = Vec3::new(0.0, 0.1, 0.0);
+        assert!(refusal(&mut moved, &world).contains("camera changed"));
+        let mut reseeded = camera();
+        reseeded.seed = 1;
+        assert!(refusal(&mut reseeded, &world).contains("camera changed"));
+
+        // only stratified sampling depends on the sample count
+        let mut more = camera();
+        more.samples_per_pixel = 16;
+        assert_eq!(more.fingerprint(), camera().fingerprint());
+        let mut stratified = camera();
+        stratified.sampler = SamplerKind::Stratified;
+        let before = stratified.fingerprint();
+        stratified.samples_per_pixel = 16;
+        assert_ne!(stratified.fingerprint(), before);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_headers_are_refused_before_allocating() {
+        let path = temp_path("corrupt");
+        let checkpoint = Checkpoint { seed: 1, scene_hash: 2, camera_hash: 3, film: Film::new(4, 3) };
+        checkpoint.save(&path).unwrap();
+
+        // claim a film far bigger than the file
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[8 + 3 * 8..8 + 4 * 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // and a torn one
+        checkpoint.save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
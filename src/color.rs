@@ -1,4 +1,4 @@
-use std::ops::{Deref, DerefMut, Mul, Add, AddAssign, Div};
+use std::ops::{Deref, DerefMut, Mul, Add, AddAssign, Sub, Div};
 use crate::vec3::Vec3;
 use crate::interval::Interval;
 
@@ -21,11 +21,37 @@ impl Color {
     pub fn g(&self) -> f64 { self.0.y }
     pub fn b(&self) -> f64 { self.0.z }
 
+    /// false-color ramp for t in [0, 1]: blue -> green -> red
+    pub fn heatmap(t: f64) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        if t < 0.5 {
+            Self::new(0.0, 2.0 * t, 1.0 - 2.0 * t)
+        } else {
+            Self::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+        }
+    }
+
+    /// stable, well-separated false color for an id hash
+    pub fn from_hash(h: u64) -> Self {
+        let h = crate::rng::mix64(h);
+        let channel = |shift: u32| 0.15 + 0.85 * ((h >> shift) & 0xff) as f64 / 255.0;
+        Self::new(channel(0), channel(8), channel(16))
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.r().max(self.g()).max(self.b())
+    }
+
+    /// Rec. 709 relative luminance of the linear color
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
+    }
+
     pub fn linear_to_gamma(linear_component: f64) -> f64 {
         if linear_component > 0.0 {
             return linear_component.sqrt()
         }
-        return 0.0
+        0.0
     }
 
     pub fn clamp(&self, min: f64, max: f64) -> Self {
@@ -79,6 +105,14 @@ impl AddAssign for Color {
     }
 }
 
+// allow `Color - Color`
+impl Sub for Color {
+    type Output = Color;
+    fn sub(self, rhs: Self) -> Self::Output {
+        Color(self.0 - rhs.0)
+    }
+}
+
 // allow `Color * f64`
 impl Mul<f64> for Color {
     type Output = Color;
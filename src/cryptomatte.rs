@@ -0,0 +1,158 @@
+//! Cryptomatte ID mattes: per-pixel coverage of every object and material a pixel's
+//! camera rays hit first, written as Cryptomatte 1.2 EXR layers so compositors can
+//! isolate any of them after the render.
+//!
+//! Names are hashed with MurmurHash3 (x86, 32-bit, seed 0) and stored as floats; the
+//! manifest mapping names to hashes goes into the EXR header. Coverage is counted
+//! per pixel over its samples, like the other AOVs.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::film::{AlphaMode, Film};
+use crate::hittable_list::HittableList;
+
+/// Coverage counts of the ids seen by one pixel's samples.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MattePixel {
+    pub samples: u32,
+    /// (object id, samples that hit it first)
+    pub objects: Vec<(u32, u32)>,
+    /// (material id, samples that hit it first)
+    pub materials: Vec<(u32, u32)>,
+}
+
+impl MattePixel {
+    /// count one sample; `hit` is the (object id, material id) its camera ray hit first
+    pub fn add(&mut self, hit: Option<(u32, u32)>) {
+        self.samples += 1;
+        let Some((object, material)) = hit else { return };
+        count(&mut self.objects, object);
+        count(&mut self.materials, material);
+    }
+}
+
+fn count(entries: &mut Vec<(u32, u32)>, id: u32) {
+    match entries.iter_mut().find(|(e, _)| *e == id) {
+        Some((_, n)) => *n += 1,
+        None => entries.push((id, 1)),
+    }
+}
+
+/// MurmurHash3_x86_32
+pub fn murmur3_32(bytes: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+    let mix = |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+
+    let mut h = seed;
+    let mut blocks = bytes.chunks_exact(4);
+    for block in &mut blocks {
+        h ^= mix(u32::from_le_bytes(block.try_into().unwrap()));
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0u32, |k, &b| (k << 8) | b as u32);
+        h ^= mix(k);
+    }
+    h ^= bytes.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+/// The Cryptomatte id of `name`: its hash reinterpreted as a float, with the exponent
+/// kept off 0 and 255 so the value is never denormal, infinite or NaN.
+pub fn name_to_id(name: &str) -> f32 {
+    let hash = murmur3_32(name.as_bytes(), 0);
+    let exponent = ((hash >> 23) & 0xff).clamp(1, 254);
+    f32::from_bits((hash & 0x8000_0000) | (exponent << 23) | (hash & 0x7f_ffff))
+}
+
+/// A Cryptomatte type: its layer name, which of a pixel's counts it reads and how
+/// its ids are named.
+struct MatteType {
+    name: &'static str,
+    entries: fn(&MattePixel) -> &[(u32, u32)],
+    name_of: fn(&HittableList, u32) -> String,
+}
+
+const TYPES: [MatteType; 2] = [
+    MatteType { name: "CryptoObject", entries: |p| &p.objects, name_of: HittableList::object_name },
+    MatteType { name: "CryptoMaterial", entries: |p| &p.materials, name_of: HittableList::material_name },
+];
+
+/// Write `<stem>.cryptomatte.exr`: the beauty plus `CryptoObject` and
+/// `CryptoMaterial` layers of `ranks` (id, coverage) pairs per pixel, most coverage
+/// first, and their manifests. Names come from `world`.
+pub fn write_cryptomatte(film: &Film, world: &HittableList, stem: &str, ranks: usize, alpha: AlphaMode) -> io::Result<String> {
+    let mut image = film.beauty_exr(alpha);
+    for matte in &TYPES {
+        let names: BTreeMap<u32, String> = film.mattes.iter()
+            .flat_map(|p| (matte.entries)(p).iter().map(|&(id, _)| id))
+            .map(|id| (id, (matte.name_of)(world, id)))
+            .collect();
+        let ids: BTreeMap<u32, f32> = names.iter().map(|(&id, name)| (id, name_to_id(name))).collect();
+
+        // each RGBA layer holds two ranks as (id, coverage, id, coverage)
+        let mut channels = vec![vec![0.0f32; film.mattes.len()]; 4 * ranks.div_ceil(2)];
+        for (k, pixel) in film.mattes.iter().enumerate() {
+            let mut ranked = (matte.entries)(pixel).to_vec();
+            ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            for (rank, &(id, count)) in ranked.iter().take(ranks).enumerate() {
+                channels[2 * rank][k] = ids[&id];
+                channels[2 * rank + 1][k] = count as f32 / pixel.samples.max(1) as f32;
+            }
+        }
+        for (c, data) in channels.into_iter().enumerate() {
+            image.add_channel(format!("{}{:02}.{}", matte.name, c / 4, ["R", "G", "B", "A"][c % 4]), data);
+        }
+
+        let key = &format!("{:08x}", murmur3_32(matte.name.as_bytes(), 0))[..7];
+        let manifest: Vec<String> = names.values()
+            .map(|name| format!("\"{}\":\"{:08x}\"", json_escape(name), name_to_id(name).to_bits()))
+            .collect();
+        image.attributes.push((format!("cryptomatte/{}/name", key), matte.name.to_string()));
+        image.attributes.push((format!("cryptomatte/{}/hash", key), "MurmurHash3_32".to_string()));
+        image.attributes.push((format!("cryptomatte/{}/conversion", key), "uint32_to_float32".to_string()));
+        image.attributes.push((format!("cryptomatte/{}/manifest", key), format!("{{{}}}", manifest.join(","))));
+    }
+    let path = format!("{}.cryptomatte.exr", stem);
+    image.write(&path)?;
+    Ok(path)
+}
+
+pub(crate) fn json_escape(s: &str) -> String {
+    s.chars().flat_map(|c| match c {
+        '"' | '\\' => vec!['\\', c],
+        c => vec![c],
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_hash_to_finite_ids_and_ranks_count_coverage() {
+        assert_eq!(murmur3_32(b"", 0), 0);
+        assert_eq!(murmur3_32(b"hello", 0), 0x248b_fa47);
+        assert_eq!(murmur3_32(b"The quick brown fox jumps over the lazy dog", 0), 0x2e4f_f723);
+        // the example object from the Cryptomatte specification
+        assert_eq!(name_to_id("bunny").to_bits(), 0x13851a76);
+        for name in ["", "ground", "glass/bubble"] {
+            assert!(name_to_id(name).is_normal(), "{}", name);
+        }
+
+        let mut pixel = MattePixel::default();
+        for hit in [Some((2, 7)), Some((1, 7)), Some((2, 9)), None] {
+            pixel.add(hit);
+        }
+        assert_eq!(pixel.samples, 4);
+        assert_eq!(pixel.objects, [(2, 2), (1, 1)]);
+        assert_eq!(pixel.materials, [(7, 2), (9, 1)]);
+    }
+}
@@ -0,0 +1,236 @@
+//! Edge-avoiding À-trous wavelet denoiser (Dammertz et al. 2010) with the
+//! variance-guided luminance weight of SVGF (Schied et al. 2017).
+//!
+//! Each pass is a 5x5 B3-spline blur whose taps spread 2^k pixels apart; the tap
+//! weights drop across luminance edges (scaled by the pixel's own noise level) and
+//! across first-hit normal, depth and albedo edges. With feature buffers the color
+//! is divided by the albedo before filtering and multiplied back after, so texture
+//! detail survives.
+
+use rayon::prelude::*;
+
+use crate::color::Color;
+use crate::vec3::Vec3;
+use crate::film::Film;
+
+const KERNEL: [f64; 3] = [3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// strength parameters; larger sigmas blur across stronger edges
+#[derive(Clone, Debug, PartialEq)]
+pub struct DenoiseSettings {
+    /// filter passes; pass k spaces its taps 2^k pixels apart
+    pub iterations: usize,
+    /// luminance tolerance in standard deviations of the pixel estimate
+    pub sigma_color: f64,
+    /// exponent on the cosine between normals; higher keeps creases sharper
+    pub sigma_normal: f64,
+    /// depth tolerance relative to the pixel's depth, per pixel of tap distance
+    pub sigma_depth: f64,
+    pub sigma_albedo: f64,
+    /// 0 returns the input, 1 the fully filtered image
+    pub blend: f64,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        Self { iterations: 5, sigma_color: 4.0, sigma_normal: 32.0, sigma_depth: 0.1, sigma_albedo: 0.2, blend: 1.0 }
+    }
+}
+
+/// First-hit guide buffers in scanline order; a zero normal marks a pixel that only saw background.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeatureBuffers {
+    pub albedo: Vec<Color>,
+    pub normal: Vec<Vec3>,
+    pub depth: Vec<f64>,
+}
+
+impl FeatureBuffers {
+    /// the film's AOVs, or None if it was rendered without them
+    pub fn from_film(film: &Film) -> Option<Self> {
+        if film.aovs.is_empty() {
+            return None;
+        }
+        Some(Self {
+            albedo: film.aovs.iter().map(|a| a.mean_albedo()).collect(),
+            normal: film.aovs.iter().map(|a| a.mean_normal()).collect(),
+            depth: film.aovs.iter().map(|a| a.mean_depth()).collect(),
+        })
+    }
+}
+
+/// Denoise the film's mean colors, using its AOVs as guides when it has them. Pixels
+/// with bad samples take no part in filtering their neighbours; they keep their own
+/// color, or the film's signal color.
+pub fn denoise_film(film: &Film, settings: &DenoiseSettings) -> Vec<Color> {
+    let color = film.raw_colors();
+    // variance of the mean; with a single sample assume 100% relative error
+    let variance: Vec<f64> = film.pixels.iter()
+        .map(|p| if p.count < 2 { p.mean_luminance * p.mean_luminance } else { p.variance() / p.count as f64 })
+        .collect();
+    let features = FeatureBuffers::from_film(film);
+    let skip: Vec<bool> = (0..film.pixels.len()).map(|k| film.has_bad_samples(k)).collect();
+    let mut out = denoise_skipping(film.width, film.height, &color, &variance, features.as_ref(), &skip, settings);
+    if let Some(signal) = film.signal {
+        for (c, _) in out.iter_mut().zip(&skip).filter(|(_, skip)| **skip) {
+            *c = signal;
+        }
+    }
+    out
+}
+
+/// Standalone image-in/image-out denoiser. `variance` is the per-pixel variance of
+/// the luminance estimate (not of a single sample); `features` is optional.
+pub fn denoise(width: usize, height: usize, color: &[Color], variance: &[f64], features: Option<&FeatureBuffers>, settings: &DenoiseSettings) -> Vec<Color> {
+    denoise_skipping(width, height, color, variance, features, &[], settings)
+}
+
+/// `denoise`, leaving out the pixels `skip` marks (which come back unchanged)
+fn denoise_skipping(width: usize, height: usize, color: &[Color], variance: &[f64], features: Option<&FeatureBuffers>, skip: &[bool], settings: &DenoiseSettings) -> Vec<Color> {
+    let n = width * height;
+    let skipped = |p: usize| skip.get(p).copied().unwrap_or(false);
+    let albedo = |p: usize| {
+        let a = features.map_or(Color::new(1.0, 1.0, 1.0), |f| f.albedo[p]);
+        Color::new(a.r().max(1e-3), a.g().max(1e-3), a.b().max(1e-3))
+    };
+
+    let mut c: Vec<Color> = (0..n).map(|p| demodulate(color[p], albedo(p))).collect();
+    let mut var: Vec<f64> = (0..n).map(|p| variance[p] / albedo(p).luminance().powi(2)).collect();
+    for k in 0..settings.iterations {
+        let step = 1isize << k;
+        (c, var) = (0..n).into_par_iter()
+            .map(|p| if skipped(p) { (c[p], var[p]) } else { filter_pixel(width, height, p, step, &c, &var, features, skip, settings) })
+            .unzip();
+    }
+
+    (0..n).map(|p| if skipped(p) { color[p] } else { color[p] * (1.0 - settings.blend) + c[p] * albedo(p) * settings.blend }).collect()
+}
+
+fn demodulate(c: Color, albedo: Color) -> Color {
+    Color::new(c.r() / albedo.r(), c.g() / albedo.g(), c.b() / albedo.b())
+}
+
+/// one À-trous tap pattern around pixel `p`: filtered color and its propagated variance
+#[allow(clippy::too_many_arguments)]
+fn filter_pixel(width: usize, height: usize, p: usize, step: isize, c: &[Color], var: &[f64], features: Option<&FeatureBuffers>, skip: &[bool], settings: &DenoiseSettings) -> (Color, f64) {
+    let (x, y) = ((p % width) as isize, (p / width) as isize);
+    let lp = c[p].luminance();
+    let sigma_l = settings.sigma_color * local_variance(width, height, x, y, var, skip).sqrt() + 1e-6;
+
+    let mut sum = Color::new(0.0, 0.0, 0.0);
+    let mut sum_w = 0.0;
+    let mut sum_var = 0.0;
+    for dy in -2isize..=2 {
+        for dx in -2isize..=2 {
+            let (qx, qy) = (x + dx * step, y + dy * step);
+            if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                continue;
+            }
+            let q = qy as usize * width + qx as usize;
+            if skip.get(q).copied().unwrap_or(false) {
+                continue;
+            }
+            let mut w = KERNEL[dx.unsigned_abs()] * KERNEL[dy.unsigned_abs()];
+            w *= (-(lp - c[q].luminance()).abs() / sigma_l).exp();
+            if let Some(f) = features {
+                w *= feature_weight(f, p, q, step, settings);
+            }
+            sum += c[q] * w;
+            sum_w += w;
+            sum_var += w * w * var[q];
+        }
+    }
+    // the center tap is never skipped and always has a positive weight
+    (sum / sum_w, sum_var / (sum_w * sum_w))
+}
+
+/// 3x3 Gaussian of the variance around (x, y), so a pixel whose few samples happened
+/// to agree is not taken as noise-free
+fn local_variance(width: usize, height: usize, x: isize, y: isize, var: &[f64], skip: &[bool]) -> f64 {
+    let mut sum = 0.0;
+    let mut sum_w = 0.0;
+    for dy in -1isize..=1 {
+        for dx in -1isize..=1 {
+            let (qx, qy) = (x + dx, y + dy);
+            if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                continue;
+            }
+            let q = qy as usize * width + qx as usize;
+            if skip.get(q).copied().unwrap_or(false) {
+                continue;
+            }
+            let w = [0.5, 0.25][dx.unsigned_abs()] * [0.5, 0.25][dy.unsigned_abs()];
+            sum += w * var[q].max(0.0);
+            sum_w += w;
+        }
+    }
+    sum / sum_w
+}
+
+fn feature_weight(f: &FeatureBuffers, p: usize, q: usize, step: isize, settings: &DenoiseSettings) -> f64 {
+    let (np, nq) = (f.normal[p], f.normal[q]);
+    let (hit_p, hit_q) = (np.length_squared() > 0.0, nq.length_squared() > 0.0);
+    if hit_p != hit_q {
+        return 0.0;
+    }
+    if !hit_p {
+        return 1.0;
+    }
+    let w_normal = np.unit_vector().dot(&nq.unit_vector()).max(0.0).powf(settings.sigma_normal);
+    let sigma_z = settings.sigma_depth * f.depth[p] * step as f64 + 1e-6;
+    let w_depth = (-(f.depth[p] - f.depth[q]).abs() / sigma_z).exp();
+    let da = f.albedo[p] - f.albedo[q];
+    let w_albedo = (-da.length_squared() / (settings.sigma_albedo * settings.sigma_albedo).max(1e-12)).exp();
+    w_normal * w_depth * w_albedo
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adaptive::RunningStats;
+    use crate::rng::Rng;
+
+    #[test]
+    fn bad_pixels_are_left_out_of_their_neighbours() {
+        let mut film = Film::new(8, 8);
+        for p in film.pixels.iter_mut() {
+            *p = RunningStats { count: 4, mean: Color::new(0.5, 0.5, 0.5), mean_alpha: 1.0, mean_luminance: 0.5, m2: 0.01 };
+        }
+        let bad = film.index(3, 4);
+        film.pixels[bad].mean = Color::new(f64::NAN, 0.5, 0.5);
+        film.bad_samples = vec![0; 64];
+        film.bad_samples[bad] = 1;
+        film.signal = Some(Color::new(1.0, 0.0, 1.0));
+
+        let out = denoise_film(&film, &DenoiseSettings::default());
+        assert_eq!(out[bad], Color::new(1.0, 0.0, 1.0));
+        for (k, c) in out.iter().enumerate().filter(|&(k, _)| k != bad) {
+            assert!((*c - Color::new(0.5, 0.5, 0.5)).0.length() < 1e-9, "pixel {}: {:?}", k, c);
+        }
+    }
+
+    #[test]
+    fn smooths_noise_but_keeps_albedo_edges() {
+        let (width, height) = (32, 32);
+        let mut rng = Rng::new(7, 0);
+        let albedo: Vec<Color> = (0..width * height)
+            .map(|p| if p % width < width / 2 { Color::new(0.2, 0.2, 0.2) } else { Color::new(0.8, 0.8, 0.8) })
+            .collect();
+        let noisy: Vec<Color> = albedo.iter().map(|&a| a * (0.5 + rng.random_double())).collect();
+        let variance: Vec<f64> = albedo.iter().map(|a| a.luminance().powi(2) / 12.0).collect();
+        let features = FeatureBuffers {
+            albedo: albedo.clone(),
+            normal: vec![Vec3::new(0.0, 0.0, 1.0); width * height],
+            depth: vec![1.0; width * height],
+        };
+
+        let out = denoise(width, height, &noisy, &variance, Some(&features), &DenoiseSettings::default());
+        let error = |img: &[Color]| img.iter().zip(&albedo).map(|(c, a)| (c.luminance() - a.luminance()).abs()).sum::<f64>();
+        assert!(error(&out) < 0.25 * error(&noisy), "{} vs {}", error(&out), error(&noisy));
+        // the columns either side of the albedo edge stay apart
+        for y in 0..height {
+            let k = y * width + width / 2;
+            assert!(out[k].luminance() - out[k - 1].luminance() > 0.4);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Bad-sample diagnostics: with checking on, the integrator inspects every ray, hit
+//! record, scattering weight and light contribution for NaN, infinite or negative
+//! values, and the camera checks each sample's radiance. Offending samples are
+//! counted by problem, the earliest one (in scanline order) is kept with the bounce
+//! and material involved, and the film can paint the pixels they landed in.
+
+use std::fmt;
+
+use crate::hittable_list::HittableList;
+use crate::vec3::Vec3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    NaN,
+    Infinite,
+    Negative,
+}
+
+impl Problem {
+    /// the worst problem among `values`; negative values only count when `signed` is false
+    pub fn find(values: Vec3, signed: bool) -> Option<Self> {
+        let values = [values.x, values.y, values.z];
+        if values.iter().any(|v| v.is_nan()) {
+            Some(Problem::NaN)
+        } else if values.iter().any(|v| v.is_infinite()) {
+            Some(Problem::Infinite)
+        } else if !signed && values.iter().any(|&v| v < 0.0) {
+            Some(Problem::Negative)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Problem::NaN => "NaN",
+            Problem::Infinite => "infinite",
+            Problem::Negative => "negative",
+        })
+    }
+}
+
+/// the first bad value the integrator met in one sample
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Finding {
+    /// None when only the sample's final radiance was bad
+    pub bounce: Option<usize>,
+    /// which value, e.g. "ray direction" or "attenuation"
+    pub what: &'static str,
+    pub problem: Problem,
+    /// `aov::material_id` of the surface at that bounce
+    pub material: Option<u32>,
+}
+
+/// a finding with the sample it came from
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BadSample {
+    pub pixel: (usize, usize),
+    pub sample: usize,
+    pub finding: Finding,
+}
+
+impl BadSample {
+    /// scanline order, for keeping the earliest sample whatever order tiles finish in
+    fn key(&self) -> (usize, usize, usize) { (self.pixel.1, self.pixel.0, self.sample) }
+}
+
+/// Bad samples counted by problem, and the earliest of them.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Diagnostics {
+    pub nan: u64,
+    pub infinite: u64,
+    pub negative: u64,
+    pub first: Option<BadSample>,
+}
+
+impl Diagnostics {
+    pub fn bad_samples(&self) -> u64 { self.nan + self.infinite + self.negative }
+
+    pub fn add(&mut self, sample: BadSample) {
+        match sample.finding.problem {
+            Problem::NaN => self.nan += 1,
+            Problem::Infinite => self.infinite += 1,
+            Problem::Negative => self.negative += 1,
+        }
+        self.keep_earliest(sample);
+    }
+
+    pub fn merge(&mut self, other: &Diagnostics) {
+        self.nan += other.nan;
+        self.infinite += other.infinite;
+        self.negative += other.negative;
+        if let Some(sample) = other.first {
+            self.keep_earliest(sample);
+        }
+    }
+
+    fn keep_earliest(&mut self, sample: BadSample) {
+        if self.first.is_none_or(|first| sample.key() < first.key()) {
+            self.first = Some(sample);
+        }
+    }
+
+    /// one-line summary, naming materials after `world`
+    pub fn summary(&self, world: &HittableList) -> String {
+        let Some(first) = self.first else { return "no bad samples".to_string() };
+        let finding = first.finding;
+        let mut out = format!(
+            "{} bad samples ({} NaN, {} infinite, {} negative); first at pixel ({}, {}) sample {}: {} {}",
+            self.bad_samples(), self.nan, self.infinite, self.negative,
+            first.pixel.0, first.pixel.1, first.sample, finding.problem, finding.what,
+        );
+        if let Some(bounce) = finding.bounce {
+            out += &format!(" at bounce {}", bounce);
+        }
+        if let Some(material) = finding.material {
+            out += &format!(" on material {}", world.material_name(material));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_by_problem_and_keeps_the_earliest_sample() {
+        assert_eq!(Problem::find(Vec3::new(1.0, f64::NAN, f64::INFINITY), false), Some(Problem::NaN));
+        assert_eq!(Problem::find(Vec3::new(1.0, -2.0, f64::INFINITY), false), Some(Problem::Infinite));
+        assert_eq!(Problem::find(Vec3::new(1.0, -2.0, 0.0), false), Some(Problem::Negative));
+        assert_eq!(Problem::find(Vec3::new(1.0, -2.0, 0.0), true), None);
+
+        let bad = |pixel, sample, problem| BadSample {
+            pixel,
+            sample,
+            finding: Finding { bounce: Some(1), what: "attenuation", problem, material: None },
+        };
+        let (mut a, mut b) = (Diagnostics::default(), Diagnostics::default());
+        a.add(bad((5, 2), 0, Problem::NaN));
+        b.add(bad((9, 1), 3, Problem::Negative));
+        b.add(bad((9, 1), 1, Problem::NaN));
+        a.merge(&b);
+        assert_eq!((a.nan, a.infinite, a.negative), (2, 0, 1));
+        assert_eq!(a.first.map(|f| (f.pixel, f.sample)), Some(((9, 1), 1)));
+    }
+}
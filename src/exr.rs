@@ -0,0 +1,129 @@
+//! Minimal OpenEXR writer: single-part scanline image, uncompressed FLOAT channels.
+//!
+//! Channel names follow the usual layer convention (`"depth.Z"`, `"N.X"`, plain
+//! `"R"`/`"G"`/`"B"`/`"A"` for the beauty), so compositors pick up named layers.
+
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+
+const MAGIC: u32 = 20_000_630;
+const FLOAT: i32 = 2;
+
+pub struct ExrChannel {
+    pub name: String,
+    /// width * height values in scanline order
+    pub data: Vec<f32>,
+}
+
+#[derive(Default)]
+pub struct ExrImage {
+    pub width: usize,
+    pub height: usize,
+    pub channels: Vec<ExrChannel>,
+    /// extra string attributes written into the header
+    pub attributes: Vec<(String, String)>,
+}
+
+impl ExrImage {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, ..Self::default() }
+    }
+
+    pub fn add_channel(&mut self, name: impl Into<String>, data: Vec<f32>) {
+        self.channels.push(ExrChannel { name: name.into(), data });
+    }
+
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&self.encode())?;
+        out.flush()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        // EXR requires channels sorted by name, both in the header and in the pixel data
+        let mut channels: Vec<&ExrChannel> = self.channels.iter().collect();
+        channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&MAGIC.to_le_bytes());
+        buf.extend_from_slice(&2u32.to_le_bytes());
+
+        let mut chlist = Vec::new();
+        for ch in &channels {
+            chlist.extend_from_slice(ch.name.as_bytes());
+            chlist.push(0);
+            chlist.extend_from_slice(&FLOAT.to_le_bytes());
+            chlist.extend_from_slice(&[0, 0, 0, 0]); // pLinear + reserved
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+        }
+        chlist.push(0);
+        attribute(&mut buf, "channels", "chlist", &chlist);
+        attribute(&mut buf, "compression", "compression", &[0]);
+
+        let mut window = Vec::new();
+        for v in [0, 0, self.width as i32 - 1, self.height as i32 - 1] {
+            window.extend_from_slice(&v.to_le_bytes());
+        }
+        attribute(&mut buf, "dataWindow", "box2i", &window);
+        attribute(&mut buf, "displayWindow", "box2i", &window);
+        attribute(&mut buf, "lineOrder", "lineOrder", &[0]);
+        attribute(&mut buf, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+        attribute(&mut buf, "screenWindowCenter", "v2f", &[0u8; 8]);
+        attribute(&mut buf, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+        for (name, value) in &self.attributes {
+            attribute(&mut buf, name, "string", value.as_bytes());
+        }
+        buf.push(0);
+
+        // one uncompressed scanline per block, preceded by the offset table
+        let block_size = 8 + 4 * self.width * channels.len();
+        let table_end = buf.len() + 8 * self.height;
+        for y in 0..self.height {
+            buf.extend_from_slice(&((table_end + y * block_size) as u64).to_le_bytes());
+        }
+        for y in 0..self.height {
+            buf.extend_from_slice(&(y as i32).to_le_bytes());
+            buf.extend_from_slice(&((4 * self.width * channels.len()) as i32).to_le_bytes());
+            for ch in &channels {
+                for x in 0..self.width {
+                    let v = ch.data.get(y * self.width + x).copied().unwrap_or(0.0);
+                    buf.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+        }
+        buf
+    }
+}
+
+fn attribute(buf: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    buf.extend_from_slice(name.as_bytes());
+    buf.push(0);
+    buf.extend_from_slice(kind.as_bytes());
+    buf.push(0);
+    buf.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    buf.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_point_at_scanline_blocks() {
+        let mut image = ExrImage::new(3, 2);
+        image.add_channel("Z", vec![1.0; 6]);
+        image.add_channel("A", vec![0.5; 6]);
+        let bytes = image.encode();
+        assert_eq!(u32::from_le_bytes(bytes[0..4].try_into().unwrap()), MAGIC);
+
+        let block_size = 8 + 4 * 3 * 2;
+        let table = bytes.len() - 2 * block_size - 16;
+        for y in 0..2 {
+            let offset = u64::from_le_bytes(bytes[table + 8 * y..table + 8 * y + 8].try_into().unwrap()) as usize;
+            assert_eq!(i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()), y as i32);
+            // channels sorted by name: A before Z
+            assert_eq!(f32::from_le_bytes(bytes[offset + 8..offset + 12].try_into().unwrap()), 0.5);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+
+use crate::color::Color;
+use crate::adaptive::RunningStats;
+use crate::aov::AovPixel;
+use crate::cryptomatte::MattePixel;
+use crate::exr::ExrImage;
+use crate::filter::{Splat, SplatBuffer};
+use crate::png;
+
+/// How RGBA outputs store color. The film itself keeps color premultiplied by alpha.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum AlphaMode {
+    /// color divided by alpha, as PNG readers expect
+    Straight,
+    /// color already scaled by alpha, as compositors expect from EXR
+    #[default]
+    Premultiplied,
+}
+
+impl AlphaMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "straight" | "unassociated" => Some(AlphaMode::Straight),
+            "premultiplied" | "associated" => Some(AlphaMode::Premultiplied),
+            _ => None,
+        }
+    }
+
+    /// `color` (premultiplied by `alpha`) as stored in this mode
+    pub fn apply(self, color: Color, alpha: f64) -> Color {
+        match self {
+            AlphaMode::Premultiplied => color,
+            AlphaMode::Straight if alpha > 1e-6 => color / alpha,
+            AlphaMode::Straight => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Float accumulation buffer for a whole image, one running estimate per pixel
+/// in scanline order. Progressive passes keep adding samples to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<RunningStats>,
+    /// first-hit AOVs per pixel; empty unless the film was made `with_aovs`
+    pub aovs: Vec<AovPixel>,
+    /// filtered splats per pixel; empty when samples are simply averaged per pixel
+    pub splats: Vec<Splat>,
+    /// per light pass, its contributions per pixel, weighted like the beauty so the
+    /// passes sum to it
+    pub passes: Vec<Vec<Splat>>,
+    /// ID matte coverage per pixel; empty unless Cryptomatte output is wanted
+    pub mattes: Vec<MattePixel>,
+    /// bad samples per pixel; empty unless the camera checks samples
+    pub bad_samples: Vec<u32>,
+    /// color shown instead of any pixel with bad samples
+    pub signal: Option<Color>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![RunningStats::default(); width * height],
+            aovs: Vec::new(),
+            splats: Vec::new(),
+            passes: Vec::new(),
+            mattes: Vec::new(),
+            bad_samples: Vec::new(),
+            signal: None,
+        }
+    }
+
+    pub fn with_aovs(width: usize, height: usize) -> Self {
+        Self { aovs: vec![AovPixel::default(); width * height], ..Self::new(width, height) }
+    }
+
+    pub fn index(&self, i: usize, j: usize) -> usize { j * self.width + i }
+
+    /// the reconstructed color: filtered splats if the film has them and their weights
+    /// don't cancel out, otherwise the pixel mean; the signal color instead if the pixel
+    /// got bad samples
+    pub fn color(&self, i: usize, j: usize) -> Color { self.color_at(self.index(i, j)) }
+
+    fn color_at(&self, k: usize) -> Color {
+        match self.signal.filter(|_| self.has_bad_samples(k)) {
+            Some(signal) => signal,
+            None => self.raw_color_at(k),
+        }
+    }
+
+    fn raw_color_at(&self, k: usize) -> Color {
+        self.splats.get(k).and_then(Splat::color).unwrap_or(self.pixels[k].mean)
+    }
+
+    /// `color` of every pixel in scanline order
+    pub fn colors(&self) -> Vec<Color> { (0..self.pixels.len()).map(|k| self.color_at(k)).collect() }
+
+    /// the reconstructed colors, never the signal color
+    pub fn raw_colors(&self) -> Vec<Color> { (0..self.pixels.len()).map(|k| self.raw_color_at(k)).collect() }
+
+    /// whether the pixel at scanline index `k` got bad samples (only known when checking)
+    pub fn has_bad_samples(&self, k: usize) -> bool { self.bad_samples.get(k).is_some_and(|&n| n > 0) }
+
+    /// coverage in [0, 1], reconstructed the same way as the color
+    pub fn alpha(&self, i: usize, j: usize) -> f64 { self.alpha_at(self.index(i, j)) }
+
+    fn alpha_at(&self, k: usize) -> f64 {
+        self.splats.get(k).and_then(Splat::alpha).unwrap_or(self.pixels[k].mean_alpha).clamp(0.0, 1.0)
+    }
+
+    /// `alpha` of every pixel in scanline order
+    pub fn alphas(&self) -> Vec<f64> { (0..self.pixels.len()).map(|k| self.alpha_at(k)).collect() }
+
+    /// color and alpha of every pixel, color stored as `mode` says
+    pub fn rgba(&self, mode: AlphaMode) -> Vec<(Color, f64)> {
+        (0..self.pixels.len()).map(|k| {
+            let alpha = self.alpha_at(k);
+            (mode.apply(self.color_at(k), alpha), alpha)
+        }).collect()
+    }
+
+    pub fn add_splats(&mut self, buffer: &SplatBuffer) {
+        merge(&mut self.splats, self.width, buffer);
+    }
+
+    pub fn add_pass_splats(&mut self, pass: usize, buffer: &SplatBuffer) {
+        merge(&mut self.passes[pass], self.width, buffer);
+    }
+
+    /// light pass `pass` of every pixel in scanline order
+    pub fn pass_colors(&self, pass: usize) -> Vec<Color> {
+        self.passes[pass].iter().map(|s| s.color().unwrap_or(Color::new(0.0, 0.0, 0.0))).collect()
+    }
+
+    pub fn sample_counts(&self) -> Vec<usize> { self.pixels.iter().map(|p| p.count).collect() }
+
+    pub fn total_samples(&self) -> usize { self.pixels.iter().map(|p| p.count).sum() }
+
+    /// gamma-corrected 8-bit plain PPM, same format `Camera::render` streams out
+    pub fn write_ppm(&self, path: &str) -> io::Result<()> {
+        write_ppm(path, self.width, self.height, self.colors().into_iter())
+    }
+
+    /// the same gamma-corrected 8-bit image as a PNG
+    pub fn write_png(&self, path: &str) -> io::Result<()> {
+        write_png(path, self.width, self.height, self.colors().into_iter())
+    }
+
+    /// gamma-corrected 8-bit RGBA PNG; alpha stays linear
+    pub fn write_png_rgba(&self, path: &str, mode: AlphaMode) -> io::Result<()> {
+        let rgba: Vec<u8> = self.rgba(mode).into_iter().flat_map(|(c, alpha)| {
+            let (r, g, b) = c.to_rgb_i32();
+            [r as u8, g as u8, b as u8, (alpha * 255.0).round() as u8]
+        }).collect();
+        png::write_rgba(path, self.width, self.height, &rgba)
+    }
+
+    /// linear float RGBA EXR
+    pub fn write_exr(&self, path: &str, mode: AlphaMode) -> io::Result<()> {
+        self.beauty_exr(mode).write(path)
+    }
+
+    /// EXR holding the R, G, B and A channels
+    pub fn beauty_exr(&self, mode: AlphaMode) -> ExrImage {
+        let rgba = self.rgba(mode);
+        let mut image = ExrImage::new(self.width, self.height);
+        image.add_channel("R", rgba.iter().map(|(c, _)| c.r() as f32).collect());
+        image.add_channel("G", rgba.iter().map(|(c, _)| c.g() as f32).collect());
+        image.add_channel("B", rgba.iter().map(|(c, _)| c.b() as f32).collect());
+        image.add_channel("A", rgba.iter().map(|&(_, a)| a as f32).collect());
+        image
+    }
+}
+
+fn merge(splats: &mut [Splat], width: usize, buffer: &SplatBuffer) {
+    for y in 0..buffer.height {
+        for x in 0..buffer.width {
+            splats[(buffer.y0 + y) * width + buffer.x0 + x].add(&buffer.splats[y * buffer.width + x]);
+        }
+    }
+}
+
+/// linear colors in scanline order as a gamma-corrected 8-bit plain PPM
+pub fn write_ppm(path: &str, width: usize, height: usize, colors: impl Iterator<Item = Color>) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "P3")?;
+    writeln!(out, "{} {}", width, height)?;
+    writeln!(out, "255")?;
+    for c in colors {
+        out.write_all(c.to_ppm_string().as_bytes())?;
+    }
+    out.flush()
+}
+
+/// linear colors in scanline order as a gamma-corrected 8-bit PNG
+pub fn write_png(path: &str, width: usize, height: usize, colors: impl Iterator<Item = Color>) -> io::Result<()> {
+    let rgb: Vec<u8> = colors.flat_map(|c| {
+        let (r, g, b) = c.to_rgb_i32();
+        [r as u8, g as u8, b as u8]
+    }).collect();
+    png::write(path, width, height, &rgb)
+}
@@ -0,0 +1,249 @@
+//! Pixel reconstruction filters and the splat buffers samples are added to.
+//!
+//! Samples are still drawn uniformly inside their pixel, but each one is added,
+//! weighted by the filter, to every pixel whose center lies within the filter
+//! radius. A pixel's color is the weighted sum divided by the summed weights.
+
+use crate::color::Color;
+use crate::rtweekend::PI;
+use crate::scheduler::Tile;
+
+/// separable filter; `radius` is in pixels along each axis
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PixelFilter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    /// Gaussian of standard deviation `sigma`, shifted to reach zero at the radius
+    Gaussian { radius: f64, sigma: f64 },
+    /// Mitchell-Netravali cubic with parameters B and C, stretched over the radius
+    Mitchell { radius: f64, b: f64, c: f64 },
+    /// sinc windowed by a sinc that stretches over the radius
+    Lanczos { radius: f64 },
+    BlackmanHarris { radius: f64 },
+}
+
+impl PixelFilter {
+    /// `name` as accepted on the command line, with the default radius unless given
+    pub fn parse(name: &str, radius: Option<f64>) -> Option<Self> {
+        let filter = match name {
+            "box" => PixelFilter::Box { radius: radius.unwrap_or(0.5) },
+            "tent" => PixelFilter::Tent { radius: radius.unwrap_or(1.0) },
+            "gaussian" => {
+                let radius = radius.unwrap_or(1.5);
+                PixelFilter::Gaussian { radius, sigma: radius / 3.0 }
+            }
+            "mitchell" => PixelFilter::Mitchell { radius: radius.unwrap_or(2.0), b: 1.0 / 3.0, c: 1.0 / 3.0 },
+            "lanczos" => PixelFilter::Lanczos { radius: radius.unwrap_or(3.0) },
+            "blackman-harris" => PixelFilter::BlackmanHarris { radius: radius.unwrap_or(1.5) },
+            _ => return None,
+        };
+        Some(filter)
+    }
+
+    pub fn radius(&self) -> f64 {
+        match *self {
+            PixelFilter::Box { radius }
+            | PixelFilter::Tent { radius }
+            | PixelFilter::Gaussian { radius, .. }
+            | PixelFilter::Mitchell { radius, .. }
+            | PixelFilter::Lanczos { radius }
+            | PixelFilter::BlackmanHarris { radius } => radius,
+        }
+    }
+
+    /// weight of a sample `(dx, dy)` pixels away from a pixel center
+    pub fn eval(&self, dx: f64, dy: f64) -> f64 {
+        self.eval_1d(dx) * self.eval_1d(dy)
+    }
+
+    fn eval_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        let radius = self.radius();
+        if x > radius {
+            return 0.0;
+        }
+        match *self {
+            PixelFilter::Box { .. } => 1.0,
+            PixelFilter::Tent { radius } => radius - x,
+            PixelFilter::Gaussian { radius, sigma } => {
+                let g = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (g(x) - g(radius)).max(0.0)
+            }
+            PixelFilter::Mitchell { radius, b, c } => mitchell(2.0 * x / radius, b, c),
+            PixelFilter::Lanczos { radius } => sinc(x) * sinc(x / radius),
+            PixelFilter::BlackmanHarris { radius } => {
+                let t = 2.0 * PI * (0.5 + 0.5 * x / radius);
+                0.35875 - 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() - 0.01168 * (3.0 * t).cos()
+            }
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 { 1.0 } else { (PI * x).sin() / (PI * x) }
+}
+
+/// Mitchell-Netravali kernel on [0, 2]
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)) / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+    } else {
+        0.0
+    }
+}
+
+/// filtered sums of color and alpha and the total weight of the samples splatted
+/// into one pixel
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Splat {
+    pub sum: Color,
+    pub alpha: f64,
+    pub weight: f64,
+    /// sum of the weights' magnitudes; more than `weight` once negative lobes add in
+    pub abs_weight: f64,
+}
+
+impl Default for Splat {
+    fn default() -> Self {
+        Self { sum: Color::new(0.0, 0.0, 0.0), alpha: 0.0, weight: 0.0, abs_weight: 0.0 }
+    }
+}
+
+/// below this share of `abs_weight`, negative lobes (Lanczos, Mitchell) have cancelled
+/// so much of the weight that dividing by it would blow the pixel up
+const MIN_WEIGHT_SHARE: f64 = 0.25;
+
+impl Splat {
+    pub fn add(&mut self, other: &Splat) {
+        self.sum += other.sum;
+        self.alpha += other.alpha;
+        self.weight += other.weight;
+        self.abs_weight += other.abs_weight;
+    }
+
+    /// None when no sample has reached the pixel yet, or when negative lobes leave too
+    /// little weight to divide by; the film then falls back to the pixel's own mean
+    pub fn color(&self) -> Option<Color> {
+        if self.degenerate() { None } else { Some(self.sum / self.weight) }
+    }
+
+    pub fn alpha(&self) -> Option<f64> {
+        if self.degenerate() { None } else { Some(self.alpha / self.weight) }
+    }
+
+    fn degenerate(&self) -> bool {
+        self.weight < 1e-12 || self.weight < MIN_WEIGHT_SHARE * self.abs_weight
+    }
+}
+
+/// Splats of one tile's samples, covering the tile plus the filter's reach into its
+/// neighbors; merged into the film once the tile is done.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SplatBuffer {
+    pub x0: usize,
+    pub y0: usize,
+    pub width: usize,
+    pub height: usize,
+    pub splats: Vec<Splat>,
+}
+
+impl SplatBuffer {
+    /// buffer for `tile` in an image of `image_width` x `image_height`
+    pub fn for_tile(tile: &Tile, filter: &PixelFilter, image_width: usize, image_height: usize) -> Self {
+        Self::with_margin(tile, filter.radius().ceil() as usize, image_width, image_height)
+    }
+
+    /// buffer for exactly the pixels of `tile`, for `add_to_pixel`
+    pub fn for_pixels(tile: &Tile) -> Self {
+        Self::with_margin(tile, 0, tile.x1, tile.y1)
+    }
+
+    fn with_margin(tile: &Tile, margin: usize, image_width: usize, image_height: usize) -> Self {
+        let (x0, y0) = (tile.x0.saturating_sub(margin), tile.y0.saturating_sub(margin));
+        let (x1, y1) = ((tile.x1 + margin).min(image_width), (tile.y1 + margin).min(image_height));
+        let (width, height) = (x1 - x0, y1 - y0);
+        Self { x0, y0, width, height, splats: vec![Splat::default(); width * height] }
+    }
+
+    /// add a sample taken at raster position (x, y), pixel (i, j) spanning [i, i + 1) x [j, j + 1)
+    pub fn add(&mut self, filter: &PixelFilter, x: f64, y: f64, color: Color, alpha: f64) {
+        let radius = filter.radius();
+        let first = |v: f64, origin: usize| ((v - 0.5 - radius).ceil().max(origin as f64)) as usize;
+        let last = |v: f64, origin: usize, size: usize| ((v - 0.5 + radius).floor() as isize).min((origin + size) as isize - 1);
+        let (i0, j0) = (first(x, self.x0), first(y, self.y0));
+        let (i1, j1) = (last(x, self.x0, self.width), last(y, self.y0, self.height));
+        for j in j0 as isize..=j1 {
+            for i in i0 as isize..=i1 {
+                let w = filter.eval(i as f64 + 0.5 - x, j as f64 + 0.5 - y);
+                if w == 0.0 {
+                    continue;
+                }
+                let k = (j as usize - self.y0) * self.width + (i as usize - self.x0);
+                self.splats[k].sum += color * w;
+                self.splats[k].alpha += alpha * w;
+                self.splats[k].weight += w;
+                self.splats[k].abs_weight += w.abs();
+            }
+        }
+    }
+
+    /// add a sample to pixel (i, j) alone with weight 1, matching a film that simply
+    /// averages each pixel's own samples
+    pub fn add_to_pixel(&mut self, i: usize, j: usize, color: Color, alpha: f64) {
+        let k = (j - self.y0) * self.width + (i - self.x0);
+        self.splats[k].sum += color;
+        self.splats[k].alpha += alpha;
+        self.splats[k].weight += 1.0;
+        self.splats[k].abs_weight += 1.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_peak_at_center_and_vanish_past_radius() {
+        for name in ["box", "tent", "gaussian", "mitchell", "lanczos", "blackman-harris"] {
+            let filter = PixelFilter::parse(name, None).unwrap();
+            let r = filter.radius();
+            assert!(filter.eval(0.0, 0.0) > 0.0, "{}", name);
+            assert!(filter.eval(0.0, 0.0) >= filter.eval(0.3, 0.2), "{}", name);
+            assert_eq!(filter.eval(r + 1e-3, 0.0), 0.0, "{}", name);
+        }
+    }
+
+    #[test]
+    fn splats_reach_neighbors_across_the_tile_border() {
+        let filter = PixelFilter::Tent { radius: 1.5 };
+        let tile = Tile { index: 0, x0: 4, y0: 4, x1: 8, y1: 8 };
+        let mut buffer = SplatBuffer::for_tile(&tile, &filter, 16, 16);
+        assert_eq!((buffer.x0, buffer.y0, buffer.width, buffer.height), (2, 2, 8, 8));
+
+        buffer.add(&filter, 4.1, 5.5, Color::new(1.0, 1.0, 1.0), 1.0);
+        let at = |i: usize, j: usize| buffer.splats[(j - buffer.y0) * buffer.width + (i - buffer.x0)].weight;
+        assert!(at(3, 5) > 0.0, "pixel left of the tile gets weight");
+        assert!(at(4, 5) > at(3, 5));
+        assert_eq!(at(2, 5), 0.0);
+    }
+
+    #[test]
+    fn cancelled_weights_fall_back_to_the_pixel_mean() {
+        let filter = PixelFilter::Lanczos { radius: 3.0 };
+        let tile = Tile { index: 0, x0: 0, y0: 0, x1: 8, y1: 1 };
+        let mut buffer = SplatBuffer::for_tile(&tile, &filter, 8, 1);
+        // samples on the negative lobe of pixel 0, about 1.5 pixels away
+        buffer.add(&filter, 2.0, 0.5, Color::new(1.0, 1.0, 1.0), 1.0);
+        let lobe = buffer.splats[0];
+        assert!(lobe.weight < 0.0);
+        assert_eq!((lobe.color(), lobe.alpha()), (None, None));
+
+        // a sample of its own outweighs the lobe
+        buffer.add(&filter, 0.5, 0.5, Color::new(0.2, 0.2, 0.2), 1.0);
+        let color = buffer.splats[0].color().unwrap();
+        assert!(color.r() > 0.0 && color.r() < 0.2, "{:?}", color);
+        assert_eq!(Splat::default().color(), None);
+    }
+}
@@ -0,0 +1,38 @@
+/// FNV-1a hasher for scene and camera fingerprints.
+///
+/// `std::hash::Hash` isn't implemented for `f64`, so objects feed their
+/// parameters in explicitly; the value is stable across runs and platforms.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Fingerprint(u64);
+
+impl Default for Fingerprint {
+    fn default() -> Self { Self(0xcbf2_9ce4_8422_2325) }
+}
+
+impl Fingerprint {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub fn write_u64(&mut self, v: u64) { self.write_bytes(&v.to_le_bytes()) }
+
+    pub fn write_f64(&mut self, v: f64) { self.write_u64(v.to_bits()) }
+
+    pub fn write_str(&mut self, s: &str) {
+        self.write_u64(s.len() as u64);
+        self.write_bytes(s.as_bytes());
+    }
+
+    pub fn write_vec3(&mut self, v: &crate::vec3::Vec3) {
+        self.write_f64(v.x);
+        self.write_f64(v.y);
+        self.write_f64(v.z);
+    }
+
+    pub fn finish(&self) -> u64 { self.0 }
+}
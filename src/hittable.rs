@@ -1,28 +1,43 @@
-use std::ops::{Add, Sub, Mul, Div, Neg, Deref, DerefMut};
-
 use crate::ray::Ray;
 use crate::vec3::{Vec3, Point3};
 use crate::material::MaterialPtr;
+use crate::fingerprint::Fingerprint;
 // use crate::rtweekend::Shared;
 
 #[derive(Clone)]
 pub struct HitRecord {
     pub p: Point3,
+    /// shading normal, facing against the incoming ray
     pub normal: Vec3,
     pub t: f64,
     pub front_face: bool,
     pub mat: MaterialPtr,
+    /// surface texture coordinates
+    pub u: f64,
+    pub v: f64,
+    /// 1-based index of the object in the enclosing `HittableList`; 0 if unknown
+    pub object_id: u32,
+    /// id the enclosing `HittableList` registered `mat` under; 0 if unnamed
+    pub material_id: u32,
 }
 
 impl HitRecord {
     pub fn new(p: Point3, t: f64, r: &Ray, outward_normal: Vec3, mat: MaterialPtr) -> Self {
         let front_face: bool = r.direction.dot(&outward_normal) < 0.0;
         let normal: Vec3 = if front_face { outward_normal } else { -outward_normal };
-        Self { p, normal, t, front_face, mat }
+        Self { p, normal, t, front_face, mat, u: 0.0, v: 0.0, object_id: 0, material_id: 0 }
     }
 }
 
 pub trait Hittable: Send + Sync {
     /// Return Some(HitRecord) if the ray hits the object in (t_min, t_max), else None.
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+
+    /// the material hits on the object get, if it has just one
+    fn material(&self) -> Option<MaterialPtr> { None }
+
+    /// Feed everything that affects the rendered image into `fp` (checkpoints compare these).
+    /// The default only writes the type name, so a checkpoint can't tell when such an
+    /// object's parameters change; override it to feed them in.
+    fn fingerprint(&self, fp: &mut Fingerprint) { fp.write_str(std::any::type_name::<Self>()) }
 }
\ No newline at end of file
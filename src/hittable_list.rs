@@ -1,17 +1,32 @@
 use crate::hittable::{Hittable, HitRecord};
 use crate::ray::Ray;
 use crate::rtweekend::Shared;
-use std::sync::Arc;
-
+use crate::fingerprint::Fingerprint;
+use crate::light::Light;
 use crate::material::MaterialPtr;
+use std::sync::Arc;
 
 pub struct HittableList {
     pub objects: Vec<Shared<dyn Hittable>>,
+    /// lights the path tracer samples directly; hitting objects never involves them
+    pub lights: Vec<Light>,
+    /// names of the objects by index, for ID mattes; unnamed objects are `object<id>`
+    pub names: Vec<String>,
+    /// names of the materials by material id
+    pub material_names: Vec<(u32, String)>,
+    /// material instances hits report an id for (`HitRecord::material_id`), set by `name_material`
+    pub materials: Vec<(MaterialPtr, u32)>,
+}
+
+impl Default for HittableList {
+    fn default() -> Self { Self::new() }
 }
 
 impl HittableList {
-    pub fn new() -> Self { Self { objects: Vec::new() } }
-    pub fn with_capacity(cap: usize) -> Self { Self { objects: Vec::with_capacity(cap) } }
+    pub fn new() -> Self { Self::with_capacity(0) }
+    pub fn with_capacity(cap: usize) -> Self {
+        Self { objects: Vec::with_capacity(cap), lights: Vec::new(), names: Vec::new(), material_names: Vec::new(), materials: Vec::new() }
+    }
 
     /// push a boxed trait object
     pub fn add(&mut self, object: Shared<dyn Hittable>) {
@@ -26,7 +41,54 @@ impl HittableList {
         self.objects.push(Arc::new(v));
     }
 
-    pub fn clear(&mut self) { self.objects.clear() }
+    pub fn clear(&mut self) {
+        self.objects.clear();
+        self.lights.clear();
+        self.names.clear();
+        self.material_names.clear();
+        self.materials.clear();
+    }
+
+    /// Name every hit on this material instance. Instances given the same name share
+    /// an id; ids count up from 1 in the order names first appear.
+    pub fn name_material(&mut self, material: &MaterialPtr, name: &str) {
+        let id = match self.material_names.iter().find(|(_, n)| n == name) {
+            Some(&(id, _)) => id,
+            None => {
+                let id = self.material_names.len() as u32 + 1;
+                self.material_names.push((id, name.to_string()));
+                id
+            }
+        };
+        self.materials.retain(|(m, _)| !same_material(m, material));
+        self.materials.push((material.clone(), id));
+    }
+
+    /// the id `name_material` gave this material instance
+    pub fn registered_material_id(&self, material: &MaterialPtr) -> Option<u32> {
+        self.materials.iter().find(|(m, _)| same_material(m, material)).map(|&(_, id)| id)
+    }
+
+    /// name of the object with `HitRecord::object_id` `id`
+    pub fn object_name(&self, id: u32) -> String {
+        match self.names.get((id as usize).wrapping_sub(1)) {
+            Some(name) => name.clone(),
+            None => format!("object{}", id),
+        }
+    }
+
+    /// name of the material with id `id`, as `aov::material_id` gives it; unnamed
+    /// materials are `material_<id>`
+    pub fn material_name(&self, id: u32) -> String {
+        match self.material_names.iter().find(|(m, _)| *m == id) {
+            Some((_, name)) => name.clone(),
+            None => format!("material_{:06x}", id),
+        }
+    }
+}
+
+fn same_material(a: &MaterialPtr, b: &MaterialPtr) -> bool {
+    std::ptr::addr_eq(Arc::as_ptr(a), Arc::as_ptr(b))
 }
 
 impl Hittable for HittableList {
@@ -34,13 +96,65 @@ impl Hittable for HittableList {
         let mut closest = t_max;
         let mut result: Option<HitRecord> = None;
 
-        for obj in &self.objects {
-            if let Some(rec) = obj.hit(r, t_min, closest) {
+        for (k, obj) in self.objects.iter().enumerate() {
+            if let Some(mut rec) = obj.hit(r, t_min, closest) {
                 closest = rec.t;
+                if rec.object_id == 0 {
+                    rec.object_id = k as u32 + 1;
+                }
                 result = Some(rec);
             }
         }
+        if let Some(rec) = result.as_mut().filter(|rec| rec.material_id == 0) {
+            rec.material_id = self.registered_material_id(&rec.mat).unwrap_or(0);
+        }
 
         result
     }
-}
\ No newline at end of file
+
+    fn fingerprint(&self, fp: &mut Fingerprint) {
+        fp.write_str("HittableList");
+        fp.write_u64(self.objects.len() as u64);
+        for obj in &self.objects {
+            obj.fingerprint(fp);
+        }
+        if !self.lights.is_empty() {
+            fp.write_u64(self.lights.len() as u64);
+            for light in &self.lights {
+                light.fingerprint(fp);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aov::material_id;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::{Point3, Vec3};
+
+    #[test]
+    fn materials_are_named_per_instance() {
+        // identical parameters, different materials
+        let red: MaterialPtr = Shared::new(Lambertian::new(Color::new(0.8, 0.1, 0.1)));
+        let paint: MaterialPtr = Shared::new(Lambertian::new(Color::new(0.8, 0.1, 0.1)));
+        let unnamed: MaterialPtr = Shared::new(Lambertian::new(Color::new(0.2, 0.2, 0.2)));
+        let mut world = HittableList::new();
+        world.push(Sphere::new(Point3::new(-2.0, 0.0, -5.0), 0.5, red.clone()));
+        world.push(Sphere::new(Point3::new(0.0, 0.0, -5.0), 0.5, paint.clone()));
+        world.push(Sphere::new(Point3::new(2.0, 0.0, -5.0), 0.5, unnamed));
+        world.name_material(&red, "red");
+        world.name_material(&paint, "paint");
+
+        let hit = |x: f64| world.hit(&Ray::new(Point3::new(x, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), 0.001, f64::INFINITY).unwrap();
+        let ids = [-2.0, 0.0, 2.0].map(|x| material_id(&hit(x)));
+        assert_eq!(&ids[..2], [1, 2]);
+        assert_eq!(world.material_name(ids[0]), "red");
+        assert_eq!(world.material_name(ids[1]), "paint");
+        assert_eq!(world.material_name(ids[2]), format!("material_{:06x}", ids[2]));
+        assert!(ids[2] > 2);
+    }
+}
@@ -0,0 +1,168 @@
+//! Placing a shared object in the scene under a transform, optionally with another
+//! material, without copying the object.
+
+use crate::hittable::{Hittable, HitRecord};
+use crate::material::MaterialPtr;
+use crate::ray::{Ray, Visibility};
+use crate::rtweekend::{Shared, degrees_to_radians};
+use crate::fingerprint::Fingerprint;
+use crate::vec3::{Vec3, Point3};
+
+/// Uniform scale, then rotation by `rotation` degrees about x, y and z (in that
+/// order), both about `pivot`, then translation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Vec3,
+    pub scale: f64,
+    pub pivot: Point3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self { translation: Vec3::zero(), rotation: Vec3::zero(), scale: 1.0, pivot: Point3::zero() }
+    }
+}
+
+impl Transform {
+    /// rows of the rotation matrix Rz * Ry * Rx
+    fn matrix(&self) -> [Vec3; 3] {
+        let (sx, cx) = degrees_to_radians(self.rotation.x).sin_cos();
+        let (sy, cy) = degrees_to_radians(self.rotation.y).sin_cos();
+        let (sz, cz) = degrees_to_radians(self.rotation.z).sin_cos();
+        [
+            Vec3::new(cz * cy, cz * sy * sx - sz * cx, cz * sy * cx + sz * sx),
+            Vec3::new(sz * cy, sz * sy * sx + cz * cx, sz * sy * cx - cz * sx),
+            Vec3::new(-sy, cy * sx, cy * cx),
+        ]
+    }
+}
+
+/// Rotation, uniform scale and translation as x -> scale * R x + offset. Unlike
+/// `Transform`, these compose, so nested placements flatten into one.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Similarity {
+    /// rows of R
+    pub rows: [Vec3; 3],
+    pub scale: f64,
+    pub offset: Vec3,
+}
+
+impl Default for Similarity {
+    fn default() -> Self {
+        Self { rows: [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)], scale: 1.0, offset: Vec3::zero() }
+    }
+}
+
+impl From<Transform> for Similarity {
+    fn from(t: Transform) -> Self {
+        let rows = t.matrix();
+        let mut s = Self { rows, scale: t.scale, offset: Vec3::zero() };
+        s.offset = t.pivot + t.translation - s.vector(t.pivot);
+        s
+    }
+}
+
+impl Similarity {
+    /// `inner` first, then `self`
+    pub fn compose(&self, inner: &Similarity) -> Similarity {
+        // row i of A B mixes B's rows by row i of A
+        let row = |a: Vec3| a.x * inner.rows[0] + a.y * inner.rows[1] + a.z * inner.rows[2];
+        Similarity {
+            rows: [row(self.rows[0]), row(self.rows[1]), row(self.rows[2])],
+            scale: self.scale * inner.scale,
+            offset: self.point(inner.offset),
+        }
+    }
+
+    fn rotate(&self, v: Vec3) -> Vec3 {
+        Vec3::new(self.rows[0].dot(&v), self.rows[1].dot(&v), self.rows[2].dot(&v))
+    }
+
+    fn rotate_inverse(&self, v: Vec3) -> Vec3 {
+        v.x * self.rows[0] + v.y * self.rows[1] + v.z * self.rows[2]
+    }
+
+    pub fn point(&self, p: Point3) -> Point3 { self.vector(p) + self.offset }
+
+    pub fn vector(&self, v: Vec3) -> Vec3 { self.rotate(v) * self.scale }
+
+    pub fn inverse_point(&self, p: Point3) -> Point3 { self.inverse_vector(p - self.offset) }
+
+    pub fn inverse_vector(&self, v: Vec3) -> Vec3 { self.rotate_inverse(v) / self.scale }
+}
+
+/// `object` moved by `placement`, shaded with `material` instead of its own if given,
+/// and only hit by the kinds of rays in `visibility`.
+pub struct Instance {
+    pub object: Shared<dyn Hittable>,
+    pub placement: Similarity,
+    pub material: Option<MaterialPtr>,
+    pub visibility: Visibility,
+}
+
+impl Instance {
+    pub fn new(object: Shared<dyn Hittable>, transform: Transform, material: Option<MaterialPtr>) -> Self {
+        Self::placed(object, transform.into(), material)
+    }
+
+    pub fn placed(object: Shared<dyn Hittable>, placement: Similarity, material: Option<MaterialPtr>) -> Self {
+        Self { object, placement, material, visibility: Visibility::ALL }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // the object-space ray keeps the same parameterization, so t carries over
+        if !self.visibility.sees(r.kind) {
+            return None;
+        }
+        let m = &self.placement;
+        let mut rec = self.object.hit(&Ray::with_kind(m.inverse_point(r.origin), m.inverse_vector(r.direction), r.kind), t_min, t_max)?;
+        rec.p = m.point(rec.p);
+        rec.normal = m.rotate(rec.normal);
+        if let Some(material) = &self.material {
+            rec.mat = material.clone();
+            rec.material_id = 0;
+        }
+        Some(rec)
+    }
+
+    fn material(&self) -> Option<MaterialPtr> { self.material.clone().or_else(|| self.object.material()) }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) {
+        fp.write_str("Instance");
+        for row in &self.placement.rows {
+            fp.write_vec3(row);
+        }
+        fp.write_f64(self.placement.scale);
+        fp.write_vec3(&self.placement.offset);
+        fp.write_u64(self.visibility.bits() as u64);
+        self.object.fingerprint(fp);
+        if let Some(material) = &self.material {
+            material.fingerprint(fp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn transformed_hits_land_where_the_object_moved() {
+        let mat = Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere: Shared<dyn Hittable> = Shared::new(Sphere::new(Point3::new(1.0, 0.0, 0.0), 0.5, mat));
+        // swing the sphere a quarter turn about y around the origin, double it and lift it
+        let transform = Transform { translation: Vec3::new(0.0, 1.0, 0.0), rotation: Vec3::new(0.0, 90.0, 0.0), scale: 2.0, pivot: Point3::zero() };
+        let instance = Instance::new(sphere, transform, None);
+        let ray = Ray::new(Point3::new(0.0, 1.0, -10.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = instance.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.p - Point3::new(0.0, 1.0, -3.0)).length() < 1e-9, "{:?}", rec.p);
+        assert!((rec.t - 7.0).abs() < 1e-9);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-9);
+    }
+}
@@ -0,0 +1,526 @@
+use crate::color::Color;
+use crate::vec3::Vec3;
+use crate::ray::{Ray, RayKind};
+use crate::hittable::{Hittable, HitRecord};
+use crate::hittable_list::HittableList;
+use crate::sampler::Sampler;
+use crate::fingerprint::Fingerprint;
+use crate::rtweekend::{Shared, INFINITY_F64};
+use crate::aov::{FirstHit, material_id};
+use crate::material::Matte;
+use crate::stats::RayCounters;
+use crate::diagnostics::{Finding, Problem};
+use crate::path_debug::{Bounce, BounceHit};
+
+/// Side channel an integrator fills for one camera sample, next to the radiance.
+#[derive(Clone, Debug, Default)]
+pub struct SampleRecord {
+    /// rays traced and paths finished so far
+    pub counters: RayCounters,
+    /// set by the camera when it records AOVs
+    pub want_first_hit: bool,
+    pub first_hit: Option<FirstHit>,
+    /// set by the camera for a transparent film: background seen by the camera ray
+    /// comes out black, and shadow catchers only record what falls onto them
+    pub transparent: bool,
+    /// coverage of the sample, 0 for background and holdouts
+    pub alpha: f64,
+    /// set by the camera when it splits the beauty into light passes
+    pub want_light_paths: bool,
+    /// every contribution to the radiance with its path, spelled as in a light path
+    /// expression; together they sum to the radiance
+    pub light_paths: Vec<(Vec<u8>, Color)>,
+    /// set by the camera in diagnostics mode: check rays, hits, scattering weights and
+    /// light for NaN, infinite and negative values
+    pub check: bool,
+    /// the first bad value met in this sample
+    pub finding: Option<Finding>,
+    /// set by `Camera::trace_sample` to log every bounce of the path
+    pub want_bounces: bool,
+    pub bounces: Vec<Bounce>,
+}
+
+impl SampleRecord {
+    fn record_first_hit(&mut self, r: &Ray, rec: &HitRecord) {
+        if self.want_first_hit && self.first_hit.is_none() {
+            self.first_hit = Some(FirstHit::from_record(r, rec));
+        }
+    }
+
+    /// note `values` met at `bounce` if they are bad and nothing was found yet;
+    /// `signed` values such as positions and directions may be negative
+    fn check(&mut self, bounce: usize, what: &'static str, values: Vec3, signed: bool, rec: Option<&HitRecord>) {
+        if !self.check || self.finding.is_some() {
+            return;
+        }
+        if let Some(problem) = Problem::find(values, signed) {
+            self.finding = Some(Finding { bounce: Some(bounce), what, problem, material: rec.map(material_id) });
+        }
+    }
+
+    /// log a new ray of the path
+    fn log_ray(&mut self, depth: usize, ray: &Ray, throughput: Color) {
+        if self.want_bounces {
+            self.bounces.push(Bounce {
+                depth,
+                origin: ray.origin,
+                direction: ray.direction,
+                kind: ray.kind,
+                hit: None,
+                radiance: BLACK,
+                scatter: None,
+                throughput,
+                end: None,
+            });
+        }
+    }
+
+    /// update the bounce logged last
+    fn log(&mut self, update: impl FnOnce(&mut Bounce)) {
+        if !self.want_bounces {
+            return;
+        }
+        if let Some(bounce) = self.bounces.last_mut() {
+            update(bounce);
+        }
+    }
+
+    fn record_light_path(&mut self, path: &[u8], end: &[u8], color: Color) {
+        if self.want_light_paths && color != BLACK {
+            self.light_paths.push(([path, end].concat(), color));
+        }
+    }
+}
+
+/// Turns a camera ray into a radiance estimate; the camera delegates to one of these.
+pub trait Integrator: Send + Sync {
+    /// radiance arriving along `r` from `world` and its lights; `record` collects ray
+    /// counts and first-hit data
+    fn li(&self, r: &Ray, world: &HittableList, sampler: &mut dyn Sampler, record: &mut SampleRecord) -> Color;
+
+    /// identify the integrator and its settings for checkpoint compatibility; the
+    /// default only writes the type name
+    fn fingerprint(&self, fp: &mut Fingerprint) { fp.write_str(std::any::type_name::<Self>()) }
+}
+
+const BLACK: Color = Color(Vec3 { x: 0.0, y: 0.0, z: 0.0 });
+
+/// sky gradient seen by rays that leave the scene
+pub fn background(r: &Ray) -> Color {
+    let unit_direction: Vec3 = r.direction.unit_vector();
+    let a: f64 = 0.5 * (unit_direction.y + 1.0);
+    let c: Vec3 = (1.0 - a) * Vec3::new(1.0, 1.0, 1.0)
+        + a * Vec3::new(0.5, 0.7, 1.0);
+    Color::from(c)
+}
+
+/// The unidirectional path tracer, traced as an iterative path with a throughput
+/// accumulator. After `rr_min_depth` bounces Russian roulette ends paths with
+/// probability tied to the throughput (reweighting survivors, so the estimate stays
+/// unbiased); `max_depth`, when set, is only a hard safety cap. The scene's explicit
+/// lights are added at every diffuse vertex through shadow rays.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PathIntegrator {
+    pub max_depth: Option<usize>,
+    pub rr_min_depth: usize,
+}
+
+impl PathIntegrator {
+    pub fn new(max_depth: Option<usize>, rr_min_depth: usize) -> Self { Self { max_depth, rr_min_depth } }
+
+    /// radiance along `r` and the number of bounces the path survived
+    pub fn trace(&self, r: &Ray, world: &HittableList, sampler: &mut dyn Sampler, record: &mut SampleRecord) -> (Color, usize) {
+        let (color, bounces) = self.walk(*r, Color::new(1.0, 1.0, 1.0), 0, world, sampler, record);
+        record.counters.count_path(bounces);
+        (color, bounces)
+    }
+
+    /// continue a path `depth` bounces in, carrying `throughput`
+    fn walk(&self, mut ray: Ray, mut throughput: Color, mut depth: usize, world: &HittableList, sampler: &mut dyn Sampler, record: &mut SampleRecord) -> (Color, usize) {
+        let mut radiance = BLACK;
+        // scatter events so far, for light path expressions
+        let mut path = if record.want_light_paths { vec![b'C'] } else { Vec::new() };
+
+        loop {
+            if self.max_depth.is_some_and(|max| depth >= max) {
+                record.log(|b| b.end = Some("max depth"));
+                return (radiance, depth);
+            }
+            record.counters.count_ray(ray.kind);
+            record.log_ray(depth, &ray, throughput);
+            record.check(depth, "ray origin", ray.origin, true, None);
+            record.check(depth, "ray direction", ray.direction, true, None);
+
+            let Some(rec) = world.hit(&ray, 0.001, INFINITY_F64) else {
+                record.log(|b| b.end = Some("escaped"));
+                if depth == 0 {
+                    record.alpha = 0.0;
+                    if record.transparent {
+                        return (BLACK, depth);
+                    }
+                }
+                let sky = throughput * background(&ray);
+                record.log(|b| b.radiance = sky);
+                record.check(depth, "sky", sky.0, false, None);
+                record.record_light_path(&path, b"B", sky);
+                return (radiance + sky, depth);
+            };
+            record.log(|b| {
+                b.hit = Some(BounceHit {
+                    object_id: rec.object_id,
+                    t: rec.t,
+                    point: rec.p,
+                    normal: rec.normal,
+                    front_face: rec.front_face,
+                    material_id: material_id(&rec),
+                });
+            });
+            record.check(depth, "hit point", rec.p, true, Some(&rec));
+            record.check(depth, "normal", rec.normal, true, Some(&rec));
+            if depth == 0 {
+                record.record_first_hit(&ray, &rec);
+                record.alpha = 1.0;
+                match rec.mat.matte() {
+                    Some(Matte::Holdout) => {
+                        record.log(|b| b.end = Some("holdout"));
+                        record.alpha = 0.0;
+                        return (BLACK, depth);
+                    }
+                    Some(Matte::ShadowCatcher) if record.transparent => {
+                        record.log(|b| b.end = Some("shadow catcher, continued as its own path"));
+                        return self.catch_shadow(&ray, &rec, world, sampler, record);
+                    }
+                    _ => {}
+                }
+            }
+            if !world.lights.is_empty() {
+                let direct = throughput * direct_light(&rec, world, record).0;
+                record.check(depth, "direct light", direct.0, false, Some(&rec));
+                record.log(|b| b.radiance += direct);
+                // only diffuse lobes have a BRDF for lights to reach
+                record.record_light_path(&path, b"DL", direct);
+                radiance += direct;
+            }
+            // `scatter` returns Some((attenuation_color, scattered_ray)), or None when
+            // the material absorbed the ray
+            let Some((attenuation, scattered)) = rec.mat.scatter(&ray, &rec, sampler) else {
+                record.log(|b| b.end = Some("absorbed"));
+                return (radiance, depth);
+            };
+            record.log(|b| b.scatter = Some((attenuation, scattered.direction)));
+            record.check(depth, "attenuation", attenuation.0, false, Some(&rec));
+            throughput = throughput * attenuation;
+            if record.want_light_paths {
+                path.push(rec.mat.event(&scattered).symbol());
+            }
+            depth += 1;
+
+            if depth >= self.rr_min_depth {
+                // capped below 1 so lossless paths (total internal reflection) still end
+                let survive = throughput.max_component().min(0.95);
+                if sampler.get_1d() >= survive {
+                    record.log(|b| b.end = Some("russian roulette"));
+                    return (radiance, depth);
+                }
+                throughput = throughput / survive;
+            }
+            record.log(|b| b.throughput = throughput);
+            record.check(depth, "throughput", throughput.0, false, Some(&rec));
+            ray = scattered;
+        }
+    }
+
+    /// Shadow catcher seen by the camera: shades the surface once with the rest of
+    /// the scene and once as if it were alone (lights unshadowed, the bounce escaping
+    /// to the sky). Alpha is the fraction of light the scene takes away; the color
+    /// is whatever light it adds, such as reflections of bright objects, and goes to
+    /// light passes as diffuse indirect light (`CDDL`).
+    fn catch_shadow(&self, ray: &Ray, rec: &HitRecord, world: &HittableList, sampler: &mut dyn Sampler, record: &mut SampleRecord) -> (Color, usize) {
+        let want_light_paths = std::mem::replace(&mut record.want_light_paths, false);
+        let (mut full, mut alone) = direct_light(rec, world, record);
+        let mut depth = 0;
+        if let Some((attenuation, scattered)) = rec.mat.scatter(ray, rec, sampler) {
+            let (indirect, bounces) = self.walk(scattered, attenuation, 1, world, sampler, record);
+            full += indirect;
+            alone += attenuation * background(&scattered);
+            depth = bounces;
+        }
+        let reference = alone.luminance();
+        record.alpha = if reference > 1e-9 { (1.0 - full.luminance() / reference).clamp(0.0, 1.0) } else { 0.0 };
+        let added = (full - alone).clamp(0.0, INFINITY_F64);
+        record.want_light_paths = want_light_paths;
+        record.record_light_path(b"CD", b"DL", added);
+        (added, depth)
+    }
+}
+
+/// light reflected toward the path at `rec` from the lights linked to its object,
+/// and the same without shadow rays
+fn direct_light(rec: &HitRecord, world: &HittableList, record: &mut SampleRecord) -> (Color, Color) {
+    let (mut sum, mut unoccluded) = (BLACK, BLACK);
+    for light in world.lights.iter().filter(|light| light.link.illuminates(rec.object_id)) {
+        let (wi, distance, irradiance) = light.sample(rec.p);
+        let cos = rec.normal.dot(&wi);
+        if cos <= 0.0 {
+            continue;
+        }
+        let Some(f) = rec.mat.brdf(rec, &wi) else { return (BLACK, BLACK) };
+        let contribution = f * irradiance * cos;
+        unoccluded += contribution;
+        record.counters.count_ray(RayKind::Shadow);
+        if world.hit(&Ray::with_kind(rec.p, wi, RayKind::Shadow), 0.001, distance).is_none() {
+            sum += contribution;
+        }
+    }
+    (sum, unoccluded)
+}
+
+impl Integrator for PathIntegrator {
+    fn li(&self, r: &Ray, world: &HittableList, sampler: &mut dyn Sampler, record: &mut SampleRecord) -> Color {
+        self.trace(r, world, sampler, record).0
+    }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) {
+        fp.write_str("PathIntegrator");
+        fp.write_u64(self.max_depth.map_or(0, |d| d as u64 + 1));
+        fp.write_u64(self.rr_min_depth as u64);
+    }
+}
+
+/// which first-hit quantity a `DebugIntegrator` shows
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DebugView {
+    /// shading normal mapped from [-1, 1] to [0, 1]
+    ShadingNormal,
+    Albedo,
+    /// distance from the camera, white at the lens fading to black at `max_distance`
+    Depth { max_distance: f64 },
+    /// fraction of a cosine-weighted hemisphere left unoccluded within `radius`
+    AmbientOcclusion { radius: f64 },
+    /// heatmap of path-tracer bounces, red at `max_bounces`
+    BounceCount { max_bounces: usize },
+    /// stable false color per distinct material
+    MaterialId,
+}
+
+/// Quick views of the scene for triage; misses come out black.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DebugIntegrator {
+    pub view: DebugView,
+    /// the paths the bounce-count view counts, usually the camera's own settings
+    pub path: PathIntegrator,
+}
+
+impl DebugIntegrator {
+    pub fn new(view: DebugView, path: PathIntegrator) -> Self { Self { view, path } }
+
+    /// `name` as accepted on the command line; `param` is the AO radius, depth range or bounce cap
+    pub fn parse(name: &str, param: Option<f64>, path: PathIntegrator) -> Option<Self> {
+        let view = match name {
+            "normals" | "shading-normals" => DebugView::ShadingNormal,
+            "albedo" => DebugView::Albedo,
+            "depth" => DebugView::Depth { max_distance: param.unwrap_or(10.0) },
+            "ao" => DebugView::AmbientOcclusion { radius: param.unwrap_or(1.0) },
+            "bounces" => DebugView::BounceCount { max_bounces: param.map_or(16, |p| p as usize) },
+            "material-id" => DebugView::MaterialId,
+            _ => return None,
+        };
+        Some(Self::new(view, path))
+    }
+}
+
+fn normal_color(n: &Vec3) -> Color {
+    Color::from(0.5 * (n.unit_vector() + Vec3::new(1.0, 1.0, 1.0)))
+}
+
+impl Integrator for DebugIntegrator {
+    fn li(&self, r: &Ray, world: &HittableList, sampler: &mut dyn Sampler, record: &mut SampleRecord) -> Color {
+        if let DebugView::BounceCount { max_bounces } = self.view {
+            let (_, bounces) = self.path.trace(r, world, sampler, record);
+            return Color::heatmap(bounces as f64 / max_bounces.max(1) as f64);
+        }
+
+        record.counters.count_ray(r.kind);
+        let Some(rec) = world.hit(r, 0.001, INFINITY_F64) else {
+            record.alpha = 0.0;
+            return BLACK;
+        };
+        record.record_first_hit(r, &rec);
+        record.alpha = 1.0;
+        match self.view {
+            DebugView::ShadingNormal => normal_color(&rec.normal),
+            DebugView::Albedo => rec.mat.albedo(),
+            DebugView::Depth { max_distance } => {
+                let distance = rec.t * r.direction.length();
+                let v = 1.0 - (distance / max_distance).clamp(0.0, 1.0);
+                Color::new(v, v, v)
+            }
+            DebugView::AmbientOcclusion { radius } => {
+                let (u, v) = sampler.get_2d();
+                let mut direction = rec.normal + Vec3::unit_vector_from_sample(u, v);
+                if direction.near_zero() {
+                    direction = rec.normal;
+                }
+                record.counters.count_ray(RayKind::Shadow);
+                let occlusion_ray = Ray::with_kind(rec.p, direction.unit_vector(), RayKind::Shadow);
+                if world.hit(&occlusion_ray, 0.001, radius).is_some() { BLACK } else { Color::new(1.0, 1.0, 1.0) }
+            }
+            DebugView::MaterialId => Color::from_hash(material_id(&rec) as u64),
+            DebugView::BounceCount { .. } => unreachable!(),
+        }
+    }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) {
+        fp.write_str(&format!("DebugIntegrator {:?}", self.view));
+        self.path.fingerprint(fp);
+    }
+}
+
+/// handle type stored on the camera
+pub type IntegratorPtr = Shared<dyn Integrator>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::Light;
+    use crate::material::{Holdout, Lambertian, MaterialPtr, ShadowCatcher};
+    use crate::sampler::SamplerKind;
+    use crate::scene::SceneNode;
+    use crate::sphere::Sphere;
+    use crate::vec3::Point3;
+
+    /// mean radiance of `n` samples of `integrator` along `ray`
+    fn mean_radiance(integrator: &dyn Integrator, world: &HittableList, ray: &Ray, n: usize) -> Color {
+        let mut sampler = SamplerKind::default().build(3, n);
+        let mut record = SampleRecord::default();
+        (0..n).fold(BLACK, |sum, k| {
+            sampler.start_pixel_sample(0, 0, k);
+            sum + integrator.li(ray, world, sampler.as_mut(), &mut record) / n as f64
+        })
+    }
+
+    #[test]
+    fn russian_roulette_matches_a_deep_fixed_depth() {
+        // a bright sphere on bright ground, so light bounces between them a lot
+        let mut world = HittableList::new();
+        world.push(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, Shared::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)))));
+        world.push(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, Shared::new(Lambertian::new(Color::new(0.9, 0.7, 0.5)))));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, -0.3, -1.0));
+
+        let fixed = mean_radiance(&PathIntegrator::new(Some(64), usize::MAX), &world, &ray, 20_000);
+        let roulette = mean_radiance(&PathIntegrator::new(None, 1), &world, &ray, 20_000);
+        assert!((fixed - roulette).0.length() < 0.02 * fixed.0.length(), "{:?} vs {:?}", fixed, roulette);
+    }
+
+    #[test]
+    fn max_depth_caps_the_path() {
+        // inside a white sphere nothing escapes and roulette never comes into play
+        let mut world = HittableList::new();
+        world.push(Sphere::new(Point3::new(0.0, 0.0, 0.0), 10.0, Shared::new(Lambertian::new(Color::new(1.0, 1.0, 1.0)))));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut sampler = SamplerKind::default().build(3, 16);
+        for max_depth in [1, 4, 9] {
+            let path = PathIntegrator::new(Some(max_depth), usize::MAX);
+            let mut record = SampleRecord::default();
+            for k in 0..16 {
+                sampler.start_pixel_sample(0, 0, k);
+                assert_eq!(path.trace(&ray, &world, sampler.as_mut(), &mut record), (BLACK, max_depth));
+            }
+            assert_eq!(record.counters.rays(), 16 * max_depth as u64);
+        }
+    }
+
+    #[test]
+    fn debug_views_show_depth_occlusion_and_material_ids() {
+        let red: MaterialPtr = Shared::new(Lambertian::new(Color::new(0.8, 0.1, 0.1)));
+        let paint: MaterialPtr = Shared::new(Lambertian::new(Color::new(0.8, 0.1, 0.1)));
+        let mut world = HittableList::new();
+        world.push(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, red.clone()));
+        world.push(Sphere::new(Point3::new(0.0, 0.52, 0.0), 0.5, paint.clone()));
+        world.name_material(&red, "red");
+        world.name_material(&paint, "paint");
+        let path = PathIntegrator::new(Some(50), 3);
+        let view = |view| DebugIntegrator::new(view, path);
+        let down = |x: f64| Ray::new(Point3::new(x, 4.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+
+        // the ground is about 4 units below the ray origin
+        let depth = mean_radiance(&view(DebugView::Depth { max_distance: 16.0 }), &world, &down(5.0), 1);
+        assert!((depth - Color::new(0.75, 0.75, 0.75)).0.length() < 2e-3, "{:?}", depth);
+        let sky = Ray::new(Point3::new(0.0, 4.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(mean_radiance(&view(DebugView::Depth { max_distance: 16.0 }), &world, &sky, 1), BLACK);
+
+        let ao = view(DebugView::AmbientOcclusion { radius: 10.0 });
+        assert_eq!(mean_radiance(&ao, &world, &down(5.0), 256), Color::new(1.0, 1.0, 1.0));
+        let under = Ray::new(Point3::new(0.3, 0.01, 0.0), Vec3::new(-0.1, -1.0, 0.0));
+        assert!(mean_radiance(&ao, &world, &under, 256).luminance() < 0.5, "the sphere shades the ground under it");
+
+        // equal parameters, but two materials
+        let ids = view(DebugView::MaterialId);
+        assert_ne!(mean_radiance(&ids, &world, &down(5.0), 1), mean_radiance(&ids, &world, &down(0.0), 1));
+        assert_eq!(mean_radiance(&ids, &world, &down(5.0), 1), mean_radiance(&ids, &world, &down(-5.0), 1));
+    }
+
+    #[test]
+    fn light_links_choose_what_a_light_shades() {
+        let gray: Shared<dyn Hittable> = Shared::new(Sphere::new(Point3::zero(), 0.5, Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        let mut root = SceneNode::new("root");
+        root.add(SceneNode::with_object("ball", gray.clone())).unwrap();
+        root.add(SceneNode::with_object("prop", gray)).unwrap().transform.translation = Vec3::new(3.0, 0.0, 0.0);
+        root.add(SceneNode::with_light("lamp", Light::point(Point3::new(1.5, 4.0, 0.0), Color::new(10.0, 10.0, 10.0)))).unwrap();
+
+        let shade = |root: &SceneNode, x: f64| {
+            let world = root.compile().unwrap();
+            let rec = world.hit(&Ray::new(Point3::new(x, 4.0, 0.0), Vec3::new(0.0, -1.0, 0.0)), 0.001, INFINITY_F64).unwrap();
+            direct_light(&rec, &world, &mut SampleRecord::default()).0
+        };
+        let (ball, prop) = (shade(&root, 0.0), shade(&root, 3.0));
+        assert!(ball.luminance() > 0.0 && prop.luminance() > 0.0);
+
+        root.find_mut("lamp").unwrap().light_exclude = vec!["ball".to_string()];
+        assert_eq!((shade(&root, 0.0), shade(&root, 3.0)), (BLACK, prop));
+        root.find_mut("lamp").unwrap().light_include = vec!["ball".to_string()];
+        assert_eq!((shade(&root, 0.0), shade(&root, 3.0)), (ball, BLACK), "include wins over exclude");
+    }
+
+    #[test]
+    fn bounce_view_counts_paths_with_the_given_depths() {
+        let mut world = HittableList::new();
+        world.push(Sphere::new(Point3::new(0.0, 0.0, 0.0), 10.0, Shared::new(Lambertian::new(Color::new(1.0, 1.0, 1.0)))));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let bounces = DebugIntegrator::new(DebugView::BounceCount { max_bounces: 8 }, PathIntegrator::new(Some(2), usize::MAX));
+        assert_eq!(mean_radiance(&bounces, &world, &ray, 1), Color::heatmap(0.25));
+    }
+
+    #[test]
+    fn transparent_film_keeps_only_what_falls_on_the_shadow_catcher() {
+        let mut world = HittableList::new();
+        world.push(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Shared::new(ShadowCatcher::new(Color::new(0.5, 0.5, 0.5)))));
+        world.push(Sphere::new(Point3::new(0.0, 1.0, 0.0), 0.5, Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))));
+        world.push(Sphere::new(Point3::new(-3.0, 1.0, 0.0), 0.5, Shared::new(Holdout)));
+        world.lights.push(Light::point(Point3::new(0.0, 3.0, 0.0), Color::new(10.0, 10.0, 10.0)));
+
+        let path = PathIntegrator::new(None, 3);
+        let mut sampler = SamplerKind::default().build(7, 256);
+        let mut mean_alpha = |ray: Ray, transparent: bool| {
+            let mut record = SampleRecord { transparent, ..SampleRecord::default() };
+            let (mut color, mut alpha) = (BLACK, 0.0);
+            for k in 0..256 {
+                sampler.start_pixel_sample(0, 0, k);
+                color += path.li(&ray, &world, sampler.as_mut(), &mut record) / 256.0;
+                alpha += record.alpha / 256.0;
+            }
+            (color, alpha)
+        };
+
+        let sky = Ray::new(Point3::new(0.0, 1.0, 5.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(mean_alpha(sky, true), (BLACK, 0.0));
+        assert!(mean_alpha(sky, false).0.luminance() > 0.5, "an opaque film still sees the sky");
+        let holdout = Ray::new(Point3::new(-3.0, 1.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(mean_alpha(holdout, false), (BLACK, 0.0));
+
+        let under = mean_alpha(Ray::new(Point3::new(1.0, 0.5, 0.0), Vec3::new(-1.0, -0.5, 0.0)), true).1;
+        let open = mean_alpha(Ray::new(Point3::new(5.0, 0.5, 0.0), Vec3::new(-1.0, -0.5, 0.0)), true).1;
+        assert!(under > 0.3, "shadow under the occluder: alpha {}", under);
+        assert!(open < 0.1, "open ground stays clear: alpha {}", open);
+    }
+}
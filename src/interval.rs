@@ -9,7 +9,7 @@ impl Interval {
     }
 
     pub fn surrounds(&self, x: f64) -> bool {
-        return self.min < x && x < self.max
+        self.min < x && x < self.max
     }
 
     pub fn clamp(&self, x: f64) -> f64 {
@@ -0,0 +1,414 @@
+//! Multi-element lens systems traced surface by surface (Kolb et al. 1995, as in
+//! pbrt's realistic camera).
+//!
+//! Lens space is camera space in millimeters: x right, y up, the film in the z = 0
+//! plane and the elements in front of it at negative z, so scene-bound rays travel
+//! toward -z. Vignetting, distortion and focus breathing fall out of the tracing.
+
+use std::fs;
+use std::io;
+
+use crate::vec3::{Vec3, Point3};
+
+/// exit-pupil bounds are tabulated for this many rings of film radius
+const PUPIL_INTERVALS: usize = 64;
+
+/// One refracting surface (or the aperture stop) of a lens prescription.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LensElement {
+    /// signed radius of curvature in mm, positive when the center lies toward the film;
+    /// 0 for a flat surface, which with `ior` 0 marks the aperture stop
+    pub radius: f64,
+    /// distance to the next surface toward the film (for the last one, to the film)
+    pub thickness: f64,
+    /// index of refraction between this surface and the next; 0 or 1 is air
+    pub ior: f64,
+    /// clear aperture diameter in mm
+    pub aperture: f64,
+    /// conic constant of an aspheric surface; 0 with no coefficients is a sphere
+    pub conic: f64,
+    /// aspheric coefficients of r^4, r^6, ...
+    pub aspheric: Vec<f64>,
+}
+
+impl LensElement {
+    fn is_stop(&self) -> bool { self.radius == 0.0 && self.ior == 0.0 }
+
+    fn medium(&self) -> f64 { if self.ior == 0.0 { 1.0 } else { self.ior } }
+
+    fn is_spherical(&self) -> bool { self.conic == 0.0 && self.aspheric.is_empty() }
+
+    /// surface height above the vertex plane at distance `r` from the axis, and its slope
+    fn sag(&self, r: f64) -> Option<(f64, f64)> {
+        if self.radius == 0.0 {
+            return Some((0.0, 0.0));
+        }
+        let c = 1.0 / self.radius;
+        let root = (1.0 - (1.0 + self.conic) * c * c * r * r).max(-1.0);
+        if root < 0.0 {
+            return None;
+        }
+        let mut sag = c * r * r / (1.0 + root.sqrt());
+        let mut slope = c * r / root.sqrt().max(1e-12);
+        for (k, a) in self.aspheric.iter().enumerate() {
+            let power = 2 * k as i32 + 4;
+            sag += a * r.powi(power);
+            slope += a * power as f64 * r.powi(power - 1);
+        }
+        Some((sag, slope))
+    }
+
+    /// hit point and unit surface normal (facing against `d`) of the ray o + t d with
+    /// this surface, whose vertex sits at `z`
+    fn intersect(&self, o: Point3, d: Vec3, z: f64) -> Option<(Point3, Vec3)> {
+        let (t, normal) = if self.radius == 0.0 {
+            let t = (z - o.z) / d.z;
+            (t, Vec3::new(0.0, 0.0, 1.0))
+        } else if self.is_spherical() {
+            let center = Vec3::new(0.0, 0.0, z + self.radius);
+            let oc = o - center;
+            let (a, b, c) = (d.dot(&d), 2.0 * d.dot(&oc), oc.dot(&oc) - self.radius * self.radius);
+            let disc = b * b - 4.0 * a * c;
+            if disc < 0.0 {
+                return None;
+            }
+            let (t0, t1) = ((-b - disc.sqrt()) / (2.0 * a), (-b + disc.sqrt()) / (2.0 * a));
+            // the surface near the vertex is the nearer root for a ray heading into
+            // the concave side, the farther one otherwise
+            let closer = (d.z > 0.0) ^ (self.radius < 0.0);
+            let t = if closer { t0.min(t1) } else { t0.max(t1) };
+            (t, (o + t * d - center).unit_vector())
+        } else {
+            // Newton's method on the axial distance between ray and surface, from the vertex plane
+            let mut t = (z - o.z) / d.z;
+            for _ in 0..32 {
+                let p = o + t * d;
+                let r = p.x.hypot(p.y);
+                let (sag, slope) = self.sag(r)?;
+                let g = p.z - z - sag;
+                let dr = if r > 0.0 { (p.x * d.x + p.y * d.y) / r } else { 0.0 };
+                let step = g / (d.z - slope * dr);
+                t -= step;
+                if step.abs() < 1e-10 {
+                    break;
+                }
+            }
+            let p = o + t * d;
+            let r = p.x.hypot(p.y);
+            let (sag, slope) = self.sag(r)?;
+            if (p.z - z - sag).abs() > 1e-6 {
+                return None;
+            }
+            let (nx, ny) = if r > 0.0 { (-slope * p.x / r, -slope * p.y / r) } else { (0.0, 0.0) };
+            (t, Vec3::new(nx, ny, 1.0).unit_vector())
+        };
+        if t <= 0.0 {
+            return None;
+        }
+        let p = o + t * d;
+        let half = self.aperture / 2.0;
+        if p.x * p.x + p.y * p.y > half * half {
+            return None;
+        }
+        let normal = if normal.dot(&d) > 0.0 { -normal } else { normal };
+        Some((p, normal))
+    }
+}
+
+/// refraction of unit `d` at `n` (facing against `d`); None on total internal reflection
+fn refract(d: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = -n.dot(&d);
+    let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+    if k < 0.0 {
+        return None;
+    }
+    Some(eta * d + (eta * cos_i - k.sqrt()) * n)
+}
+
+/// bounds (x0, y0, x1, y1) of the exit pupil in the rear-vertex plane, for film
+/// points on the +x axis
+type PupilBounds = Option<(f64, f64, f64, f64)>;
+
+/// A lens prescription, focused and with its exit pupil tabulated for one film size
+/// by `prepare`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LensSystem {
+    /// surfaces from the front (object side) to the rear (film side)
+    pub elements: Vec<LensElement>,
+    /// extra rear-element-to-film distance that brings `prepare`'s focus distance into focus
+    focus_shift: f64,
+    film_width: f64,
+    film_height: f64,
+    exit_pupil: Vec<PupilBounds>,
+    /// pupil area times unobstructed fraction at the film center, for normalizing weights
+    center_throughput: f64,
+}
+
+impl LensSystem {
+    pub fn new(elements: Vec<LensElement>) -> Result<Self, String> {
+        if elements.is_empty() {
+            return Err("lens has no elements".into());
+        }
+        if elements.iter().any(|e| e.aperture <= 0.0) {
+            return Err("lens element apertures must be > 0".into());
+        }
+        Ok(Self { elements, focus_shift: 0.0, film_width: 0.0, film_height: 0.0, exit_pupil: Vec::new(), center_throughput: 0.0 })
+    }
+
+    /// Parse a lens table: one surface per line, front to rear, as
+    /// `radius thickness ior aperture [conic A4 A6 ...]` in mm (aperture is a diameter).
+    /// `#` starts a comment.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut elements = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values = line.split_whitespace()
+                .map(|v| v.parse::<f64>().map_err(|e| format!("line {}: {}", n + 1, e)))
+                .collect::<Result<Vec<f64>, String>>()?;
+            if values.len() < 4 {
+                return Err(format!("line {}: expected radius, thickness, ior and aperture", n + 1));
+            }
+            elements.push(LensElement {
+                radius: values[0],
+                thickness: values[1],
+                ior: values[2],
+                aperture: values[3],
+                conic: values.get(4).copied().unwrap_or(0.0),
+                aspheric: values.get(5..).map_or(Vec::new(), |a| a.to_vec()),
+            });
+        }
+        Self::new(elements)
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+    }
+
+    fn thickness(&self, k: usize) -> f64 {
+        self.elements[k].thickness + if k + 1 == self.elements.len() { self.focus_shift } else { 0.0 }
+    }
+
+    fn rear_z(&self) -> f64 { -self.thickness(self.elements.len() - 1) }
+
+    fn front_z(&self) -> f64 { -(0..self.elements.len()).map(|k| self.thickness(k)).sum::<f64>() }
+
+    /// trace a ray leaving the film side toward the scene; None if it is blocked
+    pub fn trace_from_film(&self, mut o: Point3, d: Vec3) -> Option<(Point3, Vec3)> {
+        let mut d = d.unit_vector();
+        let mut z = 0.0;
+        for k in (0..self.elements.len()).rev() {
+            let element = &self.elements[k];
+            z -= self.thickness(k);
+            let (p, n) = element.intersect(o, d, z)?;
+            o = p;
+            if !element.is_stop() {
+                let outside = if k > 0 { self.elements[k - 1].medium() } else { 1.0 };
+                d = refract(d, n, element.medium() / outside)?;
+            }
+        }
+        Some((o, d))
+    }
+
+    /// trace a ray from the scene side toward the film; None if it is blocked
+    fn trace_from_scene(&self, mut o: Point3, d: Vec3) -> Option<(Point3, Vec3)> {
+        let mut d = d.unit_vector();
+        let mut z = self.front_z();
+        for (k, element) in self.elements.iter().enumerate() {
+            let (p, n) = element.intersect(o, d, z)?;
+            o = p;
+            if !element.is_stop() {
+                let outside = if k > 0 { self.elements[k - 1].medium() } else { 1.0 };
+                d = refract(d, n, outside / element.medium())?;
+            }
+            z += self.thickness(k);
+        }
+        Some((o, d))
+    }
+
+    /// Focus at `focus_dist` mm from the film and tabulate the exit pupil for a film of
+    /// `film_width` x `film_height` mm.
+    pub fn prepare(&mut self, film_width: f64, film_height: f64, focus_dist: f64) -> Result<(), String> {
+        self.focus_shift = 0.0;
+        self.film_width = film_width;
+        self.film_height = film_height;
+        self.focus(focus_dist)?;
+        self.compute_exit_pupil();
+        Ok(())
+    }
+
+    /// principal plane and focal point (z) of the side rays exit on, from a paraxial
+    /// ray entering parallel to the axis at height `h`
+    fn cardinal_points(h: f64, out: (Point3, Vec3)) -> Option<(f64, f64)> {
+        let (o, d) = out;
+        if d.x.abs() < 1e-12 {
+            return None;
+        }
+        let focal = o.z + (-o.x / d.x) * d.z;
+        let principal = o.z + ((h - o.x) / d.x) * d.z;
+        Some((principal, focal))
+    }
+
+    fn focus(&mut self, focus_dist: f64) -> Result<(), String> {
+        let h = 0.001 * self.film_width.hypot(self.film_height).max(1.0);
+        let unfocusable = || "lens cannot form an image".to_string();
+        let out = self.trace_from_scene(Point3::new(h, 0.0, self.front_z() - 1.0), Vec3::new(0.0, 0.0, 1.0)).ok_or_else(unfocusable)?;
+        let (image_principal, image_focal) = Self::cardinal_points(h, out).ok_or_else(unfocusable)?;
+        let out = self.trace_from_film(Point3::new(h, 0.0, self.rear_z() + 1.0), Vec3::new(0.0, 0.0, -1.0)).ok_or_else(unfocusable)?;
+        let (object_principal, _) = Self::cardinal_points(h, out).ok_or_else(unfocusable)?;
+        let f = image_focal - image_principal;
+
+        // moving the lens toward the scene by `shift` must satisfy the thin-lens
+        // equation between its principal planes: 1/(a - shift) + 1/(shift - p) = 1/f
+        let a = object_principal + focus_dist;
+        let p = image_principal;
+        let disc = (a - p) * (a - p - 4.0 * f);
+        if f <= 0.0 || disc < 0.0 {
+            return Err(format!("lens cannot focus at {} mm", focus_dist));
+        }
+        let shift = ((a + p) - disc.sqrt()) / 2.0;
+        if self.elements.last().unwrap().thickness + shift < 0.0 {
+            return Err(format!("lens cannot focus at {} mm", focus_dist));
+        }
+        self.focus_shift = shift;
+        Ok(())
+    }
+
+    fn film_half_diagonal(&self) -> f64 { self.film_width.hypot(self.film_height) / 2.0 }
+
+    /// pbrt-style exit-pupil bounds: for rings of film radius, the box of rear-plane
+    /// points that rays from the film actually get through the lens by
+    fn compute_exit_pupil(&mut self) {
+        const GRID: usize = 32;
+        let rear_radius = self.elements.last().unwrap().aperture / 2.0;
+        let extent = 1.5 * rear_radius;
+        let spacing = 2.0 * extent / GRID as f64;
+        let rear_z = self.rear_z();
+        let grid_point = |a: usize, b: usize| (-extent + (a as f64 + 0.5) * spacing, -extent + (b as f64 + 0.5) * spacing);
+
+        self.exit_pupil = (0..PUPIL_INTERVALS).map(|k| {
+            let mut bounds: PupilBounds = None;
+            for s in 0..4 {
+                let r = self.film_half_diagonal() * (k as f64 + s as f64 / 3.0) / PUPIL_INTERVALS as f64;
+                let film = Point3::new(r, 0.0, 0.0);
+                for a in 0..GRID {
+                    for b in 0..GRID {
+                        let (x, y) = grid_point(a, b);
+                        if self.trace_from_film(film, Point3::new(x, y, rear_z) - film).is_some() {
+                            bounds = Some(match bounds {
+                                None => (x, y, x, y),
+                                Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                            });
+                        }
+                    }
+                }
+            }
+            bounds.map(|(x0, y0, x1, y1)| (x0 - spacing, y0 - spacing, x1 + spacing, y1 + spacing))
+        }).collect();
+
+        // fraction of the central box that is clear, so the image center keeps unit gain
+        self.center_throughput = match self.exit_pupil[0] {
+            Some((x0, y0, x1, y1)) => {
+                let film = Point3::new(0.0, 0.0, 0.0);
+                let mut clear = 0;
+                for a in 0..GRID {
+                    for b in 0..GRID {
+                        let x = x0 + (a as f64 + 0.5) / GRID as f64 * (x1 - x0);
+                        let y = y0 + (b as f64 + 0.5) / GRID as f64 * (y1 - y0);
+                        clear += self.trace_from_film(film, Point3::new(x, y, rear_z) - film).is_some() as usize;
+                    }
+                }
+                (x1 - x0) * (y1 - y0) * clear as f64 / (GRID * GRID) as f64
+            }
+            None => 0.0,
+        };
+    }
+
+    /// Camera ray for film position (sx, sy) in [-0.5, 0.5]^2 (right, up) of the
+    /// image and the 2D sample (u, v) on the exit pupil: origin and direction in
+    /// lens space, and the radiometric weight (1 at the image center, lower where
+    /// the pupil shrinks or light falls off). None if the lens blocks the ray.
+    pub fn sample_ray(&self, sx: f64, sy: f64, u: f64, v: f64) -> Option<(Point3, Vec3, f64)> {
+        if self.center_throughput <= 0.0 {
+            return None;
+        }
+        // the lens inverts the image, so the sample for screen (sx, sy) sits opposite
+        let film = Point3::new(-sx * self.film_width, -sy * self.film_height, 0.0);
+        let r = film.x.hypot(film.y);
+        let k = ((r / self.film_half_diagonal() * PUPIL_INTERVALS as f64) as usize).min(PUPIL_INTERVALS - 1);
+        let (x0, y0, x1, y1) = self.exit_pupil[k]?;
+        let (bx, by) = (x0 + u * (x1 - x0), y0 + v * (y1 - y0));
+        let (sin, cos) = if r > 0.0 { (film.y / r, film.x / r) } else { (0.0, 1.0) };
+        let rear = Point3::new(cos * bx - sin * by, sin * bx + cos * by, self.rear_z());
+        let d = (rear - film).unit_vector();
+        let (o, d_out) = self.trace_from_film(film, d)?;
+        let cos4 = d.z.powi(4);
+        Some((o, d_out, cos4 * (x1 - x0) * (y1 - y0) / self.center_throughput))
+    }
+
+    /// effective focal length in mm, from a paraxial ray
+    pub fn focal_length(&self) -> Option<f64> {
+        let h = 0.01;
+        let out = self.trace_from_scene(Point3::new(h, 0.0, self.front_z() - 1.0), Vec3::new(0.0, 0.0, 1.0))?;
+        let (principal, focal) = Self::cardinal_points(h, out)?;
+        Some(focal - principal)
+    }
+
+    /// vertical field of view in degrees at the current focus, from the ray through
+    /// the middle of the exit pupil at the top edge of the film
+    pub fn vfov(&self) -> Option<f64> {
+        let (_, d, _) = self.sample_ray(0.0, 0.5, 0.5, 0.5)?;
+        Some(2.0 * d.y.abs().atan2(-d.z).to_degrees())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOUBLE_GAUSS: &str = "
+        # radius thickness ior aperture
+        29.475  3.76   1.67   25.2
+        84.83   0.12   1      25.2
+        19.275  4.025  1.67   23
+        40.77   3.275  1.699  23
+        12.75   5.705  1      18
+        0       4.5    0      17.1   # stop
+        -14.495 1.18   1.603  17
+        40.77   6.065  1.658  20
+        -20.385 0.19   1      20
+        437.065 3.22   1.717  20
+        -39.73  0      1      20
+    ";
+
+    #[test]
+    fn double_gauss_focuses_and_images_a_point() {
+        let mut lens = LensSystem::parse(DOUBLE_GAUSS).unwrap();
+        let f = lens.focal_length().unwrap();
+        assert!((f - 50.0).abs() < 2.0, "focal length {}", f);
+        // a zero-coefficient asphere traces exactly like the sphere it reduces to
+        let aspheric = LensSystem::parse(&DOUBLE_GAUSS.replacen("3.76   1.67   25.2", "3.76   1.67   25.2  0  0", 1)).unwrap();
+        assert!(!aspheric.elements[0].is_spherical());
+        assert!((aspheric.focal_length().unwrap() - f).abs() < 1e-6);
+
+        lens.prepare(36.0, 24.0, 2000.0).unwrap();
+        // rays from one film point through different parts of the pupil converge
+        // on one point of the plane in focus
+        let mut hits = Vec::new();
+        for (u, v) in [(0.5, 0.5), (0.3, 0.6), (0.7, 0.4), (0.45, 0.3)] {
+            if let Some((o, d, weight)) = lens.sample_ray(0.1, 0.05, u, v) {
+                assert!(weight > 0.0);
+                let t = (-2000.0 - o.z) / d.z;
+                hits.push(o + t * d);
+            }
+        }
+        assert!(hits.len() >= 3);
+        for p in &hits[1..] {
+            assert!((*p - hits[0]).length() < 2.0, "{:?} vs {:?}", p, hits[0]);
+        }
+        // upright image: a film sample right of center sees the scene right of the axis
+        assert!(hits[0].x > 0.0 && hits[0].y > 0.0);
+    }
+}
@@ -7,4 +7,31 @@ pub mod sphere;
 pub mod rtweekend;
 pub mod camera;
 pub mod interval;
-pub mod material;
\ No newline at end of file
+pub mod material;
+pub mod rng;
+pub mod sampler;
+pub mod adaptive;
+pub mod scheduler;
+pub mod fingerprint;
+pub mod film;
+pub mod checkpoint;
+pub mod integrator;
+pub mod exr;
+pub mod aov;
+pub mod denoise;
+pub mod filter;
+pub mod projection;
+pub mod physical;
+pub mod aperture;
+pub mod lens;
+pub mod calibration;
+pub mod png;
+pub mod instance;
+pub mod animation;
+pub mod scene;
+pub mod light;
+pub mod lpe;
+pub mod cryptomatte;
+pub mod stats;
+pub mod diagnostics;
+pub mod path_debug;
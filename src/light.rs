@@ -0,0 +1,73 @@
+//! Explicit lights, sampled directly by the path tracer with shadow rays, and the
+//! light links that restrict which objects each one illuminates.
+//!
+//! The sky stays as it was: an environment every object sees, outside light linking.
+
+use crate::color::Color;
+use crate::fingerprint::Fingerprint;
+use crate::rtweekend::INFINITY_F64;
+use crate::vec3::{Vec3, Point3};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    /// radiant intensity `color` in every direction, falling off with distance squared
+    Point { position: Point3 },
+    /// irradiance `color` from infinitely far away, traveling along `direction`
+    Directional { direction: Vec3 },
+}
+
+/// Which objects (by `HitRecord::object_id`) a light illuminates.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub enum LightLink {
+    #[default]
+    All,
+    Include(Vec<u32>),
+    Exclude(Vec<u32>),
+}
+
+impl LightLink {
+    pub fn illuminates(&self, object_id: u32) -> bool {
+        match self {
+            LightLink::All => true,
+            LightLink::Include(ids) => ids.contains(&object_id),
+            LightLink::Exclude(ids) => !ids.contains(&object_id),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Color,
+    pub link: LightLink,
+}
+
+impl Light {
+    pub fn point(position: Point3, color: Color) -> Self {
+        Self { kind: LightKind::Point { position }, color, link: LightLink::All }
+    }
+
+    pub fn directional(direction: Vec3, color: Color) -> Self {
+        Self { kind: LightKind::Directional { direction: direction.unit_vector() }, color, link: LightLink::All }
+    }
+
+    /// unit direction from `p` toward the light, the distance a shadow ray must stay
+    /// clear for, and the irradiance arriving at `p` on a surface facing the light
+    pub fn sample(&self, p: Point3) -> (Vec3, f64, Color) {
+        match self.kind {
+            LightKind::Point { position } => {
+                let to_light = position - p;
+                let distance2 = to_light.length_squared().max(1e-12);
+                let distance = distance2.sqrt();
+                (to_light / distance, distance, self.color / distance2)
+            }
+            LightKind::Directional { direction } => (-direction, INFINITY_F64, self.color),
+        }
+    }
+
+    pub fn fingerprint(&self, fp: &mut Fingerprint) {
+        fp.write_str(&format!("{:?}", self.kind));
+        fp.write_vec3(&self.color);
+        fp.write_str(&format!("{:?}", self.link));
+    }
+}
@@ -0,0 +1,245 @@
+//! Light path expressions: regular expressions over the events of a light path,
+//! used to split the beauty into named passes.
+//!
+//! A path is spelled from the camera outward, one symbol per event:
+//!
+//! - `C` the camera
+//! - `D`, `G`, `S`, `T` diffuse, glossy, specular and transmission scattering
+//! - `L` an explicit light reached by a shadow ray
+//! - `B` the background sky
+//!
+//! Expressions combine them with `.` (any event), `[DG]` and `[^D]` (sets), `( )`,
+//! `|`, and the repetitions `*`, `+` and `?`. Whitespace is ignored, so the
+//! diffuse-indirect pass may be written `C D .+ [LB]`.
+
+use crate::color::Color;
+
+const SYMBOLS: &[u8] = b"CDGSTLB";
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    /// one event from `symbols`, or any event not in it when `negate`
+    Set { symbols: Vec<u8>, negate: bool },
+    Seq(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat { node: Box<Node>, min: usize, max: Option<usize> },
+}
+
+impl Node {
+    /// every position reachable by matching this node from any of `starts`
+    fn ends(&self, path: &[u8], starts: &[usize]) -> Vec<usize> {
+        let mut out = Vec::new();
+        match self {
+            Node::Set { symbols, negate } => {
+                for &k in starts {
+                    if path.get(k).is_some_and(|e| symbols.contains(e) != *negate) {
+                        out.push(k + 1);
+                    }
+                }
+            }
+            Node::Seq(nodes) => {
+                out = starts.to_vec();
+                for node in nodes {
+                    out = node.ends(path, &out);
+                }
+            }
+            Node::Alt(nodes) => {
+                for node in nodes {
+                    out.extend(node.ends(path, starts));
+                }
+            }
+            Node::Repeat { node, min, max } => {
+                let mut frontier = starts.to_vec();
+                for n in 0.. {
+                    if n >= *min {
+                        out.extend(&frontier);
+                    }
+                    if max.is_some_and(|max| n >= max) || frontier.is_empty() {
+                        break;
+                    }
+                    frontier = node.ends(path, &frontier);
+                    // past the minimum, positions already reached add nothing new
+                    if n >= *min {
+                        frontier.retain(|k| !out.contains(k));
+                    }
+                }
+            }
+        }
+        out.sort_unstable();
+        out.dedup();
+        out
+    }
+}
+
+/// A parsed light path expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lpe {
+    root: Node,
+}
+
+impl Lpe {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let chars: Vec<u8> = expression.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        let mut parser = Parser { chars: &chars, pos: 0 };
+        let root = parser.alt()?;
+        if parser.pos < chars.len() {
+            return Err(format!("unexpected '{}' in light path expression '{}'", chars[parser.pos] as char, expression));
+        }
+        Ok(Self { root })
+    }
+
+    /// whether the whole of `path` (e.g. `b"CDL"`) matches
+    pub fn matches(&self, path: &[u8]) -> bool {
+        self.root.ends(path, &[0]).contains(&path.len())
+    }
+}
+
+struct Parser<'a> {
+    chars: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> { self.chars.get(self.pos).copied() }
+
+    fn alt(&mut self) -> Result<Node, String> {
+        let mut options = vec![self.seq()?];
+        while self.peek() == Some(b'|') {
+            self.pos += 1;
+            options.push(self.seq()?);
+        }
+        Ok(if options.len() == 1 { options.pop().unwrap() } else { Node::Alt(options) })
+    }
+
+    fn seq(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while self.peek().is_some_and(|c| c != b'|' && c != b')') {
+            nodes.push(self.repeat()?);
+        }
+        Ok(Node::Seq(nodes))
+    }
+
+    fn repeat(&mut self) -> Result<Node, String> {
+        let mut node = self.atom()?;
+        while let Some(c @ (b'*' | b'+' | b'?')) = self.peek() {
+            self.pos += 1;
+            let (min, max) = match c {
+                b'*' => (0, None),
+                b'+' => (1, None),
+                _ => (0, Some(1)),
+            };
+            node = Node::Repeat { node: Box::new(node), min, max };
+        }
+        Ok(node)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let c = self.peek().ok_or("light path expression ends early")?;
+        self.pos += 1;
+        match c {
+            b'.' => Ok(Node::Set { symbols: Vec::new(), negate: true }),
+            b'(' => {
+                let node = self.alt()?;
+                if self.peek() != Some(b')') {
+                    return Err("missing ')' in light path expression".to_string());
+                }
+                self.pos += 1;
+                Ok(node)
+            }
+            b'[' => {
+                let negate = self.peek() == Some(b'^');
+                if negate {
+                    self.pos += 1;
+                }
+                let mut symbols = Vec::new();
+                loop {
+                    match self.peek() {
+                        Some(b']') => break,
+                        Some(s) if SYMBOLS.contains(&s) => symbols.push(s),
+                        Some(s) => return Err(format!("unknown event '{}' in light path expression", s as char)),
+                        None => return Err("missing ']' in light path expression".to_string()),
+                    }
+                    self.pos += 1;
+                }
+                self.pos += 1;
+                Ok(Node::Set { symbols, negate })
+            }
+            s if SYMBOLS.contains(&s) => Ok(Node::Set { symbols: vec![s], negate: false }),
+            s => Err(format!("unknown event '{}' in light path expression", s as char)),
+        }
+    }
+}
+
+/// the standard passes, which partition every path and so sum to the beauty
+pub const STANDARD_PASSES: [(&str, &str); 5] = [
+    ("diffuse_direct", "C D [LB]"),
+    ("diffuse_indirect", "C D .+ [LB]"),
+    ("specular", "C [GS] .* [LB]"),
+    ("transmission", "C T .* [LB]"),
+    // light seen directly; with no emissive surfaces that is the sky
+    ("emission", "C [LB]"),
+];
+
+/// A named pass collecting the contributions of the paths its expression matches.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightPass {
+    pub name: String,
+    pub expression: String,
+    lpe: Lpe,
+}
+
+impl LightPass {
+    pub fn new(name: &str, expression: &str) -> Result<Self, String> {
+        Ok(Self { name: name.to_string(), expression: expression.to_string(), lpe: Lpe::parse(expression)? })
+    }
+
+    /// `name=expression`, or the name of a standard pass
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec.split_once('=') {
+            Some((name, expression)) => Self::new(name.trim(), expression),
+            None => {
+                let (name, expression) = STANDARD_PASSES.iter().find(|(name, _)| *name == spec.trim())
+                    .ok_or_else(|| format!("unknown light pass '{}'", spec))?;
+                Self::new(name, expression)
+            }
+        }
+    }
+
+    /// every standard pass
+    pub fn standard() -> Vec<Self> {
+        STANDARD_PASSES.iter().map(|(name, expression)| Self::new(name, expression).unwrap()).collect()
+    }
+
+    pub fn matches(&self, path: &[u8]) -> bool { self.lpe.matches(path) }
+}
+
+/// add `color` to the value of each pass in `passes` whose expression matches `path`
+pub fn accumulate(passes: &[LightPass], values: &mut [Color], path: &[u8], color: Color) {
+    for (pass, value) in passes.iter().zip(values.iter_mut()) {
+        if pass.matches(path) {
+            *value += color;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expressions_match_whole_paths_and_standard_passes_partition_them() {
+        let lpe = Lpe::parse("C D .+ [LB]").unwrap();
+        assert!(lpe.matches(b"CDDL") && lpe.matches(b"CDSTB"));
+        assert!(!lpe.matches(b"CDL") && !lpe.matches(b"CDD"));
+        assert!(Lpe::parse("C(D|G)*[^S]").unwrap().matches(b"CDGDB"));
+        assert!(Lpe::parse("CD?L").unwrap().matches(b"CL"));
+        assert!(Lpe::parse("C[DX]L").is_err() && Lpe::parse("C(DL").is_err());
+
+        let passes = LightPass::standard();
+        for path in [&b"CB"[..], b"CDL", b"CDB", b"CDDL", b"CGDB", b"CSL", b"CTTB", b"CTSDL"] {
+            assert_eq!(passes.iter().filter(|p| p.matches(path)).count(), 1, "{}", String::from_utf8_lossy(path));
+        }
+        assert!(LightPass::parse("rim=C G L").unwrap().matches(b"CGL"));
+        assert!(LightPass::parse("glow").is_err());
+    }
+}
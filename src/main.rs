@@ -1,59 +1,166 @@
 // mod vec3;
 // use vec3::Color;
 
-mod color;
-mod vec3;
-mod ray;
-mod hittable;
-mod hittable_list;
-mod sphere;
-mod rtweekend;
-mod camera;
-mod interval;
-mod material;
-use color::Color;
-
-use vec3::Vec3;
-use vec3::Point3;
-use ray::Ray;
-use hittable::{Hittable, HitRecord};
-use hittable_list::HittableList;
-use sphere::Sphere;
-use rtweekend::{Shared, INFINITY_F64, PI, degrees_to_radians};
-use camera::Camera;
-use material::{Lambertian,Metal, Dielectric};
-
-use std::sync::Arc;
-
+use raytrace_rs::color::Color;
+use raytrace_rs::vec3::Vec3;
+use raytrace_rs::vec3::Point3;
+use raytrace_rs::hittable_list::HittableList;
+use raytrace_rs::sphere::Sphere;
+use raytrace_rs::rtweekend::Shared;
+use raytrace_rs::camera::{Camera, ProgressiveSettings};
+use raytrace_rs::sampler::SamplerKind;
+use raytrace_rs::adaptive::AdaptiveSampling;
+use raytrace_rs::scheduler::{TileOrder, RenderEvent};
+use raytrace_rs::scene::SceneNode;
+use raytrace_rs::hittable::Hittable;
+use raytrace_rs::integrator::{DebugIntegrator, PathIntegrator};
+use raytrace_rs::aov::AovOutput;
+use raytrace_rs::denoise::DenoiseSettings;
+use raytrace_rs::filter::PixelFilter;
+use raytrace_rs::projection::Projection;
+use raytrace_rs::physical::{PhysicalCamera, parse_shutter};
+use raytrace_rs::aperture::{ApertureShape, ApertureMask};
+use raytrace_rs::lens::LensSystem;
+use raytrace_rs::calibration::CalibratedCamera;
+use raytrace_rs::animation::{Animation, CameraTracks, Interpolation, MaterialTracks, ObjectTracks, Track};
+use raytrace_rs::rtweekend::PI;
+use raytrace_rs::material::{Lambertian, Metal, Dielectric, Holdout, ShadowCatcher, MaterialPtr};
+use raytrace_rs::film::AlphaMode;
+use raytrace_rs::lpe::LightPass;
+use raytrace_rs::light::Light;
+use raytrace_rs::ray::Visibility;
 
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::Duration;
 
-use std::cmp::max;
+/// value following `name` on the command line, e.g. `--seed 42`
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter().position(|arg| arg == name).and_then(|k| args.get(k + 1).cloned())
+}
 
-use std::fs::File;
-use std::io::{self, Write, BufWriter};
+/// `arg_value` parsed as a `T`; a value that doesn't parse is an error, not a default
+fn parsed_arg<T: FromStr>(name: &str) -> io::Result<Option<T>> {
+    arg_value(name).map(|v| {
+        v.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("{} wants a number, got '{}'", name, v)))
+    }).transpose()
+}
 
-use std::time::Duration;
+/// Demo animation of the default scene over `first..=last`: the camera circles the
+/// center sphere once while it bobs, and the gold sphere's polish fades. The loop
+/// closes one frame after `last`, so the sequence repeats seamlessly.
+fn turntable(cam: &Camera, scene: &SceneNode, first: usize, last: usize) -> Animation {
+    let (first, last) = (first as f64, last as f64 + 1.0);
+    let offset = cam.lookfrom - cam.lookat;
+    let radius = offset.x.hypot(offset.z);
+    let orbit: Vec<(f64, Point3)> = (0..=8).map(|k| {
+        let phi = offset.z.atan2(offset.x) + k as f64 * PI / 4.0;
+        (first + (last - first) * k as f64 / 8.0, cam.lookat + Vec3::new(radius * phi.cos(), offset.y, radius * phi.sin()))
+    }).collect();
+    let middle = (first + last) / 2.0;
+    let bob = Track::from_values(Interpolation::Bezier, &[(first, Vec3::zero()), (middle, Vec3::new(0.0, 0.3, 0.0)), (last, Vec3::zero())]);
+    let polish = MaterialTracks::Metal {
+        albedo: Track::from_values(Interpolation::Linear, &[(first, Color::new(0.8, 0.6, 0.2))]),
+        fuzz: Track::from_values(Interpolation::Linear, &[(first, 0.0), (last, 0.5)]),
+    };
+    let paths = scene.object_paths();
+    let index = |path: &str| paths.iter().position(|p| p == path);
+    let objects = [
+        index("center").map(|index| ObjectTracks { index, translation: Some(bob), ..ObjectTracks::default() }),
+        index("gold").map(|index| ObjectTracks { index, material: Some(polish), ..ObjectTracks::default() }),
+    ];
+    Animation {
+        camera: CameraTracks { lookfrom: Some(Track::from_values(Interpolation::CatmullRom, &orbit)), ..CameraTracks::default() },
+        objects: objects.into_iter().flatten().collect(),
+    }
+}
 
 fn main() -> io::Result<()> {
 
     let multithreaded = std::env::args().any(|arg| arg == "--mt" || arg == "-mt");
+    let seed: u64 = parsed_arg("--seed")?.unwrap_or(0);
+    let resume = arg_value("--resume");
+    let progressive: Option<usize> = parsed_arg("--progressive")?;
+    let time_budget = parsed_arg::<f64>("--time-budget")?
+        .map(|seconds| Duration::try_from_secs_f64(seconds).ok().filter(|d| !d.is_zero()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("--time-budget wants a positive number of seconds, got '{}'", seconds))
+        }))
+        .transpose()?;
     // Worls
 
-    let mut world: HittableList = HittableList::new();
+    let mut scene = SceneNode::new("scene");
 
     let mat_ground = Shared::new(Lambertian::new(Color::new(0.4, 0.4, 0.0)));
     let mat_center = Shared::new(Lambertian::new(Color::new(0.1, 0.2, 0.5)));
     // let mat_left   = Shared::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.3));
-    let mat_left = Shared::new(Dielectric::new((1.50)));
+    let mat_left = Shared::new(Dielectric::new(1.50));
     let mat_bubble = Shared::new(Dielectric::new(1.00 / 1.50));
     let mat_right  = Shared::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.0));
+    // names of the materials in the ID mattes
+    let mut material_names: Vec<(&str, MaterialPtr)> = vec![
+        ("ground", mat_ground.clone()),
+        ("blue", mat_center.clone()),
+        ("glass", mat_left.clone()),
+        ("air", mat_bubble.clone()),
+        ("gold", mat_right.clone()),
+    ];
 
-    world.add(Shared::new(crate::sphere::Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, mat_center)));
-    world.add(Shared::new(crate::sphere::Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, mat_ground)));
-    world.add(Shared::new(crate::sphere::Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.5, mat_left)));
-    world.add(Shared::new(crate::sphere::Sphere::new(Point3::new(-1.0, 0.0, -1.0), 0.4, mat_bubble)));
+    let sphere = |center: Point3, radius: f64, mat| -> Shared<dyn Hittable> { Shared::new(Sphere::new(center, radius, mat)) };
+    let node_error = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+    scene.add(SceneNode::with_object("center", sphere(Point3::new(0.0, 0.0, -1.0), 0.5, mat_center))).map_err(node_error)?;
+    scene.add(SceneNode::with_object("ground", sphere(Point3::new(0.0, -100.5, -1.0), 100.0, mat_ground))).map_err(node_error)?;
+    let glass = scene.add(SceneNode::with_object("glass", sphere(Point3::new(-1.0, 0.0, -1.0), 0.5, mat_left))).map_err(node_error)?;
+    glass.add(SceneNode::with_object("bubble", sphere(Point3::new(-1.0, 0.0, -1.0), 0.4, mat_bubble))).map_err(node_error)?;
+    scene.add(SceneNode::with_object("gold", sphere(Point3::new(1.0, 0.0, -1.0), 0.5, mat_right))).map_err(node_error)?;
 
-    world.add(Shared::new(crate::sphere::Sphere::new(Point3::new(1.0, 0.0, -1.0), 0.5, mat_right)));
+    // e.g. --hide glass/bubble,gold
+    for path in arg_value("--hide").iter().flat_map(|paths| paths.split(',')) {
+        scene.find_mut(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no scene node '{}'", path)))?
+            .visible = false;
+    }
+    // e.g. --alpha straight --holdout glass --shadow-catcher ground
+    let mattes: [(&str, MaterialPtr); 2] = [
+        ("--holdout", Shared::new(Holdout)),
+        ("--shadow-catcher", Shared::new(ShadowCatcher::new(Color::new(0.5, 0.5, 0.5)))),
+    ];
+    for (flag, material) in mattes {
+        material_names.push((flag.trim_start_matches('-'), material.clone()));
+        for path in arg_value(flag).iter().flat_map(|paths| paths.split(',')) {
+            scene.find_mut(path)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no scene node '{}'", path)))?
+                .material = Some(material.clone());
+        }
+    }
+    // e.g. --visibility center=shadow,reflection:glass=all
+    for entry in arg_value("--visibility").iter().flat_map(|entries| entries.split(':')) {
+        let (path, kinds) = entry.split_once('=')
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("--visibility wants PATH=KINDS, got '{}'", entry)))?;
+        let visibility = Visibility::parse(kinds)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown ray kinds '{}'", kinds)))?;
+        scene.find_mut(path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no scene node '{}'", path)))?
+            .visibility = visibility;
+    }
+    // e.g. --point-light 2,3,1,20 --light-exclude ground
+    if let Some(spec) = arg_value("--point-light") {
+        let v: Vec<f64> = spec.split(',').map(|s| s.trim().parse()).collect::<Result<_, _>>().unwrap_or_default();
+        let [x, y, z, intensity] = v[..] else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("--point-light wants X,Y,Z,INTENSITY, got '{}'", spec)));
+        };
+        let key = scene.add(SceneNode::with_light("key", Light::point(Point3::new(x, y, z), Color::new(intensity, intensity, intensity))))
+            .map_err(node_error)?;
+        let paths = |name| arg_value(name).map(|paths| paths.split(',').map(str::to_string).collect()).unwrap_or_default();
+        key.light_include = paths("--light-include");
+        key.light_exclude = paths("--light-exclude");
+    } else if arg_value("--light-include").is_some() || arg_value("--light-exclude").is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--light-include and --light-exclude link the --point-light"));
+    }
+    let mut world: HittableList = scene.compile().map_err(node_error)?;
+    for (name, material) in &material_names {
+        world.name_material(material, name);
+    }
 
     // let mut cam = Camera::default();
     // cam.image_width = 800;
@@ -74,8 +181,205 @@ fn main() -> io::Result<()> {
 
     cam.defocus_angle = 10.0;
     cam.focus_dist = 3.4;
+    cam.seed = seed;
+    if let Some(spp) = parsed_arg("--spp")? {
+        cam.samples_per_pixel = spp;
+    }
+    if let Some(depth) = arg_value("--max-depth") {
+        // "none" leaves path termination entirely to Russian roulette
+        cam.max_depth = match depth.as_str() {
+            "none" => None,
+            _ => Some(depth.parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("--max-depth wants a number or 'none', got '{}'", depth))
+            })?),
+        };
+    }
+    if let Some(depth) = parsed_arg("--rr-depth")? {
+        cam.rr_min_depth = depth;
+    }
+    if let Some(name) = arg_value("--integrator").filter(|name| name != "path") {
+        // --debug-param: AO radius, depth range or bounce cap
+        let param = parsed_arg("--debug-param")?;
+        let integrator = DebugIntegrator::parse(&name, param, PathIntegrator::new(cam.max_depth, cam.rr_min_depth))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown integrator '{}'", name)))?;
+        cam.integrator = Some(Shared::new(integrator));
+    }
+    if let Some(name) = arg_value("--sampler") {
+        cam.sampler = SamplerKind::parse(&name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown sampler '{}'", name)))?;
+    }
+    if let Some(threshold) = parsed_arg("--adaptive")? {
+        let min_spp = parsed_arg("--min-spp")?.unwrap_or(16);
+        let mut adaptive = AdaptiveSampling::new(min_spp, threshold);
+        adaptive.heatmap_path = arg_value("--heatmap");
+        cam.adaptive = Some(adaptive);
+    }
+    if let Some(size) = parsed_arg("--tile-size")? {
+        cam.scheduler.tile_size = size;
+    }
+    if let Some(name) = arg_value("--tile-order") {
+        cam.scheduler.order = TileOrder::parse(&name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown tile order '{}'", name)))?;
+    }
+    cam.scheduler.threads = parsed_arg("--threads")?;
+    if let Some(name) = arg_value("--alpha") {
+        cam.alpha = Some(AlphaMode::parse(&name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown alpha mode '{}'", name)))?);
+    }
+    // e.g. --passes standard, or --passes diffuse_direct,rim=C G L
+    if let Some(specs) = arg_value("--passes") {
+        cam.light_passes = if specs == "standard" {
+            LightPass::standard()
+        } else {
+            specs.split(',').map(LightPass::parse).collect::<Result<_, _>>()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        };
+    }
+    // e.g. --cryptomatte 6: ranks of object and material mattes per pixel
+    if let Some(ranks) = parsed_arg::<usize>("--cryptomatte")? {
+        if ranks == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "--cryptomatte wants at least one rank"));
+        }
+        cam.cryptomatte = Some(ranks);
+    }
+    if let Some(name) = arg_value("--aovs") {
+        cam.aov_output = Some(AovOutput::parse(&name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown AOV output '{}'", name)))?);
+    }
+    if let Some(focal_length) = parsed_arg("--focal-length")? {
+        let mut physical = PhysicalCamera { focal_length_mm: focal_length, ..PhysicalCamera::default() };
+        if let Some(sensor) = arg_value("--sensor") {
+            (physical.sensor_width_mm, physical.sensor_height_mm) = sensor.split_once('x')
+                .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("--sensor wants WIDTHxHEIGHT in mm, got '{}'", sensor)))?;
+        }
+        if let Some(n) = parsed_arg("--f-number")? {
+            physical.f_number = n;
+        }
+        if let Some(shutter) = arg_value("--shutter") {
+            physical.shutter_time = parse_shutter(&shutter)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("--shutter wants seconds or 1/N, got '{}'", shutter)))?;
+        }
+        if let Some(iso) = parsed_arg("--iso")? {
+            physical.iso = iso;
+        }
+        cam.physical = Some(physical);
+    }
+    cam.autofocus = std::env::args().any(|arg| arg == "--autofocus");
+    if let Some(blades) = parsed_arg("--aperture-blades")? {
+        let rotation = parsed_arg("--aperture-rotation")?.unwrap_or(0.0);
+        cam.aperture.shape = ApertureShape::Polygon { blades, rotation };
+    }
+    if let Some(inner) = parsed_arg("--aperture-annular")? {
+        cam.aperture.shape = ApertureShape::Annular { inner };
+    }
+    if let Some(path) = arg_value("--aperture-mask") {
+        cam.aperture.shape = ApertureShape::Mask(Shared::new(ApertureMask::load(&path)?));
+    }
+    if let Some(amount) = parsed_arg("--cat-eye")? {
+        cam.aperture.cat_eye = amount;
+    }
+    if let Some(path) = arg_value("--lens") {
+        cam.lens = Some(LensSystem::load(&path)?);
+    }
+    if let Some(path) = arg_value("--calibration") {
+        cam.calibration = Some(CalibratedCamera::load(&path)?);
+    }
+    if let Some(name) = arg_value("--projection") {
+        let fov = parsed_arg("--fov")?;
+        cam.projection = Projection::parse(&name, fov)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown projection '{}'", name)))?;
+        match cam.projection {
+            Projection::Equirectangular => cam.aspect_ratio = 2.0,
+            Projection::CubeMap => cam.aspect_ratio = 1.5,
+            _ => {}
+        }
+    }
+    if let Some(name) = arg_value("--filter") {
+        let radius = parsed_arg("--filter-radius")?;
+        cam.filter = Some(PixelFilter::parse(&name, radius)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown filter '{}'", name)))?);
+    }
+    // --stats prints ray counts, path lengths and tile times; --stats-json also writes image.stats.json
+    cam.print_stats = std::env::args().any(|arg| arg == "--stats");
+    cam.stats_json = std::env::args().any(|arg| arg == "--stats-json");
+    // e.g. --check-samples --signal-color 1,0,1
+    cam.check_samples = std::env::args().any(|arg| arg == "--check-samples");
+    if let Some(spec) = arg_value("--signal-color") {
+        let v: Vec<f64> = spec.split(',').map(|s| s.trim().parse()).collect::<Result<_, _>>().unwrap_or_default();
+        let [r, g, b] = v[..] else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("--signal-color wants R,G,B, got '{}'", spec)));
+        };
+        cam.check_samples = true;
+        cam.signal_color = Some(Color::new(r, g, b));
+    }
+    if std::env::args().any(|arg| arg == "--denoise") {
+        let mut settings = DenoiseSettings::default();
+        if let Some(n) = parsed_arg("--denoise-iterations")? {
+            settings.iterations = n;
+        }
+        if let Some(sigma) = parsed_arg("--denoise-sigma")? {
+            settings.sigma_color = sigma;
+        }
+        if let Some(blend) = parsed_arg("--denoise-blend")? {
+            settings.blend = blend;
+        }
+        cam.denoise = Some(settings);
+    }
+    if multithreaded && progressive.is_none() && resume.is_none() && time_budget.is_none() {
+        cam.scheduler.on_event = Some(Shared::new(|event: &RenderEvent| {
+            if let RenderEvent::Progress { percent } = event {
+                eprint!("\rProgress: {:>5.1}%", percent);
+                if *percent >= 100.0 {
+                    eprintln!();
+                }
+                let _ = io::stderr().flush();
+            }
+        }));
+    }
+
+    // e.g. --trace-pixel 200,112,3 logs sample 3 of that pixel instead of rendering
+    if let Some(spec) = arg_value("--trace-pixel") {
+        let v: Vec<usize> = spec.split(',').map(|s| s.trim().parse()).collect::<Result<_, _>>().unwrap_or_default();
+        let (i, j, sample) = match v[..] {
+            [i, j] => (i, j, 0),
+            [i, j, sample] => (i, j, sample),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("--trace-pixel wants X,Y[,SAMPLE], got '{}'", spec))),
+        };
+        let trace = cam.trace_sample(&world, i, j, sample)?;
+        print!("{}", trace.text(&world));
+        let path = format!("trace_{}_{}_{}.json", i, j, sample);
+        std::fs::write(&path, trace.json(&world))?;
+        eprintln!("Wrote {}", path);
+        return Ok(());
+    }
 
-    if multithreaded {
+    if let Some(range) = arg_value("--frames") {
+        let (first, last) = range.split_once('-')
+            .and_then(|(a, b)| Some((a.parse::<usize>().ok()?, b.parse::<usize>().ok()?)))
+            .filter(|(a, b)| a <= b)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("--frames wants FIRST-LAST, got '{}'", range)))?;
+        let animation = turntable(&cam, &scene, first, last);
+        eprintln!("Rendering frames {}-{}...", first, last);
+        cam.render_sequence(&world, &animation, first..=last)?;
+    } else if let Some(budget) = time_budget {
+        eprintln!("Rendering for {:?}...", budget);
+        cam.render_time_budget(&world, budget, multithreaded)?;
+    } else if progressive.is_some() || resume.is_some() {
+        let settings = ProgressiveSettings {
+            pass_samples: progressive.unwrap_or(16),
+            checkpoint_path: resume.clone().or_else(|| arg_value("--checkpoint")),
+            checkpoint_interval: parsed_arg("--checkpoint-every")?.unwrap_or(1),
+            multithreaded,
+            ..ProgressiveSettings::default()
+        };
+        if resume.is_some() {
+            cam.resume_progressive(&world, &settings)?;
+        } else {
+            eprintln!("Rendering progressively...");
+            cam.render_progressive(&world, &settings)?;
+        }
+    } else if multithreaded {
         eprintln!("Rendering multithreaded...");
         cam.render_multithreaded(&world)?;
     } else {
@@ -1,14 +1,72 @@
 
-use crate::ray::Ray;
+use crate::ray::{Ray, RayKind};
 use crate::hittable::HitRecord;
 use crate::color::Color;
-use crate::vec3::{Vec3, Point3};
-use crate::rtweekend::{Shared, random_double};
+use crate::vec3::Vec3;
+use crate::rtweekend::{Shared, PI};
+use crate::sampler::Sampler;
+use crate::fingerprint::Fingerprint;
 
 /// object-safe trait representing a material (like a C++ abstract base)
 pub trait Material: Send + Sync {
     /// return Some((attenuation, scattered_ray)) if the ray scatters, else None
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)>;
+
+    /// feed the material parameters into a scene fingerprint; the default only writes
+    /// the type name
+    fn fingerprint(&self, fp: &mut Fingerprint) { fp.write_str(std::any::type_name::<Self>()) }
+
+    /// base color, for the albedo debug view
+    fn albedo(&self) -> Color { Color::new(1.0, 1.0, 1.0) }
+
+    /// BRDF for light arriving from direction `wi`; None for materials that only
+    /// scatter specularly, which explicit lights cannot reach
+    fn brdf(&self, _rec: &HitRecord, _wi: &Vec3) -> Option<Color> { None }
+
+    /// compositing role of the surface when the camera sees it directly
+    fn matte(&self) -> Option<Matte> { None }
+
+    /// how `scattered`, as returned by `scatter`, left the surface
+    fn event(&self, scattered: &Ray) -> ScatterEvent {
+        match scattered.kind {
+            RayKind::Diffuse => ScatterEvent::Diffuse,
+            RayKind::Refraction => ScatterEvent::Transmission,
+            _ => ScatterEvent::Specular,
+        }
+    }
+}
+
+/// Kind of scattering at a path vertex, as spelled in light path expressions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScatterEvent {
+    Diffuse,
+    /// blurred reflection
+    Glossy,
+    /// mirror reflection
+    Specular,
+    /// refraction into or out of a surface
+    Transmission,
+}
+
+impl ScatterEvent {
+    /// the event's symbol in a light path expression
+    pub fn symbol(self) -> u8 {
+        match self {
+            ScatterEvent::Diffuse => b'D',
+            ScatterEvent::Glossy => b'G',
+            ScatterEvent::Specular => b'S',
+            ScatterEvent::Transmission => b'T',
+        }
+    }
+}
+
+/// Surfaces that stand in for the plate photography a render is composited over.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Matte {
+    /// cuts the surface out of the image: black with zero alpha
+    Holdout,
+    /// transparent except for the shadows and reflections other objects cast onto it
+    ShadowCatcher,
 }
 
 /// runtime handle type: use Box for single ownership, or Arc (Shared) to share between threads
@@ -21,16 +79,26 @@ impl Lambertian {
     pub fn new(a: Color) -> Self { Self { albedo: a } }
 }
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let (u, v) = sampler.get_2d();
+        let mut scatter_direction = rec.normal + Vec3::unit_vector_from_sample(u, v);
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
         // let scattered = Ray::new(rec.p + rec.normal * 1e-4, scatter_direction);
-        let scattered = Ray::new(rec.p, scatter_direction);
+        let scattered = Ray::with_kind(rec.p, scatter_direction, RayKind::Diffuse);
 
         Some((self.albedo, scattered))
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) {
+        fp.write_str("Lambertian");
+        fp.write_vec3(&self.albedo);
+    }
+
+    fn albedo(&self) -> Color { self.albedo }
+
+    fn brdf(&self, _rec: &HitRecord, _wi: &Vec3) -> Option<Color> { Some(self.albedo / PI) }
 }
 
 
@@ -42,15 +110,16 @@ impl Metal {
     pub fn new(a: Color, fuzz: f64) -> Self { Self { albedo: a, fuzz: fuzz.min(1.0)} }
 }
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let (u, v) = sampler.get_2d();
         let mut reflected = Vec3::reflect(&r_in.direction, &rec.normal);
-        reflected = reflected.unit_vector() + (self.fuzz * Vec3::random_unit_vector());
+        reflected = reflected.unit_vector() + (self.fuzz * Vec3::unit_vector_from_sample(u, v));
         // let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
         // if scatter_direction.near_zero() {
         //     scatter_direction = rec.normal;
         // }
         // let scattered = Ray::new(rec.p + rec.normal * 1e-4, scatter_direction);
-        let scattered = Ray::new(rec.p, reflected);
+        let scattered = Ray::with_kind(rec.p, reflected, RayKind::Reflection);
         if scattered.direction.dot(&rec.normal) > 0.0{
             Some((self.albedo, scattered))
         } else {
@@ -58,6 +127,18 @@ impl Material for Metal {
         }
         // Some((self.albedo, scattered))
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) {
+        fp.write_str("Metal");
+        fp.write_vec3(&self.albedo);
+        fp.write_f64(self.fuzz);
+    }
+
+    fn albedo(&self) -> Color { self.albedo }
+
+    fn event(&self, _scattered: &Ray) -> ScatterEvent {
+        if self.fuzz > 0.0 { ScatterEvent::Glossy } else { ScatterEvent::Specular }
+    }
 }            
 
 pub struct Dielectric {
@@ -65,7 +146,7 @@ pub struct Dielectric {
 
 }
 impl Dielectric {
-    pub fn new(refraction_index: f64) -> Self { Self { refraction_index: refraction_index } }
+    pub fn new(refraction_index: f64) -> Self { Self { refraction_index } }
 
     fn reflectance(&self, cosine: f64, refraction_index: f64) -> f64 {
         let r0 = (( 1.0 - refraction_index) / ( 1.0 + refraction_index)).sqrt();
@@ -75,7 +156,7 @@ impl Dielectric {
 
 }
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
         let attenuation = Color::new(1.0,1.0,1.0);
         let ri = if rec.front_face { 1.0 / self.refraction_index } else { self.refraction_index };
         let unit_direction = r_in.direction.unit_vector();
@@ -83,14 +164,61 @@ impl Material for Dielectric {
         let cos_theta = (-unit_direction).dot(&rec.normal).min(1.0);
         let sin_theta = (1.0 - (cos_theta.sqrt())).sqrt();
 
-        let direction = match (ri * sin_theta) {
-            x if x > 1.0 || self.reflectance(cos_theta, ri) > random_double() => Vec3::reflect(&unit_direction, &rec.normal),
-            _ => Vec3::refract(&unit_direction, &rec.normal, ri),
+        let (direction, kind) = match ri * sin_theta {
+            x if x > 1.0 || self.reflectance(cos_theta, ri) > sampler.get_1d() => (Vec3::reflect(&unit_direction, &rec.normal), RayKind::Reflection),
+            _ => (Vec3::refract(&unit_direction, &rec.normal, ri), RayKind::Refraction),
         };
 
-        let scattered = Ray::new(rec.p, direction);
+        let scattered = Ray::with_kind(rec.p, direction, kind);
         Some((attenuation, scattered))
     }
 
+    fn fingerprint(&self, fp: &mut Fingerprint) {
+        fp.write_str("Dielectric");
+        fp.write_f64(self.refraction_index);
+    }
+}
+
+/// Cut-out for objects of the plate that should hide what is behind them; absorbs
+/// every ray.
+pub struct Holdout;
+
+impl Material for Holdout {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<(Color, Ray)> { None }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) {
+        fp.write_str("Holdout");
+    }
+
+    fn albedo(&self) -> Color { Color::new(0.0, 0.0, 0.0) }
+
+    fn matte(&self) -> Option<Matte> { Some(Matte::Holdout) }
+}
+
+/// Stand-in for plate geometry such as the floor. Seen from the camera on a
+/// transparent film it only records how much other objects darken or brighten it;
+/// otherwise, and in reflections, it is a Lambertian surface of `albedo`.
+pub struct ShadowCatcher {
+    pub albedo: Color,
+}
+
+impl ShadowCatcher {
+    pub fn new(albedo: Color) -> Self { Self { albedo } }
+}
+
+impl Material for ShadowCatcher {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        Lambertian::new(self.albedo).scatter(r_in, rec, sampler)
+    }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) {
+        fp.write_str("ShadowCatcher");
+        fp.write_vec3(&self.albedo);
+    }
+
+    fn albedo(&self) -> Color { self.albedo }
 
-}
\ No newline at end of file
+    fn brdf(&self, _rec: &HitRecord, _wi: &Vec3) -> Option<Color> { Some(self.albedo / PI) }
+
+    fn matte(&self) -> Option<Matte> { Some(Matte::ShadowCatcher) }
+}
@@ -0,0 +1,153 @@
+//! Single-sample path debugging: `Camera::trace_sample` replays one sample of one
+//! pixel exactly as the render draws it and the path tracer logs every bounce, so a
+//! firefly can be followed back to the surface, material and scattering that made it.
+//!
+//! The log prints as text or as JSON whose `segments` are line geometry (one per
+//! ray, camera outward) for loading into a viewer next to the scene.
+
+use std::fmt::Write as _;
+
+use crate::color::Color;
+use crate::cryptomatte::json_escape;
+use crate::hittable_list::HittableList;
+use crate::ray::RayKind;
+use crate::vec3::{Point3, Vec3};
+
+/// length of the segment drawn for a ray that leaves the scene
+pub const ESCAPE_LENGTH: f64 = 1.0;
+
+/// what a logged ray hit
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BounceHit {
+    pub object_id: u32,
+    pub t: f64,
+    pub point: Point3,
+    pub normal: Vec3,
+    pub front_face: bool,
+    /// `aov::material_id` of the surface
+    pub material_id: u32,
+}
+
+/// One ray of a path and what became of it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounce {
+    pub depth: usize,
+    pub origin: Point3,
+    pub direction: Vec3,
+    pub kind: RayKind,
+    /// None when the ray escaped to the sky
+    pub hit: Option<BounceHit>,
+    /// radiance gathered here, sky or direct light, already weighted by the throughput
+    pub radiance: Color,
+    /// attenuation and direction of the scattered ray; None if the path ended here
+    pub scatter: Option<(Color, Vec3)>,
+    /// throughput carried on from here, after any Russian roulette reweighting
+    pub throughput: Color,
+    /// why the path ended here, if it did
+    pub end: Option<&'static str>,
+}
+
+impl Bounce {
+    /// end point of the ray's segment
+    pub fn end_point(&self) -> Point3 {
+        match &self.hit {
+            Some(hit) => hit.point,
+            None => self.origin + ESCAPE_LENGTH * self.direction.unit_vector(),
+        }
+    }
+}
+
+/// The logged path of sample `sample` of pixel `pixel`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathTrace {
+    pub pixel: (usize, usize),
+    pub sample: usize,
+    pub seed: u64,
+    /// the sample's value as the film receives it, exposure and lens weight included
+    pub radiance: Color,
+    pub alpha: f64,
+    pub bounces: Vec<Bounce>,
+}
+
+fn v3(v: Vec3) -> String { format!("({:.6}, {:.6}, {:.6})", v.x, v.y, v.z) }
+
+/// JSON has no NaN or infinity, and those are exactly what a debugged path may hold
+fn json_number(x: f64) -> String {
+    if x.is_finite() { x.to_string() } else { format!("\"{}\"", x) }
+}
+
+fn json3(v: Vec3) -> String { format!("[{}, {}, {}]", json_number(v.x), json_number(v.y), json_number(v.z)) }
+
+fn kind_name(kind: RayKind) -> String { format!("{:?}", kind).to_lowercase() }
+
+impl PathTrace {
+    /// readable log, one block per bounce, naming objects and materials after `world`
+    pub fn text(&self, world: &HittableList) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "pixel ({}, {}) sample {} seed {}", self.pixel.0, self.pixel.1, self.sample, self.seed);
+        for b in &self.bounces {
+            let _ = writeln!(out, "bounce {} {} ray from {} dir {}", b.depth, kind_name(b.kind), v3(b.origin), v3(b.direction));
+            match &b.hit {
+                Some(hit) => {
+                    let _ = writeln!(
+                        out,
+                        "  hit {} t {:.6} at {} normal {} {} material {}",
+                        world.object_name(hit.object_id), hit.t, v3(hit.point), v3(hit.normal),
+                        if hit.front_face { "front" } else { "back" }, world.material_name(hit.material_id),
+                    );
+                }
+                None => {
+                    let _ = writeln!(out, "  escaped");
+                }
+            }
+            if b.radiance != Color::new(0.0, 0.0, 0.0) {
+                let _ = writeln!(out, "  radiance {}", v3(b.radiance.0));
+            }
+            if let Some((attenuation, direction)) = b.scatter {
+                let _ = writeln!(out, "  attenuation {} scattered {}", v3(attenuation.0), v3(direction));
+            }
+            let _ = writeln!(out, "  throughput {}", v3(b.throughput.0));
+            if let Some(end) = b.end {
+                let _ = writeln!(out, "  path ends: {}", end);
+            }
+        }
+        let _ = writeln!(out, "radiance {} alpha {}", v3(self.radiance.0), self.alpha);
+        out
+    }
+
+    /// the log as JSON, with a `segments` list of line geometry
+    pub fn json(&self, world: &HittableList) -> String {
+        let bounces: Vec<String> = self.bounces.iter().map(|b| {
+            let hit = match &b.hit {
+                Some(hit) => format!(
+                    "{{\"object\": \"{}\", \"t\": {}, \"point\": {}, \"normal\": {}, \"front_face\": {}, \"material\": \"{}\"}}",
+                    json_escape(&world.object_name(hit.object_id)), json_number(hit.t), json3(hit.point), json3(hit.normal), hit.front_face,
+                    json_escape(&world.material_name(hit.material_id)),
+                ),
+                None => "null".to_string(),
+            };
+            let (attenuation, scattered) = match b.scatter {
+                Some((attenuation, direction)) => (json3(attenuation.0), json3(direction)),
+                None => ("null".to_string(), "null".to_string()),
+            };
+            format!(
+                "    {{\"depth\": {}, \"kind\": \"{}\", \"origin\": {}, \"direction\": {}, \"hit\": {}, \"radiance\": {}, \"attenuation\": {}, \"scattered\": {}, \"throughput\": {}, \"end\": {}}}",
+                b.depth, kind_name(b.kind), json3(b.origin), json3(b.direction), hit, json3(b.radiance.0),
+                attenuation, scattered, json3(b.throughput.0), b.end.map_or("null".to_string(), |e| format!("\"{}\"", e)),
+            )
+        }).collect();
+        let segments: Vec<String> = self.bounces.iter()
+            .map(|b| format!("    {{\"from\": {}, \"to\": {}, \"kind\": \"{}\"}}", json3(b.origin), json3(b.end_point()), kind_name(b.kind)))
+            .collect();
+        let mut out = String::from("{\n");
+        let _ = writeln!(out, "  \"pixel\": [{}, {}],", self.pixel.0, self.pixel.1);
+        let _ = writeln!(out, "  \"sample\": {},", self.sample);
+        let _ = writeln!(out, "  \"seed\": {},", self.seed);
+        let _ = writeln!(out, "  \"radiance\": {},", json3(self.radiance.0));
+        let _ = writeln!(out, "  \"alpha\": {},", json_number(self.alpha));
+        let _ = writeln!(out, "  \"bounces\": [\n{}\n  ],", bounces.join(",\n"));
+        let _ = writeln!(out, "  \"segments\": [\n{}\n  ]", segments.join(",\n"));
+        out.push_str("}\n");
+        out
+    }
+}
@@ -0,0 +1,105 @@
+//! Photographic camera settings, translated into the thin-lens parameters
+//! (`vfov`, `defocus_angle`) and the exposure scale `Camera` renders with.
+
+/// A 35 mm-style camera body and lens. Scene units are taken to be
+/// `meters_per_unit` meters, which sizes the aperture against the scene.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhysicalCamera {
+    pub sensor_width_mm: f64,
+    pub sensor_height_mm: f64,
+    pub focal_length_mm: f64,
+    pub f_number: f64,
+    /// seconds the shutter stays open
+    pub shutter_time: f64,
+    pub iso: f64,
+    /// EV100 at which scene radiance comes out unchanged; brighter exposures scale it up
+    pub calibration_ev: f64,
+    pub meters_per_unit: f64,
+}
+
+impl Default for PhysicalCamera {
+    fn default() -> Self {
+        Self {
+            sensor_width_mm: 36.0,
+            sensor_height_mm: 24.0,
+            focal_length_mm: 50.0,
+            f_number: 2.8,
+            shutter_time: 1.0 / 125.0,
+            iso: 100.0,
+            calibration_ev: 10.0,
+            meters_per_unit: 1.0,
+        }
+    }
+}
+
+impl PhysicalCamera {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sensor_width_mm <= 0.0 || self.sensor_height_mm <= 0.0 {
+            return Err("sensor size must be > 0".into());
+        }
+        if self.focal_length_mm <= 0.0 {
+            return Err("focal length must be > 0".into());
+        }
+        if self.f_number <= 0.0 || self.shutter_time <= 0.0 || self.iso <= 0.0 {
+            return Err("f-number, shutter time and ISO must be > 0".into());
+        }
+        Ok(())
+    }
+
+    /// Vertical field of view in degrees for an image of `aspect_ratio`. The sensor is
+    /// fitted to the image the way a camera crops: whichever side would overflow is cut.
+    pub fn vfov(&self, aspect_ratio: f64) -> f64 {
+        let height = self.sensor_height_mm.min(self.sensor_width_mm / aspect_ratio);
+        2.0 * (height / (2.0 * self.focal_length_mm)).atan().to_degrees()
+    }
+
+    /// aperture radius in scene units: focal length over f-number, halved
+    pub fn aperture_radius(&self) -> f64 {
+        self.focal_length_mm / (2.0 * self.f_number) / 1000.0 / self.meters_per_unit
+    }
+
+    /// `Camera::defocus_angle` giving this aperture when focused at `focus_dist`
+    pub fn defocus_angle(&self, focus_dist: f64) -> f64 {
+        2.0 * (self.aperture_radius() / focus_dist).atan().to_degrees()
+    }
+
+    /// exposure value at ISO 100: log2(N^2 / t) - log2(ISO / 100)
+    pub fn ev100(&self) -> f64 {
+        (self.f_number * self.f_number / self.shutter_time).log2() - (self.iso / 100.0).log2()
+    }
+
+    /// factor applied to every radiance sample; doubles per stop of extra exposure
+    pub fn exposure(&self) -> f64 {
+        (self.calibration_ev - self.ev100()).exp2()
+    }
+}
+
+/// shutter time as "0.004" or "1/250"
+pub fn parse_shutter(text: &str) -> Option<f64> {
+    match text.split_once('/') {
+        Some((num, den)) => Some(num.trim().parse::<f64>().ok()? / den.trim().parse::<f64>().ok()?),
+        None => text.trim().parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn photographic_rules_of_thumb() {
+        let cam = PhysicalCamera { focal_length_mm: 50.0, ..PhysicalCamera::default() };
+        // a 50 mm lens on full frame sees about 27 degrees vertically at 3:2
+        assert!((cam.vfov(1.5) - 26.99).abs() < 0.01);
+        // the same sensor cropped to 16:9 loses height
+        assert!(cam.vfov(16.0 / 9.0) < cam.vfov(1.5));
+
+        // one stop less light per doubled f-number squared or halved shutter
+        let base = PhysicalCamera { f_number: 4.0, shutter_time: 1.0 / 60.0, iso: 100.0, ..PhysicalCamera::default() };
+        let slower = PhysicalCamera { shutter_time: 1.0 / 30.0, ..base.clone() };
+        let higher_iso = PhysicalCamera { iso: 200.0, ..base.clone() };
+        assert!((slower.exposure() / base.exposure() - 2.0).abs() < 1e-9);
+        assert!((higher_iso.exposure() / base.exposure() - 2.0).abs() < 1e-9);
+        assert_eq!(parse_shutter("1/250"), Some(0.004));
+    }
+}
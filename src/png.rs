@@ -0,0 +1,110 @@
+//! Minimal PNG writer: 8-bit RGB or RGBA, zlib stream made of stored (uncompressed)
+//! deflate blocks, so no compressor is needed. Files are about as large as a binary PPM.
+
+use std::fs::File;
+use std::io::{self, Write, BufWriter};
+
+/// encode `rgb` (width * height * 3 bytes, top row first) as a PNG file
+pub fn encode(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    encode_pixels(width, height, 2, 3, rgb)
+}
+
+/// encode `rgba` (width * height * 4 bytes, top row first, straight alpha per the PNG
+/// spec unless the reader is told otherwise) as a PNG file
+pub fn encode_rgba(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    encode_pixels(width, height, 6, 4, rgba)
+}
+
+fn encode_pixels(width: usize, height: usize, color_type: u8, channels: usize, pixels: &[u8]) -> Vec<u8> {
+    assert_eq!(pixels.len(), width * height * channels, "PNG pixel data does not match its size");
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // bit depth 8, color type (2 RGB, 6 RGBA), deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+    write_chunk(&mut out, b"IHDR", &header);
+
+    // every scanline starts with filter type 0 (none)
+    let mut raw = Vec::with_capacity(height * (width * channels + 1));
+    for row in pixels.chunks_exact(width * channels) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(65535).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        zlib.push(blocks.peek().is_none() as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+    write_chunk(&mut out, b"IDAT", &zlib);
+    write_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+pub fn write(path: &str, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    write_bytes(path, &encode(width, height, rgb))
+}
+
+pub fn write_rgba(path: &str, width: usize, height: usize, rgba: &[u8]) -> io::Result<()> {
+    write_bytes(path, &encode_rgba(width, height, rgba))
+}
+
+fn write_bytes(path: &str, png: &[u8]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(png)?;
+    out.flush()
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_carry_valid_checksums() {
+        // CRC of "IEND" with no data is a well-known constant
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+
+        let png = encode(2, 1, &[255, 0, 0, 0, 0, 255]);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[png.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]);
+        assert_eq!(encode_rgba(2, 1, &[255, 0, 0, 255, 0, 0, 255, 0])[25], 6, "RGBA color type");
+    }
+}
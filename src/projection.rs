@@ -0,0 +1,165 @@
+//! How film positions map to camera-ray directions.
+//!
+//! Directions are in camera space: x right, y up, z backwards (the camera looks
+//! down -z); `Camera` turns them into world space with its `u`, `v`, `w` frame.
+//! Film positions (px, py) run over [0, 1]^2 from the top-left corner.
+
+use crate::vec3::Vec3;
+use crate::rtweekend::{PI, degrees_to_radians};
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum Projection {
+    /// thin-lens perspective using `vfov`, `focus_dist` and `defocus_angle`
+    #[default]
+    Perspective,
+    /// parallel rays along the view direction; the view is as large as the
+    /// perspective viewport at `focus_dist`
+    Orthographic,
+    /// fisheye where the angle off-axis grows linearly with the distance from the
+    /// image center; `fov` degrees span the image width
+    FisheyeEquidistant { fov: f64 },
+    /// equal-area fisheye; `fov` degrees span the image width
+    FisheyeEquisolid { fov: f64 },
+    /// full 360 x 180 degree latitude-longitude panorama, centered on the view
+    /// direction; a 2:1 image has square pixels
+    Equirectangular,
+    /// six 90-degree faces in a 3 x 2 atlas, in the usual +X, -X, +Y, -Y, +Z, -Z
+    /// order of the camera axes (-Z is the view direction); use a 3:2 image
+    CubeMap,
+}
+
+/// (forward, right, up) of each cube-map face, as seen from inside the cube
+const CUBE_FACES: [(Vec3, Vec3, Vec3); 6] = [
+    (Vec3 { x: 1.0, y: 0.0, z: 0.0 }, Vec3 { x: 0.0, y: 0.0, z: 1.0 }, Vec3 { x: 0.0, y: 1.0, z: 0.0 }),
+    (Vec3 { x: -1.0, y: 0.0, z: 0.0 }, Vec3 { x: 0.0, y: 0.0, z: -1.0 }, Vec3 { x: 0.0, y: 1.0, z: 0.0 }),
+    (Vec3 { x: 0.0, y: 1.0, z: 0.0 }, Vec3 { x: 1.0, y: 0.0, z: 0.0 }, Vec3 { x: 0.0, y: 0.0, z: 1.0 }),
+    (Vec3 { x: 0.0, y: -1.0, z: 0.0 }, Vec3 { x: 1.0, y: 0.0, z: 0.0 }, Vec3 { x: 0.0, y: 0.0, z: -1.0 }),
+    (Vec3 { x: 0.0, y: 0.0, z: 1.0 }, Vec3 { x: -1.0, y: 0.0, z: 0.0 }, Vec3 { x: 0.0, y: 1.0, z: 0.0 }),
+    (Vec3 { x: 0.0, y: 0.0, z: -1.0 }, Vec3 { x: 1.0, y: 0.0, z: 0.0 }, Vec3 { x: 0.0, y: 1.0, z: 0.0 }),
+];
+
+impl Projection {
+    /// `name` as accepted on the command line; `fov` (degrees) applies to the fisheyes
+    pub fn parse(name: &str, fov: Option<f64>) -> Option<Self> {
+        match name {
+            "perspective" => Some(Projection::Perspective),
+            "orthographic" | "ortho" => Some(Projection::Orthographic),
+            "fisheye" | "equidistant" => Some(Projection::FisheyeEquidistant { fov: fov.unwrap_or(180.0) }),
+            "equisolid" => Some(Projection::FisheyeEquisolid { fov: fov.unwrap_or(180.0) }),
+            "equirectangular" | "360" => Some(Projection::Equirectangular),
+            "cubemap" => Some(Projection::CubeMap),
+            _ => None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Projection::FisheyeEquidistant { fov } | Projection::FisheyeEquisolid { fov } if !(fov > 0.0 && fov <= 360.0) => {
+                Err(format!("fisheye fov must be in (0, 360], got {}", fov))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// camera-space direction through film position (px, py) of an image `aspect`
+    /// wide per unit of height; None outside the fisheye image circle.
+    /// Perspective and orthographic rays are built by the camera itself.
+    pub fn direction(&self, px: f64, py: f64, aspect: f64) -> Option<Vec3> {
+        match *self {
+            Projection::Perspective | Projection::Orthographic => None,
+            Projection::FisheyeEquidistant { fov } | Projection::FisheyeEquisolid { fov } => {
+                // [-1, 1] across the width, square pixels
+                let x = 2.0 * px - 1.0;
+                let y = (1.0 - 2.0 * py) / aspect;
+                let r = (x * x + y * y).sqrt();
+                let half_fov = degrees_to_radians(fov) / 2.0;
+                let theta = if let Projection::FisheyeEquisolid { .. } = self {
+                    let s = r * (half_fov / 2.0).sin();
+                    if s > 1.0 {
+                        return None;
+                    }
+                    2.0 * s.asin()
+                } else {
+                    r * half_fov
+                };
+                if r > 1.0 || theta > PI {
+                    return None;
+                }
+                let (sx, sy) = if r > 0.0 { (x / r, y / r) } else { (0.0, 0.0) };
+                Some(Vec3::new(theta.sin() * sx, theta.sin() * sy, -theta.cos()))
+            }
+            Projection::Equirectangular => {
+                let phi = (px - 0.5) * 2.0 * PI;
+                let theta = (0.5 - py) * PI;
+                Some(Vec3::new(theta.cos() * phi.sin(), theta.sin(), -theta.cos() * phi.cos()))
+            }
+            Projection::CubeMap => {
+                let (fx, fy) = ((px * 3.0).min(2.999_999), (py * 2.0).min(1.999_999));
+                let (forward, right, up) = CUBE_FACES[fy as usize * 3 + fx as usize];
+                let a = 2.0 * fx.fract() - 1.0;
+                let b = 1.0 - 2.0 * fy.fract();
+                Some(forward + a * right + b * up)
+            }
+        }
+    }
+
+    /// inverse of `direction`: the film position a camera-space direction maps to
+    pub fn film_position(&self, d: Vec3, aspect: f64) -> Option<(f64, f64)> {
+        let d = d.unit_vector();
+        match *self {
+            Projection::Perspective | Projection::Orthographic => None,
+            Projection::FisheyeEquidistant { fov } | Projection::FisheyeEquisolid { fov } => {
+                let theta = (-d.z).clamp(-1.0, 1.0).acos();
+                let half_fov = degrees_to_radians(fov) / 2.0;
+                let r = if let Projection::FisheyeEquisolid { .. } = self {
+                    (theta / 2.0).sin() / (half_fov / 2.0).sin()
+                } else {
+                    theta / half_fov
+                };
+                if r > 1.0 {
+                    return None;
+                }
+                let phi = d.y.atan2(d.x);
+                Some(((r * phi.cos() + 1.0) / 2.0, (1.0 - r * phi.sin() * aspect) / 2.0))
+            }
+            Projection::Equirectangular => {
+                let phi = d.x.atan2(-d.z);
+                let theta = d.y.clamp(-1.0, 1.0).asin();
+                Some((phi / (2.0 * PI) + 0.5, 0.5 - theta / PI))
+            }
+            Projection::CubeMap => {
+                let (k, _) = CUBE_FACES.iter().enumerate()
+                    .map(|(k, (forward, _, _))| (k, forward.dot(&d)))
+                    .max_by(|a, b| a.1.total_cmp(&b.1))?;
+                let (forward, right, up) = CUBE_FACES[k];
+                let major = forward.dot(&d);
+                let (a, b) = (right.dot(&d) / major, up.dot(&d) / major);
+                Some((((k % 3) as f64 + (a + 1.0) / 2.0) / 3.0, ((k / 3) as f64 + (1.0 - b) / 2.0) / 2.0))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn film_position_inverts_direction() {
+        let projections = [
+            Projection::FisheyeEquidistant { fov: 180.0 },
+            Projection::FisheyeEquisolid { fov: 200.0 },
+            Projection::Equirectangular,
+            Projection::CubeMap,
+        ];
+        for projection in projections {
+            for (px, py) in [(0.5, 0.5), (0.3, 0.6), (0.62, 0.21), (0.9, 0.75)] {
+                let Some(d) = projection.direction(px, py, 1.5) else { continue };
+                let (qx, qy) = projection.film_position(d, 1.5).unwrap();
+                assert!((qx - px).abs() < 1e-9 && (qy - py).abs() < 1e-9, "{:?} {} {} -> {} {}", projection, px, py, qx, qy);
+            }
+        }
+        let center = Projection::CubeMap.direction(5.0 / 6.0, 0.75, 1.5).unwrap();
+        assert_eq!(center, Vec3::new(0.0, 0.0, -1.0));
+    }
+}
@@ -1,18 +1,84 @@
 use crate::vec3::Vec3;
 use crate::vec3::Point3;
 
+/// what a ray is for, so objects can choose which rays see them
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum RayKind {
+    #[default]
+    Camera,
+    /// toward a light, testing for occlusion
+    Shadow,
+    /// mirror or glossy reflection
+    Reflection,
+    Refraction,
+    /// diffuse bounce
+    Diffuse,
+}
+
+impl RayKind {
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Set of ray kinds an object is visible to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Visibility(u8);
+
+impl Default for Visibility {
+    fn default() -> Self { Self::ALL }
+}
+
+impl Visibility {
+    pub const ALL: Visibility = Visibility(0b11111);
+    pub const NONE: Visibility = Visibility(0);
+
+    pub fn sees(&self, kind: RayKind) -> bool { self.0 & kind.bit() != 0 }
+
+    pub fn with(self, kind: RayKind) -> Self { Self(self.0 | kind.bit()) }
+
+    pub fn without(self, kind: RayKind) -> Self { Self(self.0 & !kind.bit()) }
+
+    /// kinds both masks are visible to
+    pub fn intersect(self, other: Visibility) -> Self { Self(self.0 & other.0) }
+
+    pub fn bits(&self) -> u8 { self.0 }
+
+    /// comma-separated kinds as accepted on the command line, e.g. "shadow,reflection"
+    pub fn parse(list: &str) -> Option<Self> {
+        list.split(',').map(str::trim).filter(|s| !s.is_empty()).try_fold(Self::NONE, |mask, name| {
+            let kind = match name {
+                "camera" => RayKind::Camera,
+                "shadow" => RayKind::Shadow,
+                "reflection" => RayKind::Reflection,
+                "refraction" => RayKind::Refraction,
+                "diffuse" => RayKind::Diffuse,
+                "all" => return Some(Self::ALL),
+                _ => return None,
+            };
+            Some(mask.with(kind))
+        })
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    pub kind: RayKind,
 }
 
 impl Ray {
+    /// a camera ray
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self { origin, direction, kind: RayKind::Camera }
+    }
+
+    pub fn with_kind(origin: Point3, direction: Vec3, kind: RayKind) -> Self {
+        Self { origin, direction, kind }
     }
 
     pub fn at(&self, t: f64) -> Vec3 {
         self.origin + t * self.direction
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,81 @@
+/// Small deterministic PCG32 generator (O'Neill, pcg32_random_r).
+///
+/// Every camera sample gets its own generator seeded from
+/// (global seed, pixel, sample index), so the image only depends on the seed
+/// and never on how the pixels are spread over threads.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
+}
+
+const PCG_MULT: u64 = 6_364_136_223_846_793_005;
+
+impl Rng {
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self { state: 0, inc: (stream << 1) | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    /// generator for one camera sample of pixel (i, j)
+    pub fn for_sample(seed: u64, i: usize, j: usize, sample: usize) -> Self {
+        let pixel = mix64(mix64(seed ^ i as u64) ^ (j as u64).rotate_left(32));
+        Self::new(mix64(pixel ^ sample as u64), pixel)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(PCG_MULT).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// random in [0.0, 1.0)
+    pub fn random_double(&mut self) -> f64 {
+        let hi = (self.next_u32() as u64) << 21;
+        let lo = (self.next_u32() >> 11) as u64;
+        (hi | lo) as f64 / (1u64 << 53) as f64
+    }
+
+    /// random in [min, max)
+    pub fn random_double_range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.random_double()
+    }
+}
+
+/// SplitMix64 finalizer, used to spread (seed, pixel, sample) over the state space
+pub fn mix64(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_stream() {
+        let mut a = Rng::for_sample(7, 3, 4, 5);
+        let mut b = Rng::for_sample(7, 3, 4, 5);
+        for _ in 0..16 {
+            assert_eq!(a.random_double(), b.random_double());
+        }
+        let mut c = Rng::for_sample(7, 4, 3, 5);
+        assert_ne!(Rng::for_sample(7, 3, 4, 5).next_u32(), c.next_u32());
+    }
+
+    #[test]
+    fn doubles_in_unit_interval() {
+        let mut rng = Rng::new(42, 0);
+        for _ in 0..1000 {
+            let x = rng.random_double();
+            assert!((0.0..1.0).contains(&x));
+        }
+    }
+}
@@ -1,5 +1,5 @@
 pub const INFINITY_F64: f64 = f64::INFINITY;
-pub const PI: f64 = f64::consts::PI; ///3.141_592_653_589_793;
+pub const PI: f64 = f64::consts::PI; //3.141_592_653_589_793;
 
 #[inline]
 pub fn degrees_to_radians(deg: f64) -> f64 {
@@ -15,13 +15,27 @@ use core::f64;
 pub use crate::color::Color;
 pub use crate::ray::Ray;
 pub use crate::vec3::{Vec3, Point3};
+pub use crate::rng::Rng;
 
-/// random in [0.0, 1.0)
+thread_local! {
+    static RNG: std::cell::Cell<Rng> = std::cell::Cell::new(Rng::new(
+        std::hash::BuildHasher::hash_one(&std::collections::hash_map::RandomState::new(), 0u64), 0));
+}
+
+/// random in [0.0, 1.0), from a randomly seeded generator per thread
+#[deprecated(note = "not reproducible; draw from an `Rng` seeded with `Rng::for_sample`")]
 pub fn random_double() -> f64 {
-    rand::random::<f64>()
+    RNG.with(|cell| {
+        let mut rng = cell.get();
+        let x = rng.random_double();
+        cell.set(rng);
+        x
+    })
 }
 
 /// random in [min, max)
+#[deprecated(note = "not reproducible; draw from an `Rng` seeded with `Rng::for_sample`")]
+#[allow(deprecated)]
 pub fn random_double_range(min: f64, max: f64) -> f64 {
     min + (max - min) * random_double()
 }
\ No newline at end of file
@@ -0,0 +1,466 @@
+use std::sync::OnceLock;
+
+use crate::rng::{Rng, mix64};
+
+/// Source of sample dimensions for one camera sample.
+///
+/// The camera calls `start_pixel_sample` before tracing each sample; after that
+/// every consumer (pixel jitter, lens, then each bounce) pulls the next 1D or 2D
+/// dimension in a fixed order, so low-discrepancy samplers stay stratified per use.
+pub trait Sampler {
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: usize);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+/// which sampler the camera builds for each pixel
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum SamplerKind {
+    #[default]
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub fn build(&self, seed: u64, samples_per_pixel: usize) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(seed)),
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "independent" | "random" => Some(SamplerKind::Independent),
+            "stratified" | "jittered" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            "bluenoise" | "blue-noise" => Some(SamplerKind::BlueNoise),
+            _ => None,
+        }
+    }
+}
+
+/// per-pixel hash shared by the samplers that decorrelate pixels
+fn pixel_hash(seed: u64, i: usize, j: usize) -> u64 {
+    mix64(mix64(seed ^ i as u64) ^ (j as u64).rotate_left(32))
+}
+
+fn hash_to_unit(h: u64) -> f64 {
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn wrap_unit(x: f64) -> f64 {
+    let f = x - x.floor();
+    if f >= 1.0 { 0.0 } else { f }
+}
+
+/// plain uniform random numbers, one independent stream per sample
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self { Self { seed, rng: Rng::new(seed, 0) } }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: usize) {
+        self.rng = Rng::for_sample(self.seed, i, j, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 { self.rng.random_double() }
+
+    fn get_2d(&mut self) -> (f64, f64) { (self.rng.random_double(), self.rng.random_double()) }
+}
+
+/// Jittered strata; each dimension visits the strata in its own random order.
+///
+/// The strata are sized for `samples_per_pixel`, so changing it changes every sample.
+/// 2D strata form an nx × ny grid with nx = ⌊√spp⌋ and ny = ⌊spp / nx⌋; when spp
+/// isn't a multiple of nx the leftover samples start a second round of the grid.
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: usize,
+    pixel: u64,
+    sample_index: usize,
+    dimension: u64,
+    rng: Rng,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pixel: usize) -> Self {
+        Self { seed, samples_per_pixel: samples_per_pixel.max(1), pixel: 0, sample_index: 0, dimension: 0, rng: Rng::new(seed, 0) }
+    }
+
+    fn stratum(&mut self, count: usize) -> usize {
+        let p = mix64(self.pixel ^ self.dimension.wrapping_mul(0x9e37_79b9)) as u32;
+        self.dimension += 1;
+        permutation_element((self.sample_index % count) as u32, count as u32, p) as usize
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: usize) {
+        self.pixel = pixel_hash(self.seed, i, j);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+        self.rng = Rng::for_sample(self.seed, i, j, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let n = self.samples_per_pixel;
+        let s = self.stratum(n);
+        (s as f64 + self.rng.random_double()) / n as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let nx = (self.samples_per_pixel as f64).sqrt().floor().max(1.0) as usize;
+        let ny = self.samples_per_pixel / nx;
+        let s = self.stratum(nx * ny);
+        let x = ((s % nx) as f64 + self.rng.random_double()) / nx as f64;
+        let y = ((s / nx) as f64 + self.rng.random_double()) / ny as f64;
+        (x, y)
+    }
+}
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+fn radical_inverse(base: u64, mut index: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut reversed = 0u64;
+    while index > 0 {
+        let next = index / base;
+        reversed = reversed * base + (index - next * base);
+        inv_base_n *= inv_base;
+        index = next;
+    }
+    (reversed as f64 * inv_base_n).min(1.0 - f64::EPSILON)
+}
+
+/// Halton sequence over the samples of a pixel, one prime base per dimension,
+/// toroidally shifted per pixel so neighbouring pixels don't correlate;
+/// dimensions past the prime table fall back to uniform randoms
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    sample_index: u64,
+    dimension: usize,
+    rng: Rng,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self { Self { seed, pixel: 0, sample_index: 0, dimension: 0, rng: Rng::new(seed, 0) } }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: usize) {
+        self.pixel = pixel_hash(self.seed, i, j);
+        self.sample_index = sample_index as u64;
+        self.dimension = 0;
+        self.rng = Rng::for_sample(self.seed, i, j, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let d = self.dimension;
+        self.dimension += 1;
+        if d >= PRIMES.len() {
+            return self.rng.random_double();
+        }
+        let shift = hash_to_unit(mix64(self.pixel ^ d as u64));
+        wrap_unit(radical_inverse(PRIMES[d], self.sample_index) + shift)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) { (self.get_1d(), self.get_1d()) }
+}
+
+fn sobol_u32(index: u32, dim: usize) -> u32 {
+    let mut x = 0u32;
+    let mut v = 1u32 << 31;
+    let mut index = index;
+    let mut bit = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            x ^= if dim == 0 { (1u32 << 31) >> bit } else { v };
+        }
+        v ^= v >> 1;
+        index >>= 1;
+        bit += 1;
+    }
+    x
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// Owen-scrambled Sobol (Burley 2020): every 1D/2D request uses the first two
+/// Sobol dimensions with its own shuffled index and scramble seeds
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    sample_index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self { Self { seed, pixel: 0, sample_index: 0, dimension: 0 } }
+
+    fn next_seed(&mut self) -> u64 {
+        let h = mix64(self.pixel ^ self.dimension.wrapping_mul(0x632b_e59b_d9b4_e019));
+        self.dimension += 1;
+        h
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: usize) {
+        self.pixel = pixel_hash(self.seed, i, j);
+        self.sample_index = sample_index as u32;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let h = self.next_seed();
+        let index = nested_uniform_scramble(self.sample_index, h as u32);
+        let x = nested_uniform_scramble(sobol_u32(index, 0), (h >> 32) as u32);
+        x as f64 / 4_294_967_296.0
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let h = self.next_seed();
+        let index = nested_uniform_scramble(self.sample_index, h as u32);
+        let h2 = mix64(h);
+        let x = nested_uniform_scramble(sobol_u32(index, 0), h2 as u32);
+        let y = nested_uniform_scramble(sobol_u32(index, 1), (h2 >> 32) as u32);
+        (x as f64 / 4_294_967_296.0, y as f64 / 4_294_967_296.0)
+    }
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Rank-1 lattice (R2 / golden ratio) over the samples of a pixel, offset per
+/// pixel by a tiled blue-noise mask so the remaining error is blue across the image
+pub struct BlueNoiseSampler {
+    seed: u64,
+    i: usize,
+    j: usize,
+    sample_index: usize,
+    dimension: u64,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(seed: u64) -> Self { Self { seed, i: 0, j: 0, sample_index: 0, dimension: 0 } }
+
+    fn mask_offset(&mut self) -> f64 {
+        let h = mix64(self.seed ^ self.dimension.wrapping_mul(0xd1b5_4a32_d192_ed03));
+        self.dimension += 1;
+        let x = (self.i + (h as usize % BLUE_NOISE_SIZE)) % BLUE_NOISE_SIZE;
+        let y = (self.j + ((h >> 32) as usize % BLUE_NOISE_SIZE)) % BLUE_NOISE_SIZE;
+        blue_noise_mask()[y * BLUE_NOISE_SIZE + x]
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, i: usize, j: usize, sample_index: usize) {
+        self.i = i;
+        self.j = j;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let golden = 0.618_033_988_749_894_8;
+        wrap_unit(self.mask_offset() + self.sample_index as f64 * golden)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let plastic = 1.324_717_957_244_746;
+        let (a1, a2) = (1.0 / plastic, 1.0 / (plastic * plastic));
+        let k = self.sample_index as f64;
+        (wrap_unit(self.mask_offset() + k * a1), wrap_unit(self.mask_offset() + k * a2))
+    }
+}
+
+/// tileable 64x64 blue-noise ranks in (0, 1), built once with void-and-cluster
+pub fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE, 1.9))
+}
+
+fn void_and_cluster(size: usize, sigma: f64) -> Vec<f64> {
+    let n = size * size;
+    let mut lut = vec![0.0; n];
+    for dy in 0..size {
+        for dx in 0..size {
+            let x = dx.min(size - dx) as f64;
+            let y = dy.min(size - dy) as f64;
+            lut[dy * size + dx] = (-(x * x + y * y) / (2.0 * sigma * sigma)).exp();
+        }
+    }
+
+    let splat = |energy: &mut [f64], p: usize, sign: f64| {
+        let (px, py) = (p % size, p / size);
+        for (q, e) in energy.iter_mut().enumerate() {
+            let dx = (q % size + size - px) % size;
+            let dy = (q / size + size - py) % size;
+            *e += sign * lut[dy * size + dx];
+        }
+    };
+    let tightest_cluster = |energy: &[f64], ones: &[bool]| {
+        (0..n).filter(|&p| ones[p]).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+    let largest_void = |energy: &[f64], ones: &[bool]| {
+        (0..n).filter(|&p| !ones[p]).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+
+    // initial binary pattern: ~10% random points, relaxed until evenly spread
+    let mut rng = Rng::new(0x5eed, 0);
+    let mut ones = vec![false; n];
+    let mut energy = vec![0.0; n];
+    let initial = n / 10;
+    let mut placed = 0;
+    while placed < initial {
+        let p = (rng.next_u32() as usize) % n;
+        if !ones[p] {
+            ones[p] = true;
+            splat(&mut energy, p, 1.0);
+            placed += 1;
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&energy, &ones);
+        ones[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        let void = largest_void(&energy, &ones);
+        ones[void] = true;
+        splat(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0usize; n];
+    let (prototype, prototype_energy) = (ones.clone(), energy.clone());
+
+    // phase 1: peel off the initial points, tightest cluster first
+    let mut r = initial;
+    while r > 0 {
+        let cluster = tightest_cluster(&energy, &ones);
+        ones[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        r -= 1;
+        rank[cluster] = r;
+    }
+
+    // phase 2: fill the largest voids until the mask is full
+    ones = prototype;
+    energy = prototype_energy;
+    for r in initial..n {
+        let void = largest_void(&energy, &ones);
+        ones[void] = true;
+        splat(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+
+    rank.iter().map(|&r| (r as f64 + 0.5) / n as f64).collect()
+}
+
+/// Kensler's hashed permutation: element `i` of a random permutation of 0..l
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    if l <= 1 {
+        return 0;
+    }
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | (p >> 27));
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    i.wrapping_add(p) % l
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_stay_in_unit_square() {
+        for kind in [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol, SamplerKind::BlueNoise] {
+            let mut sampler = kind.build(3, 16);
+            for s in 0..16 {
+                sampler.start_pixel_sample(5, 9, s);
+                for _ in 0..8 {
+                    let x = sampler.get_1d();
+                    let (u, v) = sampler.get_2d();
+                    assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&u) && (0.0..1.0).contains(&v), "{:?}", kind);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_covers_every_stratum() {
+        let mut sampler = StratifiedSampler::new(11, 16);
+        let mut seen = [false; 16];
+        for s in 0..16 {
+            sampler.start_pixel_sample(2, 3, s);
+            let (u, v) = sampler.get_2d();
+            seen[(v * 4.0) as usize * 4 + (u * 4.0) as usize] = true;
+        }
+        assert!(seen.iter().all(|&b| b));
+
+        // 11 spp: a 3x3 grid, all of it covered by the first nine samples
+        let mut sampler = StratifiedSampler::new(11, 11);
+        let mut seen = [false; 9];
+        for s in 0..9 {
+            sampler.start_pixel_sample(2, 3, s);
+            let (u, v) = sampler.get_2d();
+            seen[(v * 3.0) as usize * 3 + (u * 3.0) as usize] = true;
+        }
+        assert!(seen.iter().all(|&b| b));
+    }
+}
@@ -0,0 +1,260 @@
+//! A named, hierarchical scene description that compiles into the flat
+//! `HittableList` the renderer traces.
+//!
+//! Each node has a local `Transform` relative to its parent, an optional object,
+//! an optional material override that also applies to its descendants, and a
+//! `visible` flag that hides the whole subtree. Nodes are found by slash-separated
+//! paths of names below the root, such as `"car/wheel_fl"`.
+//!
+//! A node's `visibility` narrows which kinds of rays see its subtree, and a node may
+//! carry a `Light` placed by its transform. Light links name object paths; a path
+//! also covers everything below it.
+
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::instance::{Instance, Similarity, Transform};
+use crate::light::{Light, LightKind, LightLink};
+use crate::material::MaterialPtr;
+use crate::ray::Visibility;
+use crate::rtweekend::Shared;
+
+pub struct SceneNode {
+    pub name: String,
+    pub transform: Transform,
+    pub object: Option<Shared<dyn Hittable>>,
+    /// replaces the material of this node's object and of every descendant without
+    /// an override of its own
+    pub material: Option<MaterialPtr>,
+    pub visible: bool,
+    /// ray kinds that see this node's object and its descendants, intersected with the
+    /// parent's
+    pub visibility: Visibility,
+    /// placed by this node's transform; its own `link` is replaced at compile time
+    pub light: Option<Light>,
+    /// object paths the light illuminates exclusively; empty lights everything
+    pub light_include: Vec<String>,
+    /// object paths the light skips, used when `light_include` is empty
+    pub light_exclude: Vec<String>,
+    children: Vec<SceneNode>,
+}
+
+impl SceneNode {
+    /// empty group node
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            transform: Transform::default(),
+            object: None,
+            material: None,
+            visible: true,
+            visibility: Visibility::ALL,
+            light: None,
+            light_include: Vec::new(),
+            light_exclude: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_object(name: &str, object: Shared<dyn Hittable>) -> Self {
+        Self { object: Some(object), ..Self::new(name) }
+    }
+
+    pub fn with_light(name: &str, light: Light) -> Self {
+        Self { light: Some(light), ..Self::new(name) }
+    }
+
+    pub fn children(&self) -> &[SceneNode] { &self.children }
+
+    /// Add `node` as the last child and return it. Names must be non-empty, unique
+    /// among siblings and free of '/'.
+    pub fn add(&mut self, node: SceneNode) -> Result<&mut SceneNode, String> {
+        if node.name.is_empty() || node.name.contains('/') {
+            return Err(format!("invalid node name '{}'", node.name));
+        }
+        if self.child(&node.name).is_some() {
+            return Err(format!("'{}' already has a child named '{}'", self.name, node.name));
+        }
+        self.children.push(node);
+        Ok(self.children.last_mut().unwrap())
+    }
+
+    /// detach and return the node at `path`
+    pub fn remove(&mut self, path: &str) -> Option<SceneNode> {
+        let (parent, name) = match path.rsplit_once('/') {
+            Some((parent, name)) => (self.find_mut(parent)?, name),
+            None => (self, path),
+        };
+        let k = parent.children.iter().position(|c| c.name == name)?;
+        Some(parent.children.remove(k))
+    }
+
+    fn child(&self, name: &str) -> Option<&SceneNode> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn find(&self, path: &str) -> Option<&SceneNode> {
+        path.split('/').filter(|s| !s.is_empty()).try_fold(self, |node, name| node.child(name))
+    }
+
+    pub fn find_mut(&mut self, path: &str) -> Option<&mut SceneNode> {
+        path.split('/').filter(|s| !s.is_empty())
+            .try_fold(self, |node, name| node.children.iter_mut().find(|c| c.name == name))
+    }
+
+    /// Flatten the visible objects and lights into a list, each placed by the product
+    /// of the transforms above it. The root's own name is not part of any path. Fails
+    /// if a light links to a path that names no node.
+    pub fn compile(&self) -> Result<HittableList, String> {
+        let mut world = HittableList::new();
+        let mut paths = Vec::new();
+        let mut lights = Vec::new();
+        self.visit(&Similarity::default(), None, Visibility::ALL, "", &mut |path, item| match item {
+            Emitted::Object(object) => {
+                world.add(object);
+                paths.push(path.to_string());
+            }
+            Emitted::Light(light, node) => lights.push((path.to_string(), light, node)),
+        });
+        for (path, mut light, node) in lights {
+            if let Some(link) = node.light_include.iter().chain(&node.light_exclude).find(|l| l.trim_matches('/').is_empty() || self.find(l).is_none()) {
+                return Err(format!("light '{}' links to no scene node '{}'", path, link));
+            }
+            let ids = |links: &[String]| -> Vec<u32> {
+                paths.iter().enumerate()
+                    .filter(|(_, p)| links.iter().any(|l| covers(l, p)))
+                    .map(|(k, _)| k as u32 + 1)
+                    .collect()
+            };
+            light.link = if !node.light_include.is_empty() {
+                LightLink::Include(ids(&node.light_include))
+            } else if !node.light_exclude.is_empty() {
+                LightLink::Exclude(ids(&node.light_exclude))
+            } else {
+                LightLink::All
+            };
+            world.lights.push(light);
+        }
+        world.names = paths;
+        Ok(world)
+    }
+
+    /// paths of the objects `compile` emits, in the same order: object k of the
+    /// compiled list (`HitRecord::object_id` k + 1) came from path k
+    pub fn object_paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
+        self.visit(&Similarity::default(), None, Visibility::ALL, "", &mut |path, item| {
+            if let Emitted::Object(_) = item {
+                paths.push(path.to_string());
+            }
+        });
+        paths
+    }
+
+    fn visit<'a>(&'a self, parent: &Similarity, material: Option<&MaterialPtr>, visibility: Visibility, path: &str, emit: &mut dyn FnMut(&str, Emitted<'a>)) {
+        if !self.visible {
+            return;
+        }
+        let placement = parent.compose(&self.transform.into());
+        let material = self.material.as_ref().or(material);
+        let visibility = visibility.intersect(self.visibility);
+        if let Some(object) = &self.object {
+            let mut instance = Instance::placed(object.clone(), placement, material.cloned());
+            instance.visibility = visibility;
+            emit(path, Emitted::Object(Shared::new(instance)));
+        }
+        if let Some(light) = &self.light {
+            let kind = match light.kind {
+                LightKind::Point { position } => LightKind::Point { position: placement.point(position) },
+                LightKind::Directional { direction } => LightKind::Directional { direction: placement.vector(direction).unit_vector() },
+            };
+            emit(path, Emitted::Light(Light { kind, ..light.clone() }, self));
+        }
+        for child in &self.children {
+            let child_path = if path.is_empty() { child.name.clone() } else { format!("{}/{}", path, child.name) };
+            child.visit(&placement, material, visibility, &child_path, emit);
+        }
+    }
+}
+
+/// what `visit` finds at a node
+enum Emitted<'a> {
+    Object(Shared<dyn Hittable>),
+    /// a placed light and the node that holds its links
+    Light(Light, &'a SceneNode),
+}
+
+/// whether link `path` names `object` or one of its ancestors
+fn covers(path: &str, object: &str) -> bool {
+    let path = path.trim_matches('/');
+    object == path || object.strip_prefix(path).is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::ray::{Ray, RayKind};
+    use crate::sphere::Sphere;
+    use crate::vec3::{Vec3, Point3};
+
+    #[test]
+    fn children_follow_their_parents_and_paths_resolve() {
+        let gray = Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let red = Shared::new(Lambertian::new(Color::new(0.9, 0.1, 0.1)));
+        let wheel: Shared<dyn Hittable> = Shared::new(Sphere::new(Point3::zero(), 0.25, gray.clone()));
+
+        let mut root = SceneNode::new("root");
+        let car = root.add(SceneNode::new("car")).unwrap();
+        car.transform.translation = Vec3::new(10.0, 0.0, 0.0);
+        car.material = Some(red);
+        for (name, x) in [("wheel_fl", 1.0), ("wheel_fr", -1.0)] {
+            let node = car.add(SceneNode::with_object(name, wheel.clone())).unwrap();
+            node.transform.translation = Vec3::new(x, 0.0, 0.0);
+        }
+        assert!(car.add(SceneNode::new("wheel_fl")).is_err());
+        root.add(SceneNode::with_object("ground", Shared::new(Sphere::new(Point3::new(0.0, -100.0, 0.0), 99.0, gray)))).unwrap();
+
+        assert_eq!(root.object_paths(), ["car/wheel_fl", "car/wheel_fr", "ground"]);
+        let world = root.compile().unwrap();
+        let rec = world.hit(&Ray::new(Point3::new(11.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)), 0.001, f64::INFINITY).unwrap();
+        assert!((rec.p - Point3::new(11.0, 0.25, 0.0)).length() < 1e-9);
+        assert_eq!(rec.object_id, 1);
+        assert_eq!(rec.mat.albedo(), Color::new(0.9, 0.1, 0.1), "inherits the car's override");
+
+        root.find_mut("car/wheel_fl").unwrap().visible = false;
+        assert_eq!(root.object_paths(), ["car/wheel_fr", "ground"]);
+        assert!(root.remove("car").is_some());
+        assert!(root.find("car/wheel_fr").is_none());
+        assert_eq!(root.compile().unwrap().objects.len(), 1);
+    }
+
+    #[test]
+    fn visibility_narrows_down_the_tree_and_links_resolve_to_object_ids() {
+        let gray: MaterialPtr = Shared::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let ball: Shared<dyn Hittable> = Shared::new(Sphere::new(Point3::zero(), 0.5, gray));
+
+        let mut root = SceneNode::new("root");
+        let hero = root.add(SceneNode::new("hero")).unwrap();
+        hero.visibility = Visibility::ALL.without(RayKind::Camera);
+        hero.add(SceneNode::with_object("body", ball.clone())).unwrap().visibility = Visibility::ALL.without(RayKind::Shadow);
+        root.add(SceneNode::with_object("prop", ball)).unwrap().transform.translation = Vec3::new(3.0, 0.0, 0.0);
+        let lamp = root.add(SceneNode::with_light("lamp", Light::point(Point3::zero(), Color::new(1.0, 1.0, 1.0)))).unwrap();
+        lamp.transform.translation = Vec3::new(0.0, 4.0, 0.0);
+        lamp.light_exclude = vec!["hero".to_string()];
+
+        let world = root.compile().unwrap();
+        let down = |kind| Ray::with_kind(Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), kind);
+        assert!(world.hit(&down(RayKind::Camera), 0.001, f64::INFINITY).is_none());
+        assert!(world.hit(&down(RayKind::Shadow), 0.001, f64::INFINITY).is_none(), "the child narrows further");
+        assert!(world.hit(&down(RayKind::Reflection), 0.001, f64::INFINITY).is_some());
+
+        assert_eq!(world.lights.len(), 1);
+        assert_eq!(world.lights[0].kind, LightKind::Point { position: Point3::new(0.0, 4.0, 0.0) });
+        assert_eq!(world.lights[0].link, LightLink::Exclude(vec![1]));
+        assert!(covers("hero", "hero/body") && !covers("her", "hero/body"));
+
+        root.find_mut("lamp").unwrap().light_exclude = vec!["hero/bdy".to_string()];
+        assert_eq!(root.compile().err().as_deref(), Some("light 'lamp' links to no scene node 'hero/bdy'"));
+    }
+}
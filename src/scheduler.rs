@@ -0,0 +1,255 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::rtweekend::Shared;
+
+/// order in which tiles are handed to the worker threads
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum TileOrder {
+    /// left to right, top to bottom
+    #[default]
+    Scanline,
+    /// rings outwards from the image center
+    Spiral,
+    /// along a Hilbert curve, keeping consecutive tiles close together
+    Hilbert,
+}
+
+impl TileOrder {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "scanline" => Some(TileOrder::Scanline),
+            "spiral" => Some(TileOrder::Spiral),
+            "hilbert" => Some(TileOrder::Hilbert),
+            _ => None,
+        }
+    }
+}
+
+/// rectangle of pixels [x0, x1) x [y0, y1)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub index: usize,
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Tile {
+    pub fn width(&self) -> usize { self.x1 - self.x0 }
+    pub fn height(&self) -> usize { self.y1 - self.y0 }
+
+    /// pixel coordinates (i, j) in scanline order within the tile
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.y0..self.y1).flat_map(move |j| (self.x0..self.x1).map(move |i| (i, j)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RenderEvent {
+    TileCompleted { tile: Tile, completed: usize, total: usize },
+    Progress { percent: f64 },
+}
+
+/// receives render events from worker threads; wrap an `mpsc::Sender` to get a channel
+pub type EventCallback = Shared<dyn Fn(&RenderEvent) + Send + Sync>;
+
+/// cooperative cancellation flag, cheap to clone and share with another thread
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self { Self::default() }
+    pub fn cancel(&self) { self.0.store(true, Ordering::Relaxed) }
+    pub fn is_cancelled(&self) -> bool { self.0.load(Ordering::Relaxed) }
+}
+
+pub struct TileScheduler {
+    pub tile_size: usize,
+    pub order: TileOrder,
+    /// worker threads; None uses rayon's default (one per core)
+    pub threads: Option<usize>,
+    pub on_event: Option<EventCallback>,
+    pub cancel: CancelToken,
+    /// pool built for `threads`, kept across the passes of a progressive render
+    pool: Mutex<Option<(usize, Arc<ThreadPool>)>>,
+}
+
+impl Default for TileScheduler {
+    fn default() -> Self {
+        Self { tile_size: 32, order: TileOrder::default(), threads: None, on_event: None, cancel: CancelToken::new(), pool: Mutex::new(None) }
+    }
+}
+
+impl TileScheduler {
+    pub fn emit(&self, event: RenderEvent) {
+        if let Some(cb) = &self.on_event {
+            cb(&event);
+        }
+    }
+
+    /// split a width x height image into tiles, sorted by `self.order`
+    pub fn tiles(&self, width: usize, height: usize) -> Vec<Tile> {
+        let size = self.tile_size.max(1);
+        let nx = width.div_ceil(size);
+        let ny = height.div_ceil(size);
+        let mut cells: Vec<(usize, usize)> = (0..ny).flat_map(|ty| (0..nx).map(move |tx| (tx, ty))).collect();
+
+        match self.order {
+            TileOrder::Scanline => {}
+            TileOrder::Spiral => {
+                let (cx, cy) = ((nx as f64 - 1.0) / 2.0, (ny as f64 - 1.0) / 2.0);
+                let key = |&(tx, ty): &(usize, usize)| {
+                    let (dx, dy) = (tx as f64 - cx, ty as f64 - cy);
+                    (dx.abs().max(dy.abs()), dy.atan2(dx))
+                };
+                cells.sort_by(|a, b| {
+                    let (ra, aa) = key(a);
+                    let (rb, ab) = key(b);
+                    ra.total_cmp(&rb).then(aa.total_cmp(&ab))
+                });
+            }
+            TileOrder::Hilbert => {
+                let n = nx.max(ny).next_power_of_two();
+                cells.sort_by_key(|&(tx, ty)| hilbert_index(n, tx, ty));
+            }
+        }
+
+        cells
+            .into_iter()
+            .enumerate()
+            .map(|(index, (tx, ty))| Tile {
+                index,
+                x0: tx * size,
+                y0: ty * size,
+                x1: ((tx + 1) * size).min(width),
+                y1: ((ty + 1) * size).min(height),
+            })
+            .collect()
+    }
+
+    /// Render every tile on the worker pool, handing tiles out in order.
+    /// Returns the per-tile results, or `Interrupted` once the cancel token is set.
+    pub fn run<T, F>(&self, width: usize, height: usize, render_tile: F) -> io::Result<Vec<(Tile, T)>>
+    where
+        T: Send,
+        F: Fn(&Tile) -> T + Sync,
+    {
+        let tiles = self.tiles(width, height);
+        let total = tiles.len();
+        let next = AtomicUsize::new(0);
+        let completed = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(total));
+
+        let work = || loop {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+            let k = next.fetch_add(1, Ordering::Relaxed);
+            let Some(tile) = tiles.get(k) else { break };
+            let value = render_tile(tile);
+            results.lock().unwrap().push((*tile, value));
+
+            let done = completed.fetch_add(1, Ordering::Relaxed) + 1;
+            self.emit(RenderEvent::TileCompleted { tile: *tile, completed: done, total });
+            self.emit(RenderEvent::Progress { percent: 100.0 * done as f64 / total as f64 });
+        };
+        match self.pool()? {
+            Some(pool) => pool.scope(|s| {
+                for _ in 0..pool.current_num_threads() {
+                    s.spawn(|_| work());
+                }
+            }),
+            None => rayon::scope(|s| {
+                for _ in 0..rayon::current_num_threads() {
+                    s.spawn(|_| work());
+                }
+            }),
+        }
+
+        if self.cancel.is_cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "render cancelled"));
+        }
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(tile, _)| tile.index);
+        Ok(results)
+    }
+
+    /// the pool for `threads`, built on first use; None runs on rayon's global pool
+    fn pool(&self) -> io::Result<Option<Arc<ThreadPool>>> {
+        let Some(n) = self.threads else { return Ok(None) };
+        let mut cached = self.pool.lock().unwrap();
+        if let Some((_, pool)) = cached.as_ref().filter(|(threads, _)| *threads == n) {
+            return Ok(Some(pool.clone()));
+        }
+        let pool = Arc::new(ThreadPoolBuilder::new().num_threads(n).build().map_err(io::Error::other)?);
+        *cached = Some((n, pool.clone()));
+        Ok(Some(pool))
+    }
+}
+
+/// distance of (x, y) along a Hilbert curve filling an n x n grid (n a power of two)
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_image_once_in_every_order() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let scheduler = TileScheduler { tile_size: 7, order, ..TileScheduler::default() };
+            let mut hits = vec![0; 30 * 17];
+            for tile in scheduler.tiles(30, 17) {
+                for (i, j) in tile.pixels() {
+                    hits[j * 30 + i] += 1;
+                }
+            }
+            assert!(hits.iter().all(|&h| h == 1), "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn cancelled_run_is_interrupted() {
+        let scheduler = TileScheduler { threads: Some(2), ..TileScheduler::default() };
+        let cancel = scheduler.cancel.clone();
+        let result = scheduler.run(256, 256, |_| cancel.cancel());
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn passes_share_one_pool() {
+        let mut scheduler = TileScheduler { threads: Some(2), ..TileScheduler::default() };
+        assert_eq!(scheduler.run(64, 64, |tile| tile.index).unwrap().len(), 4);
+        let pool = scheduler.pool().unwrap().unwrap();
+        scheduler.run(64, 64, |tile| tile.index).unwrap();
+        assert!(Arc::ptr_eq(&pool, &scheduler.pool().unwrap().unwrap()));
+
+        scheduler.threads = Some(3);
+        assert_eq!(scheduler.pool().unwrap().unwrap().current_num_threads(), 3);
+        scheduler.threads = None;
+        assert!(scheduler.pool().unwrap().is_none());
+        assert_eq!(scheduler.run(64, 64, |tile| tile.index).unwrap().len(), 4);
+    }
+}
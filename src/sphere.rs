@@ -3,6 +3,9 @@ use crate::vec3::{Point3, Vec3};
 use crate::ray::Ray;
 
 use crate::material::MaterialPtr;
+use crate::fingerprint::Fingerprint;
+use crate::rtweekend::PI;
+use crate::stats;
 
 pub struct Sphere {
     pub center: Point3,
@@ -12,10 +15,19 @@ pub struct Sphere {
 
 impl Sphere {
     pub fn new(center: Point3, radius: f64, mat: MaterialPtr) -> Self { Self { center, radius: radius.max(0.0) , mat} }
+
+    /// (u, v) in [0,1]^2 of a point on the unit sphere: u = angle around Y from X=-1,
+    /// v = angle from Y=-1 to Y=+1
+    fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, ray_t_min: f64, ray_t_max: f64) -> Option<HitRecord> {
+        stats::count_intersection_test();
         let oc: Vec3 = r.origin - self.center;
         let a: f64 = r.direction.length_squared();
         let h: f64 = oc.dot(&r.direction);
@@ -41,6 +53,17 @@ impl Hittable for Sphere {
         let p: Vec3 = r.at(root);
         let normal: Vec3 = ( p - self.center) / self.radius;
 
-        Some(HitRecord::new(p, root, r, normal, self.mat.clone()))
+        let mut rec = HitRecord::new(p, root, r, normal, self.mat.clone());
+        (rec.u, rec.v) = Self::get_sphere_uv(&normal);
+        Some(rec)
+    }
+
+    fn material(&self) -> Option<MaterialPtr> { Some(self.mat.clone()) }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) {
+        fp.write_str("Sphere");
+        fp.write_vec3(&self.center);
+        fp.write_f64(self.radius);
+        self.mat.fingerprint(fp);
     }
 }
\ No newline at end of file
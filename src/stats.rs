@@ -0,0 +1,297 @@
+//! Render statistics: ray counts by kind, primitive intersection tests, path lengths
+//! and time per tile, kept per worker thread and summed into a table or a JSON report.
+//!
+//! Rays and paths are counted in the integrator's `SampleRecord`. Intersection tests
+//! happen deep inside `Hittable::hit`, which has no record to count into, so each
+//! thread keeps them in a thread-local counter the camera collects after every tile.
+//! The world is a flat list without an acceleration structure, so there are no BVH
+//! nodes to count yet: every ray tests every primitive.
+
+use std::cell::Cell;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+use crate::diagnostics::Diagnostics;
+use crate::ray::RayKind;
+use crate::scheduler::Tile;
+
+/// buckets of the path-length histogram; the last one counts every longer path too
+pub const PATH_LENGTH_BUCKETS: usize = 32;
+
+thread_local! {
+    static INTERSECTION_TESTS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// count one ray-primitive intersection test on this thread
+pub fn count_intersection_test() {
+    INTERSECTION_TESTS.with(|n| n.set(n.get() + 1));
+}
+
+/// intersection tests counted on this thread since the last call
+pub fn take_intersection_tests() -> u64 {
+    INTERSECTION_TESTS.with(|n| n.replace(0))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RayCounters {
+    pub camera_rays: u64,
+    /// reflected, refracted and diffuse bounce rays
+    pub secondary_rays: u64,
+    pub shadow_rays: u64,
+    pub intersection_tests: u64,
+    /// paths by the number of bounces they survived
+    pub path_lengths: [u64; PATH_LENGTH_BUCKETS],
+}
+
+impl Default for RayCounters {
+    fn default() -> Self {
+        Self { camera_rays: 0, secondary_rays: 0, shadow_rays: 0, intersection_tests: 0, path_lengths: [0; PATH_LENGTH_BUCKETS] }
+    }
+}
+
+impl RayCounters {
+    pub fn count_ray(&mut self, kind: RayKind) {
+        match kind {
+            RayKind::Camera => self.camera_rays += 1,
+            RayKind::Shadow => self.shadow_rays += 1,
+            RayKind::Reflection | RayKind::Refraction | RayKind::Diffuse => self.secondary_rays += 1,
+        }
+    }
+
+    pub fn count_path(&mut self, bounces: usize) {
+        self.path_lengths[bounces.min(PATH_LENGTH_BUCKETS - 1)] += 1;
+    }
+
+    pub fn add(&mut self, other: &RayCounters) {
+        self.camera_rays += other.camera_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.shadow_rays += other.shadow_rays;
+        self.intersection_tests += other.intersection_tests;
+        for (a, b) in self.path_lengths.iter_mut().zip(&other.path_lengths) {
+            *a += b;
+        }
+    }
+
+    pub fn rays(&self) -> u64 { self.camera_rays + self.secondary_rays + self.shadow_rays }
+}
+
+/// the work of one tile, rendered by worker thread `thread`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TileStats {
+    pub tile: Tile,
+    pub thread: usize,
+    pub time: Duration,
+    pub counters: RayCounters,
+    /// bad samples found when the camera checks them
+    pub diagnostics: Diagnostics,
+}
+
+/// what one worker thread did over a render
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ThreadStats {
+    pub thread: usize,
+    pub tiles: usize,
+    /// time spent rendering tiles
+    pub busy: Duration,
+    pub counters: RayCounters,
+}
+
+impl ThreadStats {
+    pub fn rays_per_second(&self) -> f64 { self.counters.rays() as f64 / self.busy.as_secs_f64().max(1e-9) }
+}
+
+/// Statistics of a whole render; progressive renders append every pass's tiles.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    pub tiles: Vec<TileStats>,
+    /// wall-clock time of the render, set by the camera when it finishes
+    pub elapsed: Duration,
+}
+
+impl RenderStats {
+    pub fn add(&mut self, other: RenderStats) {
+        self.tiles.extend(other.tiles);
+        self.elapsed += other.elapsed;
+    }
+
+    pub fn total(&self) -> RayCounters {
+        let mut total = RayCounters::default();
+        for tile in &self.tiles {
+            total.add(&tile.counters);
+        }
+        total
+    }
+
+    pub fn diagnostics(&self) -> Diagnostics {
+        let mut diagnostics = Diagnostics::default();
+        for tile in &self.tiles {
+            diagnostics.merge(&tile.diagnostics);
+        }
+        diagnostics
+    }
+
+    /// per worker thread, by thread index
+    pub fn threads(&self) -> Vec<ThreadStats> {
+        let mut threads: Vec<ThreadStats> = Vec::new();
+        for tile in &self.tiles {
+            if threads.len() <= tile.thread {
+                threads.resize_with(tile.thread + 1, ThreadStats::default);
+            }
+            let thread = &mut threads[tile.thread];
+            thread.thread = tile.thread;
+            thread.tiles += 1;
+            thread.busy += tile.time;
+            thread.counters.add(&tile.counters);
+        }
+        threads.retain(|t| t.tiles > 0);
+        threads
+    }
+
+    pub fn rays_per_second(&self) -> f64 { self.total().rays() as f64 / self.elapsed.as_secs_f64().max(1e-9) }
+
+    /// table of per-thread counters, the path-length histogram and tile times
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{:>6} {:>6} {:>12} {:>12} {:>12} {:>14} {:>10} {:>9}", "thread", "tiles", "camera", "secondary", "shadow", "isect tests", "busy", "Mrays/s");
+        let row = |out: &mut String, name: &str, t: &ThreadStats| {
+            let c = &t.counters;
+            let _ = writeln!(
+                out,
+                "{:>6} {:>6} {:>12} {:>12} {:>12} {:>14} {:>9.2}s {:>9.2}",
+                name, t.tiles, c.camera_rays, c.secondary_rays, c.shadow_rays, c.intersection_tests,
+                t.busy.as_secs_f64(), t.rays_per_second() / 1e6,
+            );
+        };
+        let threads = self.threads();
+        for t in &threads {
+            row(&mut out, &t.thread.to_string(), t);
+        }
+        let total = ThreadStats {
+            thread: 0,
+            tiles: self.tiles.len(),
+            busy: threads.iter().map(|t| t.busy).sum(),
+            counters: self.total(),
+        };
+        row(&mut out, "total", &total);
+
+        let paths = &total.counters.path_lengths;
+        let count: u64 = paths.iter().sum();
+        if count > 0 {
+            let share = |n: u64| 100.0 * n as f64 / count as f64;
+            // the rare long paths go into one bucket to keep the line short
+            let shown = (paths.iter().rposition(|&n| share(n) >= 0.1).unwrap_or(0) + 1).min(PATH_LENGTH_BUCKETS - 1);
+            let _ = write!(out, "path lengths:");
+            for (bounces, &n) in paths.iter().enumerate().take(shown) {
+                let _ = write!(out, " {}: {:.1}%", bounces, share(n));
+            }
+            let rest: u64 = paths[shown..].iter().sum();
+            if rest > 0 {
+                let _ = write!(out, " {}+: {:.1}%", shown, share(rest));
+            }
+            let mean = paths.iter().enumerate().map(|(b, &n)| b as f64 * n as f64).sum::<f64>() / count as f64;
+            let _ = writeln!(out, " (mean {:.2})", mean);
+        }
+        if let Some(slowest) = self.tiles.iter().max_by_key(|t| t.time) {
+            let fastest = self.tiles.iter().map(|t| t.time).min().unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "tile time min {:?} avg {:?} max {:?} (tile {} at {},{})",
+                fastest,
+                total.busy / self.tiles.len() as u32,
+                slowest.time,
+                slowest.tile.index,
+                slowest.tile.x0,
+                slowest.tile.y0,
+            );
+        }
+        let _ = writeln!(out, "{:?} elapsed, {} rays, {:.2} Mrays/s", self.elapsed, total.counters.rays(), self.rays_per_second() / 1e6);
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let counters = |c: &RayCounters| {
+            let last = c.path_lengths.iter().rposition(|&n| n > 0).map_or(0, |k| k + 1);
+            format!(
+                "\"camera_rays\": {}, \"secondary_rays\": {}, \"shadow_rays\": {}, \"intersection_tests\": {}, \"path_lengths\": {:?}",
+                c.camera_rays, c.secondary_rays, c.shadow_rays, c.intersection_tests, &c.path_lengths[..last],
+            )
+        };
+        let mut out = String::from("{\n");
+        let total = self.total();
+        let _ = writeln!(out, "  \"elapsed_seconds\": {},", self.elapsed.as_secs_f64());
+        let _ = writeln!(out, "  \"rays\": {},", total.rays());
+        let _ = writeln!(out, "  \"rays_per_second\": {},", self.rays_per_second());
+        let _ = writeln!(out, "  \"total\": {{{}}},", counters(&total));
+        let diagnostics = self.diagnostics();
+        let first = match diagnostics.first {
+            Some(first) => format!(
+                "{{\"pixel\": [{}, {}], \"sample\": {}, \"bounce\": {}, \"value\": \"{}\", \"problem\": \"{}\", \"material_id\": {}}}",
+                first.pixel.0, first.pixel.1, first.sample,
+                first.finding.bounce.map_or("null".to_string(), |b| b.to_string()),
+                first.finding.what, first.finding.problem,
+                first.finding.material.map_or("null".to_string(), |m| m.to_string()),
+            ),
+            None => "null".to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "  \"bad_samples\": {{\"nan\": {}, \"infinite\": {}, \"negative\": {}, \"first\": {}}},",
+            diagnostics.nan, diagnostics.infinite, diagnostics.negative, first,
+        );
+        let threads: Vec<String> = self.threads().iter().map(|t| {
+            format!(
+                "    {{\"thread\": {}, \"tiles\": {}, \"busy_seconds\": {}, \"rays_per_second\": {}, {}}}",
+                t.thread, t.tiles, t.busy.as_secs_f64(), t.rays_per_second(), counters(&t.counters),
+            )
+        }).collect();
+        let _ = writeln!(out, "  \"threads\": [\n{}\n  ],", threads.join(",\n"));
+        let tiles: Vec<String> = self.tiles.iter().map(|t| {
+            format!(
+                "    {{\"index\": {}, \"x0\": {}, \"y0\": {}, \"x1\": {}, \"y1\": {}, \"thread\": {}, \"seconds\": {}, {}}}",
+                t.tile.index, t.tile.x0, t.tile.y0, t.tile.x1, t.tile.y1, t.thread, t.time.as_secs_f64(), counters(&t.counters),
+            )
+        }).collect();
+        let _ = writeln!(out, "  \"tiles\": [\n{}\n  ]", tiles.join(",\n"));
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn write_json(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threads_sum_their_tiles() {
+        let tile = |index| Tile { index, x0: 0, y0: 0, x1: 4, y1: 4 };
+        let mut counters = RayCounters::default();
+        counters.count_ray(RayKind::Camera);
+        counters.count_ray(RayKind::Diffuse);
+        counters.count_ray(RayKind::Shadow);
+        counters.count_path(1);
+        counters.count_path(100);
+        let tile_stats = |index, thread, ms| TileStats {
+            tile: tile(index),
+            thread,
+            time: Duration::from_millis(ms),
+            counters,
+            diagnostics: Diagnostics::default(),
+        };
+        let stats = RenderStats {
+            tiles: vec![tile_stats(0, 1, 3), tile_stats(1, 3, 1), tile_stats(2, 1, 2)],
+            elapsed: Duration::from_millis(4),
+        };
+        let threads = stats.threads();
+        assert_eq!(threads.iter().map(|t| (t.thread, t.tiles)).collect::<Vec<_>>(), [(1, 2), (3, 1)]);
+        assert_eq!(threads[0].busy, Duration::from_millis(5));
+        assert_eq!(stats.total().rays(), 9);
+        assert_eq!(stats.total().path_lengths[PATH_LENGTH_BUCKETS - 1], 3);
+        assert!(stats.to_json().contains("\"path_lengths\": [0, 3,"));
+    }
+}
@@ -1,8 +1,8 @@
-use std::ops::{Add, AddAssign, Sub, Mul, Div, Neg, Deref, DerefMut};
+use std::ops::{Add, AddAssign, Sub, Mul, Div, Neg};
 
-use crate::rtweekend::{random_double, random_double_range};
+use crate::rng::Rng;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -42,9 +42,9 @@ impl Vec3 {
 
     pub fn unit_vector(&self) -> Self { *self / self.length() }
 
-    pub fn random_in_unit_disk() -> Self {
+    pub fn random_in_unit_disk(rng: &mut Rng) -> Self {
         loop {
-            let mut p = Vec3::random_range(-1.0, 1.0);
+            let mut p = Vec3::random_range(rng, -1.0, 1.0);
             p.z = 0.0;
             if p.length_squared() < 1.0 {
                 return p
@@ -52,9 +52,9 @@ impl Vec3 {
         }
     }
 
-    pub fn random_unit_vector() -> Self {
+    pub fn random_unit_vector(rng: &mut Rng) -> Self {
         loop {
-            let p = Vec3::random_range(-1.0, 1.0);
+            let p = Vec3::random_range(rng, -1.0, 1.0);
             let length_squared = p.length_squared();
             if length_squared <= 1.0 && length_squared > 1e-160 {
                 return p / length_squared.sqrt();
@@ -62,16 +62,35 @@ impl Vec3 {
         }
     }
 
-    pub fn random_on_hemisphere(normal: &Vec3) -> Vec3{
-        let on_unit_sphere = Vec3::random_unit_vector();
-        if on_unit_sphere.dot(&normal) > 0.0 {
-            return on_unit_sphere;
+    pub fn random_on_hemisphere(rng: &mut Rng, normal: &Vec3) -> Vec3{
+        let on_unit_sphere = Vec3::random_unit_vector(rng);
+        if on_unit_sphere.dot(normal) > 0.0 {
+            on_unit_sphere
         }
         else {
-            return -on_unit_sphere;
+            -on_unit_sphere
         }
     }
 
+    /// map a uniform sample in [0,1)^2 onto the unit sphere (area preserving)
+    pub fn unit_vector_from_sample(u: f64, v: f64) -> Self {
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * v;
+        Self::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    /// concentric (Shirley-Chiu) map of a uniform sample in [0,1)^2 onto the unit disk
+    pub fn in_unit_disk_from_sample(u: f64, v: f64) -> Self {
+        let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Self::zero();
+        }
+        let quarter = std::f64::consts::FRAC_PI_4;
+        let (r, theta) = if a.abs() > b.abs() { (a, quarter * (b / a)) } else { (b, 2.0 * quarter - quarter * (a / b)) };
+        Self::new(r * theta.cos(), r * theta.sin(), 0.0)
+    }
+
     pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
         *v - 2.0 * v.dot(n) * *n
     }
@@ -91,15 +110,15 @@ impl Vec3 {
         )
     }
 
-    pub fn random() -> Self {
-        Self::new(random_double(), random_double(), random_double())
+    pub fn random(rng: &mut Rng) -> Self {
+        Self::new(rng.random_double(), rng.random_double(), rng.random_double())
     }
 
-    pub fn random_range(min: f64, max: f64) -> Self {
+    pub fn random_range(rng: &mut Rng, min: f64, max: f64) -> Self {
         Self::new(
-            random_double_range(min, max),
-            random_double_range(min, max),
-            random_double_range(min, max),
+            rng.random_double_range(min, max),
+            rng.random_double_range(min, max),
+            rng.random_double_range(min, max),
         )
     }
 }